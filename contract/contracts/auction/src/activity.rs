@@ -0,0 +1,33 @@
+use cosmwasm_std::{Addr, StdResult, Storage, Uint128};
+use marketplace::auction::{Activity, ActivityKind};
+
+use crate::state::{Auction, ACTIVITIES, NEXT_ACTIVITY_SEQ};
+
+// activities read per query, filtered out ones included
+pub const MAX_ACTIVITY_SCAN: usize = 300;
+
+// append to the activity feed of address
+pub fn record_activity(
+    storage: &mut dyn Storage,
+    address: &Addr,
+    kind: ActivityKind,
+    auction: &Auction,
+    amount: Uint128,
+    counterparty: Option<Addr>,
+    time: u64
+) -> StdResult<()> {
+    let seq = NEXT_ACTIVITY_SEQ.may_load(storage, address)?.unwrap_or(0);
+    let activity = Activity {
+        seq: seq,
+        kind: kind,
+        auction_id: auction.auction_id,
+        nft_contract: auction.nft_contract.clone(),
+        token_id: auction.token_id.clone(),
+        denom: auction.denom.clone(),
+        amount: amount,
+        counterparty: counterparty,
+        time: time
+    };
+    ACTIVITIES.save(storage, (address, seq), &activity)?;
+    NEXT_ACTIVITY_SEQ.save(storage, address, &(seq + 1))
+}
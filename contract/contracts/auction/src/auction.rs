@@ -1,24 +1,61 @@
-use cosmwasm_std::{CanonicalAddr, Uint128, Response, DepsMut, Deps, Env, Querier, Addr,
-    StdResult, Storage,  StdError, Decimal, CosmosMsg, WasmMsg, to_binary, QueryRequest, WasmQuery, MessageInfo};
+use cosmwasm_std::{CanonicalAddr, Uint128, Response, DepsMut, Deps, Env, Querier, Addr, Api,
+    StdResult, Storage,  StdError, Order, Event, Decimal, CosmosMsg, WasmMsg, to_binary, QueryRequest, WasmQuery, MessageInfo, Coin, Binary,
+    SubMsg, Reply, SubMsgResult};
 use terraswap::asset::{Asset, AssetInfo};
-use marketplace::auction::{AuctionType, Bid ,Royalty, ExecuteMsg};
+use marketplace::auction::{AuctionType, Bid ,Royalty, ExecuteMsg, ConfigChange, PendingConfigChange, Sale, ActivityKind, KeeperReward,
+    BatchListItem, PriceUpdate, SweepTarget, RoyaltyShare, RoyaltySplit};
+use marketplace::royalty::{query_nft_controller, validate_royalty_splits, ROYALTY_RATE_COOLDOWN};
 use cw721::{Cw721ExecuteMsg};
+use cw_storage_plus::Bound;
 
 use crate::error::ContractError;
-use crate::state::{CONFIG, STATE, ROYALTIES, AUCTIONS, BID_HISTORY_BY_AUCTION_ID, AUCTION_ID_BY_SELLER,
-    BID_COUNT_BY_AUCTION_ID ,ROYALTY_ADMINS, Auction, NFT_AUCTION_MAPS, AUCTION_ID_BY_ENDTIME, 
-    AUCTION_ID_BY_AMOUNT, NOT_STARTED_AUCTION, AUCTION_ID_BY_BIDDER};
-use crate::querier::{query_nft_owner};
+use crate::state::{CONFIG, Config, STATE, ROYALTIES, auctions, bid_history,
+    BID_COUNT_BY_AUCTION_ID ,ROYALTY_ADMINS, Auction, AUCTION_PHASE_OPEN, FLOOR_LISTED, PENDING_CONFIG_CHANGE, ORACLE_CONFIG, OracleConfig,
+    KEEPER_REWARD, KeeperRewardConfig, MAX_BATCH_SIZE, NEXT_SALE_ID, SALES, SALE_ID_BY_TOKEN, SALE_ID_BY_COLLECTION, SALE_ID_BY_SELLER, SALE_ID_BY_BUYER, FEE_DISTRIBUTION,
+    ROYALTY_RATE_CHANGED_AT, ROYALTY_REGISTRY, PRIMARY_PROTOCOL_FEE, FAILED_SETTLEMENTS, FailedSettlement,
+    is_listing_expired, TICK_CURSOR};
+use crate::querier::{query_nft_owner, query_listing_valid, query_nft_approved, load_royalty, query_primary_sale, query_is_contract};
+use crate::stats::{on_listing_created, on_listing_closed, on_sale};
+use crate::activity::record_activity;
+use crate::referral::{validate_referrer, pay_referrers};
+use crate::fee::{collect_protocol_fee, validate_fee_distribution, flush_accrued_fees};
+use crate::oracle::{record_price_observation, MAX_OBSERVATIONS_LIMIT};
+
+// items of one batch message
+pub const DEFAULT_MAX_BATCH_SIZE: u64 = 30;
+pub const MAX_BATCH_SIZE_LIMIT: u64 = 100;
+// auctions settled or closed by one Tick, and open auctions visited including the ones left alone
+pub const MAX_TICK_SETTLE: usize = 10;
+pub const MAX_TICK_SCAN: usize = 30;
+// tick retries a failed auction after this delay, doubled for each further failure up to MAX_TICK_RETRY_DOUBLINGS times
+pub const TICK_RETRY_DELAY: u64 = 600;
+pub const MAX_TICK_RETRY_DOUBLINGS: u32 = 6;
+// floor entries visited by a cheapest sweep to find max count buyable listings
+pub const MAX_SWEEP_SCAN: usize = 100;
+// reply id of a sub message is the auction id shifted by the kind bits
+pub const REPLY_KIND_BITS: u64 = 2;
+pub const REPLY_SETTLE: u64 = 0;
+pub const REPLY_CLOSE: u64 = 1;
+pub const REPLY_SEND_NFT: u64 = 2;
+
+pub fn reply_id(
+    auction_id: Uint128,
+    kind: u64
+) -> u64 {
+    ((auction_id.u128() as u64) << REPLY_KIND_BITS) | kind
+}
 
 pub fn create_auction(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     nft_contract: Addr,
     token_id: String,
     seller: Addr,
     denom: String,
     reserve_price: Uint128,
-    is_instant_sale: bool
+    is_instant_sale: bool,
+    is_approval_listing: bool,
+    referrer: Option<Addr>
 ) -> Result<Response, ContractError> {
     // check condition
     let config = CONFIG.load(deps.storage)?;
@@ -36,16 +73,21 @@ pub fn create_auction(
         return Err(ContractError::InvalidAmount("reserve price too low".to_string()));
     }
     // check additional nft auction mapping
-    match NFT_AUCTION_MAPS.may_load(deps.storage, (&nft_contract, token_id.clone()))? {
-        Some(_) => return Err(ContractError::InvalidAuction("auction is duplicated".to_string())),
-        None => {}
-    };
+    let open_auctions = auctions().idx.nft_token
+        .prefix((nft_contract.clone(), token_id.clone(), AUCTION_PHASE_OPEN))
+        .keys(deps.storage, None, None, Order::Ascending)
+        .count();
+    if open_auctions > 0 {
+        return Err(ContractError::InvalidAuction("auction is duplicated".to_string()));
+    }
     // check support royalty
     let mut creator_address: Option<Addr> = None;
-    let royalty_fee_response = ROYALTIES.may_load(deps.storage, &nft_contract)?;
+    let mut royalty_splits: Vec<RoyaltyShare> = vec![];
+    let royalty_fee_response = load_royalty(deps.as_ref(), &nft_contract, &token_id)?;
     let royalty_fee:Decimal = match royalty_fee_response {
         Some(v) => {
             creator_address = Some(v.creator);
+            royalty_splits = v.splits;
             v.royalty_fee
         }
         None => Decimal::zero()
@@ -77,18 +119,23 @@ pub fn create_auction(
         amount: reserve_price,
         creator_address: creator_address,
         royalty_fee: royalty_fee,
+        royalty_splits: royalty_splits,
         protocol_fee: config.protocol_fee.clone(),
-        is_settled: false
+        is_settled: false,
+        is_approval_listing: is_approval_listing,
+        expires_at: None,
+        recipient: None,
+        refund_to: None,
+        send_msg: None,
+        listing_referrer: referrer,
+        bid_referrer: None
     };
     // save auction
-    AUCTIONS.save(deps.storage, auction_id.clone().u128(), &auction)?;
+    auctions().save(deps.storage, auction_id.clone().u128(), &auction)?;
     state.next_auction_id += Uint128::from(1u128);
     STATE.save(deps.storage, &state)?;
-    // save auction map
-    NFT_AUCTION_MAPS.save(deps.storage, (&nft_contract, token_id.clone()), &auction_id.u128())?;
-    //updating auction indices
-    AUCTION_ID_BY_SELLER.save(deps.storage, (&seller, auction_id.u128()), &true)?;
-    NOT_STARTED_AUCTION.save(deps.storage, (&nft_contract, auction.auction_id.u128()), &true)?;
+    on_listing_created(deps.storage, &auction)?;
+    record_activity(deps.storage, &seller, ActivityKind::ListingCreated, &auction, reserve_price, None, env.block.time.seconds())?;
     Ok(Response::new()        
         .add_attribute("action", "create_auction")
         .add_attribute("auction_id", auction_id)
@@ -98,9 +145,82 @@ pub fn create_auction(
         .add_attribute("seller", seller.to_string())
         .add_attribute("denom", denom)
         .add_attribute("reserve", reserve_price)
+        .add_attribute("is_approval_listing", is_approval_listing.to_string())
     )
 }
 
+pub fn create_approval_listing(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    nft_contract: String,
+    token_id: String,
+    denom: String,
+    reserve_price: Uint128,
+    is_instant_sale: bool,
+    referrer: Option<String>
+) -> Result<Response, ContractError> {
+    let nft_contract = deps.api.addr_validate(&nft_contract)?;
+    let referrer = validate_referrer(deps.as_ref(), referrer, &info.sender)?;
+    // seller must own the nft and approve this contract before listing
+    let nft_owner = query_nft_owner(deps.as_ref(), nft_contract.to_string(), token_id.clone())?;
+    if nft_owner != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    if !query_nft_approved(deps.as_ref(), &env, &nft_contract, &token_id, &info.sender)? {
+        return Err(ContractError::InvalidAsset("nft is not approved by its owner".to_string()));
+    }
+    create_auction(deps, env, nft_contract, token_id, info.sender, denom, reserve_price, is_instant_sale, true, referrer)
+}
+
+pub fn invalidate_listing(
+    deps: DepsMut,
+    env: Env,
+    auction_id: Uint128
+) -> Result<Response, ContractError> {
+    let auction = auctions().load(deps.storage, auction_id.u128())?;
+    if query_listing_valid(deps.as_ref(), &env, &auction)? {
+        return Err(ContractError::InvalidAuction("listing is still valid".to_string()));
+    }
+    close_listing(deps, env, auction, "invalidate_listing")
+}
+
+pub fn reclaim_expired(
+    deps: DepsMut,
+    env: Env,
+    auction_id: Uint128
+) -> Result<Response, ContractError> {
+    let auction = auctions().load(deps.storage, auction_id.u128())?;
+    if !is_listing_expired(&auction, env.block.time.seconds()) {
+        return Err(ContractError::InvalidAuction("listing is not expired".to_string()));
+    }
+    close_listing(deps, env, auction, "reclaim_expired")
+}
+
+// escrowed nft goes back to the seller, closing is not rewarded since the seller sets the price and expiry
+fn close_listing(
+    deps: DepsMut,
+    env: Env,
+    auction: Auction,
+    action: &str
+) -> Result<Response, ContractError> {
+    let mut messages = _cancel_auction(deps, env, auction.auction_id)?;
+    // refund the bid, the nft can't be delivered
+    if let Some(bidder) = auction.refund_to.clone().or(auction.bidder.clone()) {
+        let refund_asset = Asset {
+            info: AssetInfo::NativeToken {
+                denom: auction.denom.clone()
+            },
+            amount: auction.amount
+        };
+        messages.push(refund_asset.into_msg(bidder)?);
+    }
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attribute("action", action)
+        .add_attribute("auction_id", auction.auction_id)
+    )
+}
 
 pub fn set_royalty_fee(
     deps: DepsMut,
@@ -109,13 +229,37 @@ pub fn set_royalty_fee(
     contract_addr: String,
     creator: String,
     royalty_fee: Decimal,
+    splits: Option<Vec<RoyaltySplit>>
 ) -> Result<Response, ContractError> {
-    only_royalty_admin(deps.as_ref(), &env, info)?;
     let nft_contract_addr = deps.api.addr_validate(&contract_addr)?;
     let creator_addr = deps.api.addr_validate(&creator)?;
+    let block_time = env.block.time.seconds();
+    let current = ROYALTIES.may_load(deps.storage, &nft_contract_addr)?;
+    let is_rate_changed = current.as_ref().map(|x| x.royalty_fee != royalty_fee).unwrap_or(true);
+    let is_royalty_admin = only_royalty_admin(deps.as_ref(), &env, info.clone()).is_ok();
+    // minter or admin of the nft contract sets the royalty of its own collection
+    if !is_royalty_admin && !query_nft_controller(&deps.querier, &nft_contract_addr, &info.sender)? {
+        return Err(ContractError::Unauthorized {});
+    }
+    // every setter, royalty admins included
+    let config = CONFIG.load(deps.storage)?;
+    if royalty_fee > config.max_royalty_fee {
+        return Err(ContractError::InvalidConfigChange("royalty fee is more than max royalty fee".to_string()));
+    }
+    if !is_royalty_admin {
+        if let Some(changed_at) = ROYALTY_RATE_CHANGED_AT.may_load(deps.storage, &nft_contract_addr)? {
+            if current.is_some() && is_rate_changed && block_time < changed_at + ROYALTY_RATE_COOLDOWN {
+                return Err(ContractError::InvalidConfigChange("royalty fee was changed recently".to_string()));
+            }
+        }
+    }
+    if is_rate_changed {
+        ROYALTY_RATE_CHANGED_AT.save(deps.storage, &nft_contract_addr, &block_time)?;
+    }
     let royalty = Royalty {
         royalty_fee: royalty_fee,
         creator: creator_addr,
+        splits: validate_royalty_splits(deps.api, royalty_fee, splits).map_err(ContractError::InvalidConfigChange)?
     };
     ROYALTIES.save(deps.storage, &nft_contract_addr, &royalty)?;
     Ok(Response::new()
@@ -147,6 +291,109 @@ pub fn set_royalty_admin(
         .add_attribute("enable", enable.to_string()))
 }
 
+pub fn set_oracle_config(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    max_observations: u64,
+    outlier_threshold: Option<Decimal>
+) -> Result<Response, ContractError> {
+    only_owner(deps.as_ref(), &env, info)?;
+    if max_observations == 0 || max_observations > MAX_OBSERVATIONS_LIMIT {
+        return Err(ContractError::InvalidConfigChange("invalid max observations".to_string()));
+    }
+    ORACLE_CONFIG.save(deps.storage, &OracleConfig {
+        max_observations: max_observations,
+        outlier_threshold: outlier_threshold
+    })?;
+
+    Ok(Response::new()
+        .add_attribute("action", "set_oracle_config")
+        .add_attribute("max_observations", max_observations.to_string())
+        .add_attribute("outlier_threshold", outlier_threshold.map(|v| v.to_string()).unwrap_or_default()))
+}
+
+pub fn set_max_batch_size(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    max_batch_size: u64
+) -> Result<Response, ContractError> {
+    only_owner(deps.as_ref(), &env, info)?;
+    if max_batch_size == 0 || max_batch_size > MAX_BATCH_SIZE_LIMIT {
+        return Err(ContractError::InvalidConfigChange("invalid max batch size".to_string()));
+    }
+    MAX_BATCH_SIZE.save(deps.storage, &max_batch_size)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "set_max_batch_size")
+        .add_attribute("max_batch_size", max_batch_size.to_string()))
+}
+
+pub fn set_royalty_registry(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    address: Option<String>
+) -> Result<Response, ContractError> {
+    only_owner(deps.as_ref(), &env, info)?;
+    match &address {
+        Some(address) => ROYALTY_REGISTRY.save(deps.storage, &deps.api.addr_validate(address)?)?,
+        None => ROYALTY_REGISTRY.remove(deps.storage)
+    };
+
+    Ok(Response::new()
+        .add_attribute("action", "set_royalty_registry")
+        .add_attribute("address", address.unwrap_or_default()))
+}
+
+pub fn load_max_batch_size(
+    storage: &dyn Storage
+) -> StdResult<u64> {
+    Ok(MAX_BATCH_SIZE.may_load(storage)?.unwrap_or(DEFAULT_MAX_BATCH_SIZE))
+}
+
+fn check_batch_size(
+    storage: &dyn Storage,
+    size: usize
+) -> Result<(), ContractError> {
+    if size == 0 {
+        return Err(ContractError::InvalidAuction("batch is empty".to_string()));
+    }
+    if size as u64 > load_max_batch_size(storage)? {
+        return Err(ContractError::InvalidAuction("batch is too large".to_string()));
+    }
+    Ok(())
+}
+
+pub fn set_keeper_reward(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    reward: Option<KeeperReward>,
+    min_delay: u64
+) -> Result<Response, ContractError> {
+    only_owner(deps.as_ref(), &env, info)?;
+    match reward {
+        Some(reward) => {
+            if let KeeperReward::ProtocolFeeShare { share } = &reward {
+                if *share > Decimal::one() {
+                    return Err(ContractError::InvalidConfigChange("keeper share is more than protocol fee".to_string()));
+                }
+            }
+            KEEPER_REWARD.save(deps.storage, &KeeperRewardConfig {
+                reward: reward,
+                min_delay: min_delay
+            })?;
+        },
+        None => KEEPER_REWARD.remove(deps.storage)
+    };
+
+    Ok(Response::new()
+        .add_attribute("action", "set_keeper_reward")
+        .add_attribute("min_delay", min_delay.to_string()))
+}
+
 pub fn cancel_auction(
     deps: DepsMut,
     env: Env,
@@ -157,31 +404,35 @@ pub fn cancel_auction(
     check_auction_owner(deps.as_ref(), &env, info, auction_id)?;
     // cancel auction
     let mut messages: Vec<CosmosMsg> = vec![];
-    let auction = AUCTIONS.load(deps.storage, auction_id.u128())?;
+    let auction = auctions().load(deps.storage, auction_id.u128())?;
     match auction.auction_type {
         AuctionType::BuyNow => {
             messages.extend(_cancel_auction(deps, env.clone(), auction_id)?);
-            messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: env.contract.address.to_string(),
-                msg: to_binary(&ExecuteMsg::SettleHook {
-                    nft_contract: auction.nft_contract.to_string(),
-                    token_id: auction.token_id.clone(),
-                    owner: auction.seller.to_string()
-                })?,
-                funds: vec![]
-            }));
+            if !auction.is_approval_listing {
+                messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: env.contract.address.to_string(),
+                    msg: to_binary(&ExecuteMsg::SettleHook {
+                        nft_contract: auction.nft_contract.to_string(),
+                        token_id: auction.token_id.clone(),
+                        owner: auction.seller.to_string()
+                    })?,
+                    funds: vec![]
+                }));
+            }
         },
         AuctionType::Auction => {
             messages.extend(_cancel_auction(deps, env.clone(), auction_id)?);
-            messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: env.contract.address.to_string(),
-                msg: to_binary(&ExecuteMsg::SettleHook {
-                    nft_contract: auction.nft_contract.to_string(),
-                    token_id: auction.token_id.clone(),
-                    owner: auction.seller.to_string()
-                })?,
-                funds: vec![]
-            }));
+            if !auction.is_approval_listing {
+                messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: env.contract.address.to_string(),
+                    msg: to_binary(&ExecuteMsg::SettleHook {
+                        nft_contract: auction.nft_contract.to_string(),
+                        token_id: auction.token_id.clone(),
+                        owner: auction.seller.to_string()
+                    })?,
+                    funds: vec![]
+                }));
+            }
         }
     }
     Ok(Response::new()
@@ -195,11 +446,22 @@ pub fn place_bid(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
-    auction_id: Uint128
+    auction_id: Uint128,
+    recipient: Option<String>,
+    refund_to: Option<String>,
+    send_msg: Option<Binary>,
+    referrer: Option<String>
 ) -> Result<Response, ContractError> {
     // retrieve config
     let config = CONFIG.load(deps.storage)?;
     let state = STATE.load(deps.storage)?;
+    let recipient = recipient.map(|x| deps.api.addr_validate(&x)).transpose()?;
+    let refund_to = refund_to.map(|x| deps.api.addr_validate(&x)).transpose()?;
+    let referrer = validate_referrer(deps.as_ref(), referrer, &info.sender)?;
+    // only a contract can take the SendNft hook
+    if send_msg.is_some() && !query_is_contract(deps.as_ref(), recipient.as_ref().unwrap_or(&info.sender)) {
+        return Err(ContractError::InvalidAsset("send_msg recipient is not a contract".to_string()));
+    }
 
     if state.is_freeze {
         return Err(ContractError::AuctionFreeze {});
@@ -208,10 +470,13 @@ pub fn place_bid(
         return Err(ContractError::InvalidAmount("sent fund in multiple denom".to_string()));
     }
     // retrieve auction
-    let mut auction = AUCTIONS.load(deps.storage, auction_id.u128())?;
+    let mut auction = auctions().load(deps.storage, auction_id.u128())?;
     if auction.is_settled {
         return Err(ContractError::InvalidAuction("already settled".to_string()));
     }
+    if !query_listing_valid(deps.as_ref(), &env, &auction)? {
+        return Err(ContractError::InvalidAuction("listing is no longer valid".to_string()));
+    }
     // call place bid
     let bid_amount: Uint128 = info.funds
         .iter()
@@ -224,12 +489,16 @@ pub fn place_bid(
 
     let bidder;
 
-    let bid_history: Bid  = Bid{
+    let new_bid: Bid  = Bid{
         auction_id: auction.auction_id,
         bidder: info.sender.clone(),
         time: env.block.time.seconds(),
         denom: auction.denom.clone(),
-        amount: bid_amount
+        amount: bid_amount,
+        recipient: recipient.clone(),
+        refund_to: refund_to.clone(),
+        send_msg: send_msg.clone(),
+        referrer: referrer.clone()
     };
 
     match auction.auction_type {
@@ -241,7 +510,14 @@ pub fn place_bid(
                 return Err(ContractError::InvalidAmount("bid amount is less than reserve price".to_string()));
             }
             auction.bidder = Some(info.sender.clone());
+            auction.recipient = recipient;
+            auction.refund_to = refund_to;
+            auction.send_msg = send_msg;
+            auction.bid_referrer = referrer;
             auction.end_time = env.block.time.seconds();
+            // a buy now listing takes one bid
+            BID_COUNT_BY_AUCTION_ID.save(deps.storage, auction.auction_id.u128(), &Uint128::from(1u128))?;
+            bid_history().save(deps.storage, (auction.auction_id.u128(), 1), &new_bid)?;
         },
         AuctionType::Auction => {
             // check end time
@@ -256,16 +532,14 @@ pub fn place_bid(
                 auction.amount = bid_amount;
                 bidder = info.sender.clone();
                 auction.bidder = Some(bidder.clone());
+                auction.recipient = recipient;
+                auction.refund_to = refund_to;
+                auction.send_msg = send_msg;
+                auction.bid_referrer = referrer;
                 auction.end_time = end_time;
                 //update bid information
                 BID_COUNT_BY_AUCTION_ID.save(deps.storage, auction.auction_id.u128(), &Uint128::from(1u128))?;
-                BID_HISTORY_BY_AUCTION_ID.save(deps.storage, (auction.auction_id.u128(), 1), &bid_history)?;
-                //updating indexing storage
-                AUCTION_ID_BY_ENDTIME.save(deps.storage, (&auction.nft_contract, auction.end_time, auction.auction_id.u128()), &true )?;
-
-                AUCTION_ID_BY_AMOUNT.save(deps.storage, (&auction.nft_contract, auction.amount.u128(), auction.auction_id.u128()), &true)?;
-                AUCTION_ID_BY_BIDDER.save(deps.storage, (&bidder, auction.auction_id.u128()), &true)?;
-                // add bidder
+                bid_history().save(deps.storage, (auction.auction_id.u128(), 1), &new_bid)?;
             } else {
                 // precondition
                 bidder = info.sender.clone();
@@ -289,8 +563,12 @@ pub fn place_bid(
                 }
                 // action
                 let last_amount = auction.amount;
-                let last_endtime = auction.end_time;
+                let last_refund_to = auction.refund_to.clone().unwrap_or(last_bidder.clone());
                 auction.bidder = Some(bidder.clone());
+                auction.recipient = recipient;
+                auction.refund_to = refund_to;
+                auction.send_msg = send_msg;
+                auction.bid_referrer = referrer;
                 auction.amount = bid_amount;
                 
                 // extension period
@@ -301,17 +579,8 @@ pub fn place_bid(
 
                 // add to bid history
                 let bid_count = BID_COUNT_BY_AUCTION_ID.load(deps.storage ,auction.auction_id.u128())? + Uint128::from(1u128);
-                BID_COUNT_BY_AUCTION_ID.save(deps.storage, auction.auction_id.u128(), &Uint128::from(1u128))?;
-                BID_HISTORY_BY_AUCTION_ID.save(deps.storage, (auction.auction_id.u128(), bid_count.u128()), &bid_history)?;
-                //remove old endtime and add new
-                AUCTION_ID_BY_ENDTIME.remove(deps.storage, (&auction.nft_contract, last_endtime, auction.auction_id.u128()));
-                AUCTION_ID_BY_ENDTIME.save(deps.storage, (&auction.nft_contract, auction.end_time, auction.auction_id.u128()), &true)?;
-
-                AUCTION_ID_BY_AMOUNT.remove(deps.storage, (&auction.nft_contract, last_amount.u128(), auction.auction_id.u128()));
-                AUCTION_ID_BY_AMOUNT.save(deps.storage, (&auction.nft_contract, auction.amount.u128(), auction.auction_id.u128()), &true)?;
-
-                AUCTION_ID_BY_BIDDER.remove(deps.storage, (&last_bidder, auction.auction_id.u128()));
-                AUCTION_ID_BY_BIDDER.save(deps.storage, (&bidder.clone(), auction.auction_id.u128()), &true)?;
+                BID_COUNT_BY_AUCTION_ID.save(deps.storage, auction.auction_id.u128(), &bid_count)?;
+                bid_history().save(deps.storage, (auction.auction_id.u128(), bid_count.u128()), &new_bid)?;
 
                 let refund_asset:Asset = Asset {
                     info: AssetInfo::NativeToken {
@@ -319,14 +588,15 @@ pub fn place_bid(
                     },
                     amount: last_amount
                 };
-                messages.push(refund_asset.into_msg(last_bidder)?);
+                record_activity(deps.storage, &last_bidder, ActivityKind::Outbid, &auction, last_amount, Some(bidder.clone()), block_time)?;
+                messages.push(refund_asset.into_msg(last_refund_to)?);
             }
         }
     }
     
-    // update auction
-    NOT_STARTED_AUCTION.remove(deps.storage , (&auction.nft_contract, auction.auction_id.u128()));
-    AUCTIONS.save(deps.storage, auction_id.u128(), &auction)?;
+    // update auction, indices follow the saved auction
+    auctions().save(deps.storage, auction_id.u128(), &auction)?;
+    record_activity(deps.storage, &info.sender, ActivityKind::BidPlaced, &auction, bid_amount, Some(auction.seller.clone()), block_time)?;
     
     // send fund back
     Ok(Response::new()
@@ -343,49 +613,146 @@ pub fn place_bid(
 pub fn settle_auction(
     deps: DepsMut,
     env: Env,
+    info: MessageInfo,
     auction_id: Uint128
 ) -> Result<Response, ContractError> {
-    // retrieve config
-    let config = CONFIG.load(deps.storage)?;
-    // retrieve auction
-    let mut auction = AUCTIONS.load(deps.storage, auction_id.u128())?;
+    _settle_auction(deps, env, Some(info.sender), auction_id)
+}
+
+pub fn settle_batch_item(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    auction_id: Uint128,
+    keeper: Option<String>
+) -> Result<Response, ContractError> {
+    if info.sender != env.contract.address {
+        return Err(ContractError::Unauthorized {});
+    }
+    let keeper = keeper.map(|x| deps.api.addr_validate(&x)).transpose()?;
+    _settle_auction(deps, env, keeper, auction_id)
+}
+
+// ended auction with a bid that is not settled yet
+fn load_settleable(
+    storage: &dyn Storage,
+    env: &Env,
+    auction_id: Uint128
+) -> Result<Auction, ContractError> {
+    let auction = auctions().may_load(storage, auction_id.u128())?
+        .ok_or_else(|| ContractError::InvalidAuction("auction not found".to_string()))?;
     if auction.is_settled {
         return Err(ContractError::InvalidAuction("already settled".to_string()));
-    };
+    }
+    if auction.bidder.is_none() {
+        return Err(ContractError::InvalidAuction("auction has no bid".to_string()));
+    }
     if env.block.time.seconds() < auction.end_time {
         return Err(ContractError::InvalidAuction("auction is not end".to_string()));
+    }
+    Ok(auction)
+}
+
+// keeper is none when settled by the chain, no reward is paid then
+fn _settle_auction(
+    deps: DepsMut,
+    env: Env,
+    keeper: Option<Addr>,
+    auction_id: Uint128
+) -> Result<Response, ContractError> {
+    let mut auction = load_settleable(deps.storage, &env, auction_id)?;
+    // transfer by this contract as approved operator moves the nft from seller to bidder
+    if !query_listing_valid(deps.as_ref(), &env, &auction)? {
+        return Err(ContractError::InvalidAuction("listing is no longer valid".to_string()));
+    }
+    let (messages, sale, keeper_reward) = finalize_sale(deps, &env, keeper, &mut auction)?;
+
+    Ok(Response::new()
+        .add_submessages(messages)
+        .add_attribute("action", "settle")
+        .add_attribute("auction_id", auction_id)
+        .add_attribute("nft_contract", auction.nft_contract)
+        .add_attribute("token_id", auction.token_id)
+        .add_attribute("denom", auction.denom)
+        .add_attribute("amount", auction.amount)
+        .add_attribute("seller", auction.seller)
+        .add_attribute("sale_id", sale.sale_id.to_string())
+        .add_attribute("keeper_reward", keeper_reward)
+    )
+}
+
+// pay out a sold auction and send the nft to its bidder, the protocol fee and royalty split is shared by every kind of sale
+pub fn finalize_sale(
+    deps: DepsMut,
+    env: &Env,
+    keeper: Option<Addr>,
+    auction: &mut Auction
+) -> Result<(Vec<SubMsg>, Sale, Uint128), ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    // primary sales pay their own protocol fee and no royalty
+    let primary_protocol_fee = match PRIMARY_PROTOCOL_FEE.may_load(deps.storage)? {
+        Some(fee) if query_primary_sale(deps.as_ref(), auction)? => Some(fee),
+        _ => None
     };
+    let is_primary_sale = primary_protocol_fee.is_some();
     // distribute fund
     let mut messages: Vec<CosmosMsg> = vec![];
-    let protocol_fee = calculate_fee(config.protocol_fee, auction.amount)?;
-    let royalty_fee = calculate_fee(auction.royalty_fee, auction.amount)?;
-    let seller_amount = auction.amount - (protocol_fee + royalty_fee);
+    let protocol_fee = calculate_fee(primary_protocol_fee.unwrap_or(config.protocol_fee), auction.amount)?;
+    let royalty_fee = if is_primary_sale {
+        Uint128::zero()
+    } else {
+        calculate_fee(auction.royalty_fee, auction.amount)?
+    };
+    // fees above the sale price must not underflow the seller amount
+    let seller_amount = auction.amount.checked_sub(protocol_fee + royalty_fee)
+        .map_err(|_| ContractError::InvalidAmount("fees are more than sale price".to_string()))?;
+    let keeper_reward = match &keeper {
+        Some(_) => calculate_keeper_reward(deps.storage, &auction.denom, protocol_fee, auction.end_time, env.block.time.seconds())?,
+        None => Uint128::zero()
+    };
+    // referral rewards are taken from what is left of protocol fee after the keeper reward
+    let referral_rewards = pay_referrers(deps.storage, auction, protocol_fee, protocol_fee - keeper_reward)?;
+    let referral_total = referral_rewards.iter().fold(Uint128::zero(), |acc, (_, x)| acc + *x);
     // protocol fee
-    if protocol_fee > Uint128::zero() {
-        let protocol_asset = Asset {
+    messages.extend(collect_protocol_fee(deps.storage, deps.api, &config, &auction.denom, protocol_fee - keeper_reward - referral_total)?);
+    // keeper reward is taken from protocol fee
+    if let Some(keeper) = keeper.filter(|_| keeper_reward > Uint128::zero()) {
+        let keeper_asset = Asset {
             info: AssetInfo::NativeToken {
                 denom: auction.denom.clone()
             },
-            amount: protocol_fee
+            amount: keeper_reward
         };
-        messages.push(protocol_asset.into_msg(config.collector_address.clone())?);
+        messages.push(keeper_asset.into_msg(keeper)?);
     }
-    // royalty
-    if royalty_fee > Uint128::zero() {
-        let royalty_asset = Asset {
+    for (referrer, reward) in referral_rewards {
+        let referral_asset = Asset {
             info: AssetInfo::NativeToken {
                 denom: auction.denom.clone()
             },
-            amount: royalty_fee
+            amount: reward
         };
-        match auction.creator_address.clone() {
-            Some(v) => {
-                messages.push(royalty_asset.into_msg(v)?);
-            }
+        messages.push(referral_asset.into_msg(referrer)?);
+    }
+    // royalty
+    let mut royalty_paid: Vec<(Addr, Uint128)> = vec![];
+    if royalty_fee > Uint128::zero() {
+        let creator = match &auction.creator_address {
+            Some(v) => v,
             None => {
                 return Err(ContractError::InvalidAuction("creator address is not set".to_string())); 
             }
         };
+        royalty_paid = royalty_payouts(creator, &auction.royalty_splits, auction.amount, royalty_fee);
+        for (recipient, amount) in royalty_paid.iter() {
+            let royalty_asset = Asset {
+                info: AssetInfo::NativeToken {
+                    denom: auction.denom.clone()
+                },
+                amount: *amount
+            };
+            messages.push(royalty_asset.into_msg(recipient.clone())?);
+        }
     }
     // seller 
     let seller_asset = Asset {
@@ -395,55 +762,587 @@ pub fn settle_auction(
         amount: seller_amount
     };
     messages.push(seller_asset.into_msg(auction.seller.clone())?);
-    // send nft to bidder
+    // send nft to the recipient of the bid, the bidder if none
     let bidder = match &auction.bidder {
         Some(v) => v.clone(),
         None => return Err(ContractError::InvalidAuction("invalid bidder".to_string()))
     };
-    messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
-        contract_addr: auction.nft_contract.to_string(),
-        msg: to_binary(&Cw721ExecuteMsg::TransferNft {
-            token_id: auction.token_id.clone(),
-            recipient: bidder.to_string()
-        })?,
-        funds: vec![]
-    }));
+    let recipient = auction.recipient.clone().unwrap_or(bidder.clone());
+    // payouts never reply, only the nft delivery may
+    let mut messages: Vec<SubMsg> = messages.into_iter().map(SubMsg::new).collect();
+    // contract buyers are notified of the nft through the hook payload of the bid, a rejected hook falls back to a transfer on reply
+    match &auction.send_msg {
+        Some(msg) => messages.push(SubMsg::reply_on_error(WasmMsg::Execute {
+            contract_addr: auction.nft_contract.to_string(),
+            msg: to_binary(&Cw721ExecuteMsg::SendNft {
+                contract: recipient.to_string(),
+                token_id: auction.token_id.clone(),
+                msg: msg.clone()
+            })?,
+            funds: vec![]
+        }, reply_id(auction.auction_id, REPLY_SEND_NFT))),
+        None => messages.push(SubMsg::new(transfer_nft_msg(auction, &recipient)?))
+    };
     // need additional message to check post condition (ex. bidder is now owner of nft) to prevent malicious nft contract
-    messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+    messages.push(SubMsg::new(WasmMsg::Execute {
         contract_addr: env.contract.address.to_string(),
         msg: to_binary(&ExecuteMsg::SettleHook {
             nft_contract: auction.nft_contract.to_string(),
             token_id: auction.token_id.clone(),
-            owner: bidder.to_string()
+            owner: recipient.to_string()
         })?,
         funds: vec![]
     }));
-    // save auction
+    // save auction, settled auctions move out of the open indices
     auction.is_settled = true;
-    AUCTIONS.save(deps.storage, auction_id.u128(), &auction)?;
+    auctions().save(deps.storage, auction.auction_id.u128(), auction)?;
+    FAILED_SETTLEMENTS.remove(deps.storage, auction.auction_id.u128());
+    // keep sale history after the auction is removed from the indices
+    let sale = record_sale(deps.storage, auction, &bidder, protocol_fee, royalty_fee, is_primary_sale, env.block.time.seconds())?;
+    on_listing_closed(deps.storage, auction)?;
+    on_sale(deps.storage, &sale)?;
+    record_price_observation(deps.storage, &sale)?;
+    record_activity(deps.storage, &bidder, ActivityKind::Won, auction, sale.price, Some(auction.seller.clone()), sale.time)?;
+    record_activity(deps.storage, &auction.seller, ActivityKind::Sold, auction, sale.price, Some(bidder.clone()), sale.time)?;
+    for (recipient, amount) in royalty_paid.iter() {
+        record_activity(deps.storage, recipient, ActivityKind::RoyaltyReceived, auction, *amount, Some(auction.seller.clone()), sale.time)?;
+    }
+
+
+    Ok((messages, sale, keeper_reward))
+}
+
+pub fn batch_settle(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    auction_ids: Vec<Uint128>
+) -> Result<Response, ContractError> {
+    check_batch_size(deps.storage, auction_ids.len())?;
+    let mut messages: Vec<SubMsg> = vec![];
+    let mut events: Vec<Event> = vec![];
+    // each auction is settled in its own sub message, a failure reverts only that item and is recorded on reply
+    for auction_id in auction_ids {
+        match load_settleable(deps.storage, &env, auction_id) {
+            Ok(_) => messages.push(settle_item_msg(&env, auction_id, Some(&info.sender))?),
+            Err(err) => {
+                events.push(Event::new("settle_skipped")
+                    .add_attribute("auction_id", auction_id)
+                    .add_attribute("reason", err.to_string()));
+            }
+        }
+    }
+    let dispatched = messages.len();
+    Ok(Response::new()
+        .add_submessages(messages)
+        .add_events(events)
+        .add_attribute("action", "batch_settle")
+        .add_attribute("dispatched", dispatched.to_string())
+    )
+}
+
+fn transfer_nft_msg(
+    auction: &Auction,
+    recipient: &Addr
+) -> StdResult<CosmosMsg> {
+    Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: auction.nft_contract.to_string(),
+        msg: to_binary(&Cw721ExecuteMsg::TransferNft {
+            recipient: recipient.to_string(),
+            token_id: auction.token_id.clone()
+        })?,
+        funds: vec![]
+    }))
+}
+
+// the receiver rejected the SendNft hook, the nft is transferred without one so the settle hook still holds
+fn transfer_rejected_nft(
+    deps: DepsMut,
+    auction_id: Uint128,
+    reason: String
+) -> Result<Response, ContractError> {
+    let auction = auctions().load(deps.storage, auction_id.u128())?;
+    let recipient = match auction.recipient.clone().or(auction.bidder.clone()) {
+        Some(v) => v,
+        None => return Err(ContractError::InvalidAuction("invalid bidder".to_string()))
+    };
+    Ok(Response::new()
+        .add_message(transfer_nft_msg(&auction, &recipient)?)
+        .add_attribute("action", "send_nft_failed")
+        .add_attribute("auction_id", auction_id)
+        .add_attribute("recipient", recipient)
+        .add_attribute("reason", reason)
+    )
+}
+
+fn settle_item_msg(
+    env: &Env,
+    auction_id: Uint128,
+    keeper: Option<&Addr>
+) -> StdResult<SubMsg> {
+    Ok(SubMsg::reply_on_error(WasmMsg::Execute {
+        contract_addr: env.contract.address.to_string(),
+        msg: to_binary(&ExecuteMsg::SettleBatchItem {
+            auction_id: auction_id,
+            keeper: keeper.map(|x| x.to_string())
+        })?,
+        funds: vec![]
+    }, reply_id(auction_id, REPLY_SETTLE)))
+}
+
+// only failed sub messages reply
+pub fn handle_reply(
+    deps: DepsMut,
+    env: Env,
+    msg: Reply
+) -> Result<Response, ContractError> {
+    let auction_id = Uint128::from(msg.id >> REPLY_KIND_BITS);
+    let reason = match msg.result {
+        SubMsgResult::Err(err) => err,
+        SubMsgResult::Ok(_) => return Ok(Response::new())
+    };
+    let action = match msg.id & ((1 << REPLY_KIND_BITS) - 1) {
+        REPLY_SETTLE => "settle_failed",
+        REPLY_CLOSE => "close_failed",
+        REPLY_SEND_NFT => return transfer_rejected_nft(deps, auction_id, reason),
+        _ => return Err(ContractError::Std(StdError::generic_err("unknown reply id")))
+    };
+    // tick backs off from recorded auctions, so a failing one doesn't take its window every time
+    let attempts = FAILED_SETTLEMENTS.may_load(deps.storage, auction_id.u128())?.map_or(0, |x| x.attempts) + 1;
+    FAILED_SETTLEMENTS.save(deps.storage, auction_id.u128(), &FailedSettlement {
+        reason: reason.clone(),
+        time: env.block.time.seconds(),
+        attempts: attempts
+    })?;
+    Ok(Response::new()
+        .add_attribute("action", action)
+        .add_attribute("auction_id", auction_id)
+        .add_attribute("reason", reason)
+    )
+}
 
-    // remove auction and auction index from mapping
-    NFT_AUCTION_MAPS.remove(deps.storage, (&auction.nft_contract, auction.token_id.clone()));
-    AUCTION_ID_BY_SELLER.remove(deps.storage, (&auction.seller, auction.auction_id.u128()));
+pub fn batch_list(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    items: Vec<BatchListItem>
+) -> Result<Response, ContractError> {
+    check_batch_size(deps.storage, items.len())?;
+    let mut events: Vec<Event> = vec![];
+    let mut listed: u64 = 0;
+    // create_approval_listing checks every condition before it writes, so a skipped item leaves no state behind
+    for item in items {
+        let nft_contract = item.nft_contract.clone();
+        let token_id = item.token_id.clone();
+        match create_approval_listing(deps.branch(), env.clone(), info.clone(), item.nft_contract, item.token_id,
+            item.denom, item.reserve_price, item.is_instant_sale, item.referrer) {
+            Ok(res) => {
+                listed += 1;
+                events.push(Event::new("create_auction").add_attributes(res.attributes));
+            },
+            Err(err) => {
+                events.push(Event::new("list_skipped")
+                    .add_attribute("nft_contract", nft_contract)
+                    .add_attribute("token_id", token_id)
+                    .add_attribute("reason", err.to_string()));
+            }
+        }
+    }
+    Ok(Response::new()
+        .add_events(events)
+        .add_attribute("action", "batch_list")
+        .add_attribute("listed", listed.to_string())
+    )
+}
 
-    if auction.auction_type == AuctionType::Auction {
-        AUCTION_ID_BY_ENDTIME.remove(deps.storage, (&auction.nft_contract, auction.end_time, auction.auction_id.u128()));
-        AUCTION_ID_BY_AMOUNT.remove(deps.storage, (&auction.nft_contract, auction.amount.u128(),  auction_id.u128()));
-        AUCTION_ID_BY_BIDDER.remove(deps.storage, (&bidder, auction.auction_id.u128() ));
+pub fn batch_cancel(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    auction_ids: Vec<Uint128>
+) -> Result<Response, ContractError> {
+    check_batch_size(deps.storage, auction_ids.len())?;
+    let mut messages: Vec<CosmosMsg> = vec![];
+    let mut events: Vec<Event> = vec![];
+    let mut cancelled: u64 = 0;
+    // cancel_auction checks every condition before it writes, so a skipped id leaves no state behind
+    for auction_id in auction_ids {
+        match cancel_auction(deps.branch(), env.clone(), info.clone(), auction_id) {
+            Ok(res) => {
+                cancelled += 1;
+                messages.extend(res.messages.into_iter().map(|x| x.msg));
+                events.push(Event::new("cancel_auction").add_attributes(res.attributes));
+            },
+            Err(err) => {
+                events.push(Event::new("cancel_skipped")
+                    .add_attribute("auction_id", auction_id)
+                    .add_attribute("reason", err.to_string()));
+            }
+        }
     }
-    // remove auction from bidder
     Ok(Response::new()
         .add_messages(messages)
-        .add_attribute("action", "settle")
+        .add_events(events)
+        .add_attribute("action", "batch_cancel")
+        .add_attribute("cancelled", cancelled.to_string())
+    )
+}
+
+pub fn update_listing(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    auction_id: Uint128,
+    reserve_price: Option<Uint128>,
+    denom: Option<String>,
+    buy_now_price: Option<Uint128>,
+    expires_at: Option<u64>
+) -> Result<Response, ContractError> {
+    // seller only, and only before the first bid
+    check_auction_owner(deps.as_ref(), &env, info, auction_id)?;
+    let config = CONFIG.load(deps.storage)?;
+    let mut auction = auctions().load(deps.storage, auction_id.u128())?;
+    let price = match auction.auction_type {
+        AuctionType::BuyNow => {
+            if reserve_price.is_some() {
+                return Err(ContractError::InvalidAuctionType("buy now listing has no reserve price".to_string()));
+            }
+            buy_now_price
+        },
+        AuctionType::Auction => {
+            if buy_now_price.is_some() {
+                return Err(ContractError::InvalidAuctionType("auction has no buy now price".to_string()));
+            }
+            reserve_price
+        }
+    };
+    // zero clears the expiry
+    match expires_at {
+        Some(0) => auction.expires_at = None,
+        Some(expires_at) => {
+            if expires_at <= env.block.time.seconds() {
+                return Err(ContractError::InvalidAuction("listing would be expired".to_string()));
+            }
+            auction.expires_at = Some(expires_at);
+        },
+        None => {}
+    }
+    let mut events: Vec<Event> = vec![];
+    if price.is_some() || denom.is_some() {
+        // active listings are counted per denom
+        let old_auction = auction.clone();
+        if let Some(denom) = denom {
+            if !config.accepted_denom.contains(&denom) {
+                return Err(ContractError::UnsupportedAsset {});
+            }
+            auction.denom = denom;
+        }
+        events.push(reprice_listing(deps.storage, &config, &mut auction, &old_auction.denom, price.unwrap_or(old_auction.reserve_price), env.block.time.seconds())?);
+        if old_auction.denom != auction.denom {
+            on_listing_closed(deps.storage, &old_auction)?;
+            on_listing_created(deps.storage, &auction)?;
+        }
+    }
+    auctions().save(deps.storage, auction_id.u128(), &auction)?;
+
+    Ok(Response::new()
+        .add_events(events)
+        .add_attribute("action", "update_listing")
         .add_attribute("auction_id", auction_id)
-        .add_attribute("nft_contract", auction.nft_contract)
-        .add_attribute("token_id", auction.token_id)
-        .add_attribute("denom", auction.denom)
-        .add_attribute("amount", auction.amount)
-        .add_attribute("seller", auction.seller)
+        .add_attribute("expires_at", auction.expires_at.map(|v| v.to_string()).unwrap_or_default())
+    )
+}
+
+// price of a buy now listing, or reserve of an auction without a bid
+fn reprice_listing(
+    storage: &mut dyn Storage,
+    config: &Config,
+    auction: &mut Auction,
+    old_denom: &str,
+    price: Uint128,
+    time: u64
+) -> Result<Event, ContractError> {
+    if price < config.min_reserve_price {
+        return Err(ContractError::InvalidAmount("reserve price too low".to_string()));
+    }
+    let event = Event::new("price_change")
+        .add_attribute("auction_id", auction.auction_id)
+        .add_attribute("old_price", auction.amount)
+        .add_attribute("old_denom", old_denom)
+        .add_attribute("new_price", price)
+        .add_attribute("new_denom", auction.denom.clone());
+    auction.reserve_price = price;
+    auction.amount = price;
+    record_activity(storage, &auction.seller, ActivityKind::PriceChanged, auction, price, None, time)?;
+    Ok(event)
+}
+
+pub fn batch_update_price(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    items: Vec<PriceUpdate>
+) -> Result<Response, ContractError> {
+    check_batch_size(deps.storage, items.len())?;
+    let config = CONFIG.load(deps.storage)?;
+    let mut events: Vec<Event> = vec![];
+    let mut updated: u64 = 0;
+    // update_price checks every condition before it writes, so a skipped item leaves no state behind
+    for item in items {
+        match update_price(deps.branch(), &env, info.clone(), &config, &item) {
+            Ok(event) => {
+                updated += 1;
+                events.push(event);
+            },
+            Err(err) => {
+                events.push(Event::new("price_update_skipped")
+                    .add_attribute("auction_id", item.auction_id)
+                    .add_attribute("reason", err.to_string()));
+            }
+        }
+    }
+    Ok(Response::new()
+        .add_events(events)
+        .add_attribute("action", "batch_update_price")
+        .add_attribute("updated", updated.to_string())
     )
 }
 
+fn update_price(
+    deps: DepsMut,
+    env: &Env,
+    info: MessageInfo,
+    config: &Config,
+    item: &PriceUpdate
+) -> Result<Event, ContractError> {
+    // seller only, and only before the first bid
+    check_auction_owner(deps.as_ref(), env, info, item.auction_id)?;
+    let mut auction = auctions().load(deps.storage, item.auction_id.u128())?;
+    let denom = auction.denom.clone();
+    let event = reprice_listing(deps.storage, config, &mut auction, &denom, item.reserve_price, env.block.time.seconds())?;
+    auctions().save(deps.storage, item.auction_id.u128(), &auction)?;
+    Ok(event)
+}
+
+pub fn sweep(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    target: SweepTarget,
+    max_total_price: Uint128,
+    allow_partial: bool,
+    referrer: Option<String>
+) -> Result<Response, ContractError> {
+    if info.funds.len() != 1 {
+        return Err(ContractError::InvalidAmount("sweep is paid in one denom".to_string()));
+    }
+    // checked once, so a bad referrer fails the sweep instead of skipping every item
+    let referrer = validate_referrer(deps.as_ref(), referrer, &info.sender)?.map(|x| x.to_string());
+    let fund = info.funds[0].clone();
+    let budget = fund.amount.min(max_total_price);
+    let auction_ids = match target {
+        SweepTarget::AuctionIds { auction_ids } => auction_ids,
+        SweepTarget::Cheapest { nft_contract, max_count } => {
+            let nft_contract = deps.api.addr_validate(&nft_contract)?;
+            // buy now listings without a buyer, cheapest first
+            // expired or unapproved listings are passed over, so they don't take the place of buyable ones
+            let floor = auctions().idx.floor
+                .sub_prefix(((nft_contract, fund.denom.clone()), FLOOR_LISTED))
+                .range(deps.storage, None, None, Order::Ascending)
+                .take(MAX_SWEEP_SCAN)
+                .map(|x| x.map(|(_, auction)| auction))
+                .collect::<StdResult<Vec<Auction>>>()?;
+            let mut auction_ids: Vec<Uint128> = vec![];
+            for auction in floor {
+                if auction_ids.len() >= max_count as usize {
+                    break;
+                }
+                if query_listing_valid(deps.as_ref(), &env, &auction).unwrap_or(false) {
+                    auction_ids.push(auction.auction_id);
+                }
+            }
+            auction_ids
+        }
+    };
+    check_batch_size(deps.storage, auction_ids.len())?;
+
+    let mut messages: Vec<SubMsg> = vec![];
+    let mut events: Vec<Event> = vec![];
+    let mut spent = Uint128::zero();
+    let mut bought: u64 = 0;
+    for auction_id in auction_ids {
+        // place_bid checks every condition before it writes, so a skipped id leaves no state behind
+        let bid = auctions().may_load(deps.storage, auction_id.u128())?
+            .ok_or_else(|| ContractError::InvalidAuction("auction not found".to_string()))
+            .and_then(|auction| {
+                if auction.auction_type != AuctionType::BuyNow {
+                    return Err(ContractError::InvalidAuctionType("only buy now listings can be swept".to_string()));
+                }
+                if auction.is_settled || auction.end_time > 0 {
+                    return Err(ContractError::InvalidAuction("already sold".to_string()));
+                }
+                if auction.denom != fund.denom {
+                    return Err(ContractError::UnsupportedAsset {});
+                }
+                if spent + auction.amount > budget {
+                    return Err(ContractError::InvalidAmount("max total price exceeded".to_string()));
+                }
+                let bid_info = MessageInfo {
+                    sender: info.sender.clone(),
+                    funds: vec![Coin { denom: auction.denom.clone(), amount: auction.amount }]
+                };
+                place_bid(deps.branch(), env.clone(), bid_info, auction_id, None, None, None, referrer.clone())?;
+                Ok(auction.amount)
+            });
+        let price = match bid {
+            Ok(price) => price,
+            Err(err) if allow_partial => {
+                events.push(Event::new("sweep_skipped")
+                    .add_attribute("auction_id", auction_id)
+                    .add_attribute("reason", err.to_string()));
+                continue;
+            },
+            Err(err) => return Err(err)
+        };
+        // the bid holds the buyer's funds, so settlement must not be skipped
+        let res = _settle_auction(deps.branch(), env.clone(), None, auction_id)?;
+        messages.extend(res.messages);
+        events.push(Event::new("sweep_item")
+            .add_attribute("auction_id", auction_id)
+            .add_attribute("price", price));
+        spent += price;
+        bought += 1;
+    }
+    let refund = fund.amount - spent;
+    if refund > Uint128::zero() {
+        let refund_asset = Asset {
+            info: AssetInfo::NativeToken {
+                denom: fund.denom.clone()
+            },
+            amount: refund
+        };
+        messages.push(SubMsg::new(refund_asset.into_msg(info.sender.clone())?));
+    }
+    events.push(Event::new("sweep_summary")
+        .add_attribute("bought", bought.to_string())
+        .add_attribute("denom", fund.denom)
+        .add_attribute("spent", spent)
+        .add_attribute("refund", refund));
+
+    Ok(Response::new()
+        .add_submessages(messages)
+        .add_events(events)
+        .add_attribute("action", "sweep")
+        .add_attribute("buyer", info.sender)
+    )
+}
+
+pub fn tick(
+    deps: DepsMut,
+    env: Env
+) -> Result<Response, ContractError> {
+    // open auctions in end time order from the cursor, listings without a bid (end time 0) are visited to be closed
+    let min = match TICK_CURSOR.may_load(deps.storage)? {
+        Some(cursor) => Bound::exclusive(cursor),
+        None => Bound::inclusive((0, 0))
+    };
+    let max = Bound::inclusive((env.block.time.seconds(), u128::MAX));
+    let scanned = auctions().idx.end_time
+        .sub_prefix(AUCTION_PHASE_OPEN)
+        .range(deps.storage, Some(min), Some(max), Order::Ascending)
+        .take(MAX_TICK_SCAN)
+        .map(|x| x.map(|(_, auction)| auction))
+        .collect::<StdResult<Vec<Auction>>>()?;
+    // every item runs in its own sub message, a failure is recorded on reply instead of reverting the tick
+    let mut messages: Vec<SubMsg> = vec![];
+    let mut cursor: Option<(u64, u128)> = None;
+    let mut settling: usize = 0;
+    for auction in scanned.iter() {
+        if messages.len() >= MAX_TICK_SETTLE {
+            break;
+        }
+        cursor = Some((auction.end_time, auction.auction_id.u128()));
+        if let Some(failed) = FAILED_SETTLEMENTS.may_load(deps.storage, auction.auction_id.u128())? {
+            if env.block.time.seconds() < retry_time(&failed) {
+                continue;
+            }
+        }
+        // expired listings are reclaimed and invalid ones closed, an nft contract that fails the query makes it invalid too
+        if is_listing_expired(auction, env.block.time.seconds()) {
+            messages.push(close_item_msg(&env, auction.auction_id, ExecuteMsg::ReclaimExpired { auction_id: auction.auction_id })?);
+        } else if !query_listing_valid(deps.as_ref(), &env, auction).unwrap_or(false) {
+            messages.push(close_item_msg(&env, auction.auction_id, ExecuteMsg::InvalidateListing { auction_id: auction.auction_id })?);
+        } else if auction.end_time > 0 {
+            messages.push(settle_item_msg(&env, auction.auction_id, None)?);
+            settling += 1;
+        }
+    }
+    // the next tick goes on after the last visited auction, or starts over once the scan reached the end
+    match cursor {
+        Some(cursor) if scanned.len() == MAX_TICK_SCAN || messages.len() >= MAX_TICK_SETTLE => TICK_CURSOR.save(deps.storage, &cursor)?,
+        _ => TICK_CURSOR.remove(deps.storage)
+    };
+    let closing = messages.len() - settling;
+    Ok(Response::new()
+        .add_submessages(messages)
+        .add_attribute("action", "tick")
+        .add_attribute("settling", settling.to_string())
+        .add_attribute("closing", closing.to_string())
+    )
+}
+
+pub fn retry_time(
+    failed: &FailedSettlement
+) -> u64 {
+    let doublings = (failed.attempts - 1).min(MAX_TICK_RETRY_DOUBLINGS);
+    failed.time + (TICK_RETRY_DELAY << doublings)
+}
+
+fn close_item_msg(
+    env: &Env,
+    auction_id: Uint128,
+    msg: ExecuteMsg
+) -> StdResult<SubMsg> {
+    Ok(SubMsg::reply_on_error(WasmMsg::Execute {
+        contract_addr: env.contract.address.to_string(),
+        msg: to_binary(&msg)?,
+        funds: vec![]
+    }, reply_id(auction_id, REPLY_CLOSE)))
+}
+
+pub fn record_sale(
+    storage: &mut dyn Storage,
+    auction: &Auction,
+    buyer: &Addr,
+    protocol_fee: Uint128,
+    royalty_fee: Uint128,
+    is_primary_sale: bool,
+    time: u64
+) -> StdResult<Sale> {
+    let sale_id = NEXT_SALE_ID.may_load(storage)?.unwrap_or(0);
+    let sale = Sale {
+        sale_id: sale_id,
+        auction_id: auction.auction_id,
+        nft_contract: auction.nft_contract.clone(),
+        token_id: auction.token_id.clone(),
+        seller: auction.seller.clone(),
+        buyer: buyer.clone(),
+        denom: auction.denom.clone(),
+        price: auction.amount,
+        protocol_fee: protocol_fee,
+        royalty_fee: royalty_fee,
+        time: time,
+        is_primary_sale: is_primary_sale
+    };
+    SALES.save(storage, sale_id, &sale)?;
+    SALE_ID_BY_TOKEN.save(storage, (&sale.nft_contract, sale.token_id.clone(), sale_id), &true)?;
+    SALE_ID_BY_COLLECTION.save(storage, (&sale.nft_contract, sale_id), &true)?;
+    SALE_ID_BY_SELLER.save(storage, (&sale.seller, sale_id), &true)?;
+    SALE_ID_BY_BUYER.save(storage, (&sale.buyer, sale_id), &true)?;
+    NEXT_SALE_ID.save(storage, &(sale_id + 1))?;
+    Ok(sale)
+}
+
 pub fn settle_hook(
     deps: DepsMut,
     env: Env,
@@ -476,7 +1375,7 @@ pub fn admin_cancel_auction(
     only_owner(deps.as_ref(), &env, info)?;
     // cancel auction
     let mut messages: Vec<CosmosMsg> = vec![];
-    let auction = AUCTIONS.load(deps.storage, auction_id.u128())?;
+    let auction = auctions().load(deps.storage, auction_id.u128())?;
     match auction.auction_type {
         AuctionType::BuyNow => {
             messages.extend(_cancel_auction(deps, env, auction_id)?);
@@ -525,36 +1424,157 @@ pub fn admin_resume(
     )
 }
 
-pub fn admin_change_config(
+pub fn propose_config_change(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
-    protocol_fee: Decimal,
-    min_increment: Decimal,
-    min_reserve_price: Uint128,
-    max_royalty_fee: Decimal,
-    duration: u64,
-    extension_duration: u64,
-    accepted_denom: Vec<String>,
-    collector_address: String
+    change: ConfigChange
 ) -> Result<Response, ContractError> {
     // check only owner
     only_owner(deps.as_ref(), &env, info)?;
+    // only one change can be pending at a time
+    if PENDING_CONFIG_CHANGE.may_load(deps.storage)?.is_some() {
+        return Err(ContractError::InvalidConfigChange("config change is already pending".to_string()));
+    }
+    if let Some(fee_distribution) = &change.fee_distribution {
+        if change.remove_fee_distribution {
+            return Err(ContractError::InvalidConfigChange("fee distribution is set and removed".to_string()));
+        }
+        validate_fee_distribution(deps.api, fee_distribution)?;
+    }
+    if let Some(primary_protocol_fee) = change.primary_protocol_fee {
+        if change.remove_primary_protocol_fee {
+            return Err(ContractError::InvalidConfigChange("primary protocol fee is set and removed".to_string()));
+        }
+        if primary_protocol_fee > Decimal::one() {
+            return Err(ContractError::InvalidConfigChange("primary protocol fee is more than sale price".to_string()));
+        }
+    }
+    // the config after the change must be valid as well
+    let config = CONFIG.load(deps.storage)?;
+    let mut new_config = config.clone();
+    apply_config_change(deps.api, &mut new_config, &change)?;
+    validate_config(&new_config)?;
+    let eta = env.block.time.seconds() + config.config_timelock;
+    PENDING_CONFIG_CHANGE.save(deps.storage, &PendingConfigChange {
+        change: change,
+        eta: eta
+    })?;
+
+    Ok(Response::new()
+        .add_attribute("action", "propose_config_change")
+        .add_attribute("eta", eta.to_string())
+    )
+}
+
+pub fn execute_config_change(
+    deps: DepsMut,
+    env: Env
+) -> Result<Response, ContractError> {
+    let pending = match PENDING_CONFIG_CHANGE.may_load(deps.storage)? {
+        Some(v) => v,
+        None => return Err(ContractError::InvalidConfigChange("no pending config change".to_string()))
+    };
+    if env.block.time.seconds() < pending.eta {
+        return Err(ContractError::InvalidConfigChange("timelock is not over".to_string()));
+    }
     // change config
+    let change = pending.change;
     let mut config = CONFIG.load(deps.storage)?;
-    config.protocol_fee = protocol_fee;
-    config.min_increment = min_increment;
-    config.min_reserve_price = min_reserve_price;
-    config.max_royalty_fee = max_royalty_fee;
-    config.duration = duration;
-    config.extension_duration = extension_duration;
-    config.accepted_denom = accepted_denom;
-    config.collector_address = deps.api.addr_validate(&collector_address)?;
+    apply_config_change(deps.api, &mut config, &change)?;
+    let mut messages: Vec<CosmosMsg> = vec![];
+    if let Some(v) = change.fee_distribution {
+        FEE_DISTRIBUTION.save(deps.storage, &v)?;
+    }
+    if change.remove_fee_distribution {
+        // fees accrued under the removed distribution are paid out before fees go back to the collector
+        messages.extend(flush_accrued_fees(deps.storage, deps.api)?);
+        FEE_DISTRIBUTION.remove(deps.storage);
+    }
+    if let Some(v) = change.primary_protocol_fee {
+        PRIMARY_PROTOCOL_FEE.save(deps.storage, &v)?;
+    }
+    if change.remove_primary_protocol_fee {
+        PRIMARY_PROTOCOL_FEE.remove(deps.storage);
+    }
 
     CONFIG.save(deps.storage, &config)?;
+    PENDING_CONFIG_CHANGE.remove(deps.storage);
 
     Ok(Response::new()
-        .add_attribute("action", "admin_change_config")
+        .add_messages(messages)
+        .add_attribute("action", "execute_config_change")
+    )
+}
+
+fn apply_config_change(
+    api: &dyn Api,
+    config: &mut Config,
+    change: &ConfigChange
+) -> StdResult<()> {
+    if let Some(v) = change.protocol_fee {
+        config.protocol_fee = v;
+    }
+    if let Some(v) = change.min_increment {
+        config.min_increment = v;
+    }
+    if let Some(v) = change.min_reserve_price {
+        config.min_reserve_price = v;
+    }
+    if let Some(v) = change.max_royalty_fee {
+        config.max_royalty_fee = v;
+    }
+    if let Some(v) = change.duration {
+        config.duration = v;
+    }
+    if let Some(v) = change.extension_duration {
+        config.extension_duration = v;
+    }
+    if let Some(v) = &change.accepted_denom {
+        config.accepted_denom = v.clone();
+    }
+    if let Some(v) = &change.collector_address {
+        config.collector_address = api.addr_validate(v)?;
+    }
+    if let Some(v) = change.config_timelock {
+        config.config_timelock = v;
+    }
+    Ok(())
+}
+
+// fees must leave something for the seller, and a zero timelock would make config changes instant
+pub fn validate_config(
+    config: &Config
+) -> Result<(), ContractError> {
+    if config.protocol_fee > Decimal::one() {
+        return Err(ContractError::InvalidConfigChange("protocol fee is more than sale price".to_string()));
+    }
+    if config.max_royalty_fee > Decimal::one() {
+        return Err(ContractError::InvalidConfigChange("max royalty fee is more than sale price".to_string()));
+    }
+    if config.protocol_fee + config.max_royalty_fee > Decimal::one() {
+        return Err(ContractError::InvalidConfigChange("protocol fee and max royalty fee are more than sale price".to_string()));
+    }
+    if config.config_timelock == 0 {
+        return Err(ContractError::InvalidConfigChange("config timelock can't be zero".to_string()));
+    }
+    Ok(())
+}
+
+pub fn cancel_config_change(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo
+) -> Result<Response, ContractError> {
+    // check only owner
+    only_owner(deps.as_ref(), &env, info)?;
+    if PENDING_CONFIG_CHANGE.may_load(deps.storage)?.is_none() {
+        return Err(ContractError::InvalidConfigChange("no pending config change".to_string()));
+    }
+    PENDING_CONFIG_CHANGE.remove(deps.storage);
+
+    Ok(Response::new()
+        .add_attribute("action", "cancel_config_change")
     )
 }
 
@@ -564,28 +1584,29 @@ fn _cancel_auction(
     auction_id: Uint128
 ) -> Result<Vec<CosmosMsg>, ContractError> {
     // send nft back to seller
-    let auction = AUCTIONS.load(deps.storage, auction_id.u128())?;
+    let auction = auctions().load(deps.storage, auction_id.u128())?;
     let mut messages: Vec<CosmosMsg> = vec![];
 
     if auction.is_settled {
         return Err(ContractError::InvalidAuction("already settled".to_string()));
     }
 
-    // return nft back to seller
-    messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
-        contract_addr: auction.nft_contract.to_string(),
-        msg: to_binary(&Cw721ExecuteMsg::TransferNft {
-            token_id: auction.token_id.clone(),
-            recipient: auction.seller.to_string()
-        })?,
-        funds: vec![]
-    }));
+    // return nft back to seller, approval listings never held it
+    if !auction.is_approval_listing {
+        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: auction.nft_contract.to_string(),
+            msg: to_binary(&Cw721ExecuteMsg::TransferNft {
+                token_id: auction.token_id.clone(),
+                recipient: auction.seller.to_string()
+            })?,
+            funds: vec![]
+        }));
+    }
     // need additional message to check post condition (ex. seller is now owner of nft) to prevent malicious nft contract
-    AUCTIONS.remove(deps.storage, auction_id.u128());
-    // remove from mapping
-    NFT_AUCTION_MAPS.remove(deps.storage, (&auction.nft_contract, auction.token_id.clone()));
-    AUCTION_ID_BY_SELLER.remove(deps.storage, (&auction.seller, auction.auction_id.u128()));
-    NOT_STARTED_AUCTION.remove(deps.storage, (&auction.nft_contract, auction.auction_id.u128()));
+    auctions().remove(deps.storage, auction_id.u128())?;
+    FAILED_SETTLEMENTS.remove(deps.storage, auction_id.u128());
+    on_listing_closed(deps.storage, &auction)?;
+    record_activity(deps.storage, &auction.seller, ActivityKind::Cancelled, &auction, auction.amount, None, env.block.time.seconds())?;
 
     Ok(messages)
 }
@@ -597,7 +1618,7 @@ pub fn check_auction_owner(
     auction_id: Uint128
 ) -> Result<bool, ContractError> {
     // retrieve auction
-    let auction = AUCTIONS.load(deps.storage, auction_id.u128())?;
+    let auction = auctions().load(deps.storage, auction_id.u128())?;
     // check that sender is the owner of the auction
     if info.sender != auction.seller {
         return Err(ContractError::Unauthorized {});
@@ -643,10 +1664,66 @@ pub fn calculate_min_bid_amount(
     Ok(min_bid_amount)
 }
 
+// keeper reward for settling at block_time, 0 until min_delay after end_time
+pub fn calculate_keeper_reward(
+    storage: &dyn Storage,
+    denom: &str,
+    protocol_fee: Uint128,
+    end_time: u64,
+    block_time: u64
+) -> StdResult<Uint128> {
+    let config = match KEEPER_REWARD.may_load(storage)? {
+        Some(v) => v,
+        None => return Ok(Uint128::zero())
+    };
+    if block_time < end_time + config.min_delay {
+        return Ok(Uint128::zero());
+    }
+    Ok(keeper_reward_amount(&config.reward, denom, protocol_fee))
+}
+
+pub fn keeper_reward_amount(
+    reward: &KeeperReward,
+    denom: &str,
+    protocol_fee: Uint128
+) -> Uint128 {
+    let amount = match reward {
+        KeeperReward::ProtocolFeeShare { share } => protocol_fee * *share,
+        KeeperReward::Flat { amounts } => amounts
+            .iter()
+            .find(|c| c.denom == denom)
+            .map(|c| c.amount)
+            .unwrap_or_default()
+    };
+    amount.min(protocol_fee)
+}
+
 pub fn calculate_fee(
     multiplier: Decimal,
     amount: Uint128
 ) -> Result<Uint128, ContractError> {
     let fee = amount * multiplier;
     Ok(fee)
+}
+
+// each split gets its share of the price, the rounding dust goes to the creator
+pub fn royalty_payouts(
+    creator: &Addr,
+    splits: &[RoyaltyShare],
+    price: Uint128,
+    royalty_fee: Uint128
+) -> Vec<(Addr, Uint128)> {
+    if splits.is_empty() {
+        return vec![(creator.clone(), royalty_fee)];
+    }
+    let mut payouts: Vec<(Addr, Uint128)> = splits
+        .iter()
+        .map(|x| (x.recipient.clone(), price * x.share))
+        .collect();
+    let dust = royalty_fee - payouts.iter().fold(Uint128::zero(), |acc, (_, x)| acc + *x);
+    match payouts.iter_mut().find(|(x, _)| x == creator) {
+        Some(payout) => payout.1 += dust,
+        None => payouts.push((creator.clone(), dust))
+    };
+    payouts.into_iter().filter(|(_, x)| !x.is_zero()).collect()
 }
\ No newline at end of file
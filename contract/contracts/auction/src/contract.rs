@@ -1,20 +1,36 @@
 use cosmwasm_std::{
     Api, Binary, Env, Querier, StdError, Deps, DepsMut, MessageInfo,
     StdResult, Storage, from_binary, to_binary, Uint128, entry_point,
-    Response
+    Response, Reply
 };
-use marketplace::auction::{InstantiateMsg, ExecuteMsg, QueryMsg, Cw721HookMsg, MigrateMsg};
+use marketplace::auction::{InstantiateMsg, ExecuteMsg, QueryMsg, Cw721HookMsg, MigrateMsg, SudoMsg};
 use cw721::Cw721ReceiveMsg;
+use cw2::{set_contract_version, ContractVersion, CONTRACT};
+use semver::Version;
 
 use crate::error::ContractError;
 use crate::state::{CONFIG, Config, STATE, State};
+use crate::migration::{migrate_state, continue_migration, check_migration_done, LEGACY_VERSION};
 use crate::auction::{create_auction, place_bid, settle_auction, set_royalty_fee, cancel_auction, admin_cancel_auction, admin_resume, 
-    admin_pause, admin_change_config, set_royalty_admin, settle_hook};
+    admin_pause, propose_config_change, execute_config_change, cancel_config_change, set_royalty_admin, settle_hook,
+    set_oracle_config, batch_settle, set_keeper_reward, tick, create_approval_listing, invalidate_listing,
+    batch_list, batch_cancel, batch_update_price, set_max_batch_size, update_listing, sweep, set_royalty_registry, validate_config,
+    settle_batch_item, handle_reply, reclaim_expired};
 use crate::querier::{query_config, query_auction, query_state, query_royalty_fee, query_royalty_admin, query_auction_by_nft,
     query_all_royalty, query_calculate_price, query_nft_auction_map, query_bid_history_by_auction_id, query_auction_by_seller,
-    query_auction_by_end_time, query_auction_by_amount, query_bid_number, construct_action_response, query_not_started_auctions,
-    query_auction_by_bidder
+    query_auction_by_end_time, query_auction_by_amount, query_bid_number, query_not_started_auctions,
+    query_auction_by_bidder, query_pending_config_change, query_sales_history, query_token_provenance,
+    query_collection_stats, query_top_collections_by_volume, query_oracle_config, query_price_observation, query_search_auctions, query_activity, query_bids_by_bidder,
+    query_settleable_auctions, query_keeper_reward, query_max_batch_size, query_order_account, query_order_digest,
+    query_referral_tiers, query_referrer, query_referrers, query_fee_distribution, query_accrued_fees, query_royalty_registry,
+    query_failed_settlements
 };
+use crate::order::{register_pubkey, fulfill_order, cancel_order, increment_nonce, deposit_order_funds, withdraw_order_funds};
+use crate::fee::distribute_fees;
+use crate::referral::{register_referrer, set_referral_tier, approve_referrer, revoke_referrer, validate_referrer};
+
+pub const CONTRACT_NAME: &str = "crates.io:auction";
+pub const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
@@ -32,8 +48,10 @@ pub fn instantiate(
         extension_duration: msg.extension_duration,
         min_increment: msg.min_increment,
         accepted_denom: msg.accepted_denom,
-        collector_address: deps.api.addr_validate(&msg.collector_address)?
+        collector_address: deps.api.addr_validate(&msg.collector_address)?,
+        config_timelock: msg.config_timelock
     };
+    validate_config(&config)?;
 
     CONFIG.save(deps.storage, &config)?;
 
@@ -43,23 +61,59 @@ pub fn instantiate(
     };
 
     STATE.save(deps.storage, &state)?;
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
     Ok(Response::default())
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> Result<Response, ContractError> {
+    // indices and stats are incomplete until the migration is done
+    if !matches!(msg, ExecuteMsg::ContinueMigration {}) {
+        check_migration_done(deps.storage)?;
+    }
     match msg {
         ExecuteMsg::ReceiveNft(msg) => receive_nft(deps, env, info, msg),
-        ExecuteMsg::PlaceBid { auction_id } => place_bid(deps, env, info, auction_id),
-        ExecuteMsg::Settle { auction_id } => settle_auction(deps, env, auction_id),
+        ExecuteMsg::CreateApprovalListing { nft_contract, token_id, denom, reserve_price, is_instant_sale, referrer } =>
+            create_approval_listing(deps, env, info, nft_contract, token_id, denom, reserve_price, is_instant_sale, referrer),
+        ExecuteMsg::InvalidateListing { auction_id } => invalidate_listing(deps, env, auction_id),
+        ExecuteMsg::ReclaimExpired { auction_id } => reclaim_expired(deps, env, auction_id),
+        ExecuteMsg::PlaceBid { auction_id, recipient, refund_to, send_msg, referrer } =>
+            place_bid(deps, env, info, auction_id, recipient, refund_to, send_msg, referrer),
+        ExecuteMsg::Settle { auction_id } => settle_auction(deps, env, info, auction_id),
+        ExecuteMsg::BatchSettle { auction_ids } => batch_settle(deps, env, info, auction_ids),
+        ExecuteMsg::BatchList { items } => batch_list(deps, env, info, items),
+        ExecuteMsg::BatchCancel { auction_ids } => batch_cancel(deps, env, info, auction_ids),
+        ExecuteMsg::Sweep { target, max_total_price, allow_partial, referrer } => sweep(deps, env, info, target, max_total_price, allow_partial, referrer),
+        ExecuteMsg::UpdateListing { auction_id, reserve_price, denom, buy_now_price, expires_at } =>
+            update_listing(deps, env, info, auction_id, reserve_price, denom, buy_now_price, expires_at),
+        ExecuteMsg::BatchUpdatePrice { items } => batch_update_price(deps, env, info, items),
+        ExecuteMsg::RegisterPubkey { pubkey } => register_pubkey(deps, info, pubkey),
+        ExecuteMsg::FulfillOrder { order, signature } => fulfill_order(deps, env, info, order, signature),
+        ExecuteMsg::CancelOrder { order } => cancel_order(deps, info, order),
+        ExecuteMsg::IncrementNonce {} => increment_nonce(deps, info),
+        ExecuteMsg::DepositOrderFunds {} => deposit_order_funds(deps, info),
+        ExecuteMsg::WithdrawOrderFunds { denom, amount } => withdraw_order_funds(deps, info, denom, amount),
+        ExecuteMsg::RegisterReferrer {} => register_referrer(deps, info),
+        ExecuteMsg::DistributeFees {} => distribute_fees(deps),
         ExecuteMsg::CancelAuction { auction_id } => cancel_auction(deps, env, info, auction_id),
         ExecuteMsg::AdminCancelAuction { auction_id } => admin_cancel_auction(deps, env, info, auction_id),
         ExecuteMsg::AdminPause {  } => admin_pause(deps, env, info),
         ExecuteMsg::AdminResume {  } => admin_resume(deps, env, info),
-        ExecuteMsg::AdminChangeConfig { protocol_fee, min_increment, min_reserve_price, max_royalty_fee, duration, extension_duration, accepted_denom, collector_address } => admin_change_config(deps, env, info, protocol_fee, min_increment, min_reserve_price, max_royalty_fee, duration, extension_duration, accepted_denom, collector_address),
-        ExecuteMsg::SetRoyaltyFee { contract_addr, royalty_fee, creator } =>  set_royalty_fee(deps, env, info, contract_addr, creator, royalty_fee),
+        ExecuteMsg::ProposeConfigChange(change) => propose_config_change(deps, env, info, change),
+        ExecuteMsg::ExecuteConfigChange {  } => execute_config_change(deps, env),
+        ExecuteMsg::CancelConfigChange {  } => cancel_config_change(deps, env, info),
+        ExecuteMsg::SetRoyaltyFee { contract_addr, royalty_fee, creator, splits } =>  set_royalty_fee(deps, env, info, contract_addr, creator, royalty_fee, splits),
         ExecuteMsg::SetRoyaltyAdmin { address, enable } => set_royalty_admin(deps, env, info, address, enable),
-        ExecuteMsg::SettleHook { nft_contract, token_id, owner } => settle_hook(deps, env, info, nft_contract, token_id, owner)
+        ExecuteMsg::SetOracleConfig { max_observations, outlier_threshold } => set_oracle_config(deps, env, info, max_observations, outlier_threshold),
+        ExecuteMsg::SetKeeperReward { reward, min_delay } => set_keeper_reward(deps, env, info, reward, min_delay),
+        ExecuteMsg::SetMaxBatchSize { max_batch_size } => set_max_batch_size(deps, env, info, max_batch_size),
+        ExecuteMsg::SetRoyaltyRegistry { address } => set_royalty_registry(deps, env, info, address),
+        ExecuteMsg::SetReferralTier { tier, fee_share } => set_referral_tier(deps, env, info, tier, fee_share),
+        ExecuteMsg::ApproveReferrer { referrer, tier } => approve_referrer(deps, env, info, referrer, tier),
+        ExecuteMsg::RevokeReferrer { referrer } => revoke_referrer(deps, env, info, referrer),
+        ExecuteMsg::SettleHook { nft_contract, token_id, owner } => settle_hook(deps, env, info, nft_contract, token_id, owner),
+        ExecuteMsg::SettleBatchItem { auction_id, keeper } => settle_batch_item(deps, env, info, auction_id, keeper),
+        ExecuteMsg::ContinueMigration {} => continue_migration(deps)
     }
 }
 
@@ -70,20 +124,21 @@ pub fn receive_nft(
     cw721_msg: Cw721ReceiveMsg,
 ) -> Result<Response, ContractError> {
     match from_binary(&cw721_msg.msg) {
-        Ok(Cw721HookMsg::CreateAuction { denom, reserve_price, is_instant_sale }) => {
+        Ok(Cw721HookMsg::CreateAuction { denom, reserve_price, is_instant_sale, referrer }) => {
             // need to check that this contract is owner of nft to prevent malicious contract call this function directly
 
             let seller = deps.api.addr_validate(&cw721_msg.sender)?;
             let nft_contract = info.sender.clone();
             let token_id = cw721_msg.token_id.clone();
-            create_auction(deps, env, nft_contract, token_id.clone(), seller, denom, reserve_price, is_instant_sale)
+            let referrer = validate_referrer(deps.as_ref(), referrer, &seller)?;
+            create_auction(deps, env, nft_contract, token_id.clone(), seller, denom, reserve_price, is_instant_sale, false, referrer)
         }
         Err(err) => Err(ContractError::Std(StdError::generic_err(err.to_string())))
     }
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::Config {} => to_binary(&query_config(deps)?),
         QueryMsg::State {} => to_binary(&query_state(deps)?),
@@ -91,40 +146,82 @@ pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
         QueryMsg::RoyaltyFee{ contract_addr } => to_binary(&query_royalty_fee(deps, contract_addr)?),
         QueryMsg::RoyaltyAdmin { address } => to_binary(&query_royalty_admin(deps, address)?),
         QueryMsg::AllRoyaltyFee { start_after, limit} => to_binary(&query_all_royalty(deps,start_after, limit)?),
-        QueryMsg::CalculatePrice { nft_contract, token_id, amount } => to_binary(&query_calculate_price(deps, nft_contract, token_id, amount)?),
+        QueryMsg::CalculatePrice { nft_contract, token_id, amount } => to_binary(&query_calculate_price(deps, env, nft_contract, token_id, amount)?),
         QueryMsg::NftAuction { nft_contract, token_id } => to_binary(&query_nft_auction_map(deps, nft_contract, token_id)?),
-        QueryMsg::BidHistoryByAuctionId{ auction_id, limit } => to_binary(&query_bid_history_by_auction_id(deps, auction_id, limit)?),
+        QueryMsg::BidHistoryByAuctionId{ auction_id, start_after, limit, is_desc } => to_binary(&query_bid_history_by_auction_id(deps, auction_id, start_after, limit, is_desc)?),
+        QueryMsg::SettleableAuctions { start_after, limit } => to_binary(&query_settleable_auctions(deps, env, start_after, limit)?),
+        QueryMsg::BidsByBidder { bidder, start_after, limit } => to_binary(&query_bids_by_bidder(deps, bidder, start_after, limit)?),
         QueryMsg::BidsCount{ auction_id } => to_binary(&query_bid_number(deps, auction_id)?),
-        QueryMsg::AuctionByContract{ nft_contract, limit } => {
-            let auction_ids = query_auction_by_nft(deps, nft_contract, limit)?;
-            to_binary(&construct_action_response(deps, auction_ids)?)
-        }
-        ,
-        QueryMsg::AuctionBySeller{ seller, limit } => {
-            let auction_ids = query_auction_by_seller(deps, seller, limit)?;
-            to_binary(&construct_action_response(deps, auction_ids)?)
-        },
-        QueryMsg::AuctionByEndTime{ nft_contract, end_time, limit, is_desc}  => {
-            let auction_ids = query_auction_by_end_time(deps, nft_contract, end_time ,limit, is_desc)?;
-            to_binary(&construct_action_response(deps, auction_ids)?)
-        },
-        QueryMsg::AuctionByAmount{ nft_contract, amount, limit }  => {
-            let auction_ids = query_auction_by_amount(deps, nft_contract, amount ,limit)?;
-            to_binary(&construct_action_response(deps, auction_ids)?)
-        },
-        QueryMsg::NotStartedAuction{ nft_contract, start_after, limit, is_desc } => {
-            let auction_ids = query_not_started_auctions(deps, nft_contract, start_after, limit, is_desc)?;
-            to_binary(&construct_action_response(deps, auction_ids)?)
-        }
-        QueryMsg::AuctionByBidder{ bidder, start_after, limit  } => {
-            let auction_ids = query_auction_by_bidder(deps, bidder, start_after, limit)?;
-            to_binary(&construct_action_response(deps, auction_ids)?)
-        }
+        QueryMsg::AuctionByContract{ nft_contract, limit } => to_binary(&query_auction_by_nft(deps, nft_contract, limit)?),
+        QueryMsg::AuctionBySeller{ seller, limit } => to_binary(&query_auction_by_seller(deps, seller, limit)?),
+        QueryMsg::AuctionByEndTime{ nft_contract, end_time, limit, is_desc} => to_binary(&query_auction_by_end_time(deps, nft_contract, end_time ,limit, is_desc)?),
+        QueryMsg::AuctionByAmount{ nft_contract, amount, limit } => to_binary(&query_auction_by_amount(deps, nft_contract, amount ,limit)?),
+        QueryMsg::NotStartedAuction{ nft_contract, start_after, limit, is_desc } => to_binary(&query_not_started_auctions(deps, nft_contract, start_after, limit, is_desc)?),
+        QueryMsg::AuctionByBidder{ bidder, start_after, limit  } => to_binary(&query_auction_by_bidder(deps, bidder, start_after, limit)?),
+        QueryMsg::PendingConfigChange {} => to_binary(&query_pending_config_change(deps)?),
+        QueryMsg::SalesHistory { nft_contract, seller, buyer, start_after, limit, is_desc } => to_binary(&query_sales_history(deps, nft_contract, seller, buyer, start_after, limit, is_desc)?),
+        QueryMsg::TokenProvenance { nft_contract, token_id, start_after, limit, is_desc } => to_binary(&query_token_provenance(deps, nft_contract, token_id, start_after, limit, is_desc)?),
+        QueryMsg::CollectionStats { nft_contract, denom } => to_binary(&query_collection_stats(deps, env, nft_contract, denom)?),
+        QueryMsg::TopCollectionsByVolume { denom, start_after, limit } => to_binary(&query_top_collections_by_volume(deps, denom, start_after, limit)?),
+        QueryMsg::OracleConfig {} => to_binary(&query_oracle_config(deps)?),
+        QueryMsg::KeeperReward {} => to_binary(&query_keeper_reward(deps)?),
+        QueryMsg::MaxBatchSize {} => to_binary(&query_max_batch_size(deps)?),
+        QueryMsg::RoyaltyRegistry {} => to_binary(&query_royalty_registry(deps)?),
+        QueryMsg::PriceObservation { nft_contract, denom, window } => to_binary(&query_price_observation(deps, env, nft_contract, denom, window)?),
+        QueryMsg::SearchAuctions { filter, sort_by, is_desc, cursor, limit } => to_binary(&query_search_auctions(deps, env, filter, sort_by, is_desc, cursor, limit)?),
+        QueryMsg::Activity { address, kinds, start_after, limit } => to_binary(&query_activity(deps, address, kinds, start_after, limit)?),
+        QueryMsg::OrderAccount { address } => to_binary(&query_order_account(deps, address)?),
+        QueryMsg::OrderDigest { order } => to_binary(&query_order_digest(deps, order)?),
+        QueryMsg::FeeDistribution {} => to_binary(&query_fee_distribution(deps)?),
+        QueryMsg::AccruedFees {} => to_binary(&query_accrued_fees(deps)?),
+        QueryMsg::ReferralTiers {} => to_binary(&query_referral_tiers(deps)?),
+        QueryMsg::Referrer { address } => to_binary(&query_referrer(deps, address)?),
+        QueryMsg::Referrers { start_after, limit, is_approved } => to_binary(&query_referrers(deps, start_after, limit, is_approved)?),
+        QueryMsg::FailedSettlements { start_after, limit } => to_binary(&query_failed_settlements(deps, start_after, limit)?)
      }
 }
 
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn sudo(deps: DepsMut, env: Env, msg: SudoMsg) -> Result<Response, ContractError> {
+    match msg {
+        SudoMsg::Tick {} => {
+            check_migration_done(deps.storage)?;
+            tick(deps, env)
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    handle_reply(deps, env, msg)
+}
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn migrate(_deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
-    Ok(Response::default())
+pub fn migrate(mut deps: DepsMut, env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
+    // contracts deployed before v2.1.0 have no contract info
+    let stored = CONTRACT.may_load(deps.storage)?.unwrap_or(ContractVersion {
+        contract: CONTRACT_NAME.to_string(),
+        version: LEGACY_VERSION.to_string()
+    });
+    if stored.contract != CONTRACT_NAME {
+        return Err(ContractError::InvalidMigration(format!("cannot migrate from {}", stored.contract)));
+    }
+    let from_version = parse_version(&stored.version)?;
+    let to_version = parse_version(CONTRACT_VERSION)?;
+    if from_version > to_version {
+        return Err(ContractError::InvalidMigration(format!("cannot downgrade from {} to {}", from_version, to_version)));
+    }
+
+    migrate_state(deps.branch(), &env, &from_version, &msg)?;
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "migrate")
+        .add_attribute("from_version", from_version.to_string())
+        .add_attribute("to_version", to_version.to_string())
+    )
+}
+
+fn parse_version(version: &str) -> Result<Version, ContractError> {
+    Version::parse(version).map_err(|e| ContractError::InvalidMigration(e.to_string()))
 }
\ No newline at end of file
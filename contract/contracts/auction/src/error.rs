@@ -21,5 +21,13 @@ pub enum ContractError {
     #[error("invalid amount: {0}")]
     InvalidAmount(String),
     #[error("invalid asset: {0}")]
-    InvalidAsset(String)
+    InvalidAsset(String),
+    #[error("invalid config change: {0}")]
+    InvalidConfigChange(String),
+    #[error("invalid migration: {0}")]
+    InvalidMigration(String),
+    #[error("invalid order: {0}")]
+    InvalidOrder(String),
+    #[error("invalid referrer: {0}")]
+    InvalidReferrer(String)
 }
@@ -0,0 +1,139 @@
+use cosmwasm_std::{Api, BankMsg, Coin, CosmosMsg, DepsMut, Order, Response, StdResult, Storage, Uint128};
+use terraswap::asset::{Asset, AssetInfo};
+use marketplace::auction::FeeDistribution;
+
+use crate::error::ContractError;
+use crate::state::{Config, FEE_DISTRIBUTION, ACCRUED_FEES};
+
+pub const MAX_FEE_RECIPIENTS: usize = 10;
+
+pub fn validate_fee_distribution(
+    api: &dyn Api,
+    distribution: &FeeDistribution
+) -> Result<(), ContractError> {
+    if distribution.recipients.len() > MAX_FEE_RECIPIENTS {
+        return Err(ContractError::InvalidConfigChange("too many fee recipients".to_string()));
+    }
+    let total_weight = distribution.recipients.iter().fold(distribution.burn_weight, |acc, x| acc + x.weight);
+    if total_weight == 0 {
+        return Err(ContractError::InvalidConfigChange("fee distribution has no weight".to_string()));
+    }
+    for recipient in distribution.recipients.iter() {
+        api.addr_validate(&recipient.address)?;
+    }
+    Ok(())
+}
+
+// split by weight, the rounding remainder goes to the first recipient or is burned if there is none
+pub fn distribute(
+    api: &dyn Api,
+    distribution: &FeeDistribution,
+    denom: &str,
+    amount: Uint128
+) -> StdResult<Vec<CosmosMsg>> {
+    let total_weight = distribution.recipients.iter().fold(distribution.burn_weight, |acc, x| acc + x.weight);
+    let mut shares: Vec<Uint128> = distribution.recipients
+        .iter()
+        .map(|x| amount.multiply_ratio(x.weight, total_weight))
+        .collect();
+    let mut burn_amount = amount.multiply_ratio(distribution.burn_weight, total_weight);
+    let remainder = amount - shares.iter().fold(burn_amount, |acc, x| acc + *x);
+    match shares.first_mut() {
+        Some(share) => *share += remainder,
+        None => burn_amount += remainder
+    };
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    for (recipient, share) in distribution.recipients.iter().zip(shares) {
+        if share.is_zero() {
+            continue;
+        }
+        let asset = Asset {
+            info: AssetInfo::NativeToken {
+                denom: denom.to_string()
+            },
+            amount: share
+        };
+        messages.push(asset.into_msg(api.addr_validate(&recipient.address)?)?);
+    }
+    if !burn_amount.is_zero() {
+        messages.push(CosmosMsg::Bank(BankMsg::Burn {
+            amount: vec![Coin { denom: denom.to_string(), amount: burn_amount }]
+        }));
+    }
+    Ok(messages)
+}
+
+// protocol fee of a sale, it is paid to the collector, split or accrued depending on the fee distribution
+pub fn collect_protocol_fee(
+    storage: &mut dyn Storage,
+    api: &dyn Api,
+    config: &Config,
+    denom: &str,
+    amount: Uint128
+) -> StdResult<Vec<CosmosMsg>> {
+    if amount.is_zero() {
+        return Ok(vec![]);
+    }
+    match FEE_DISTRIBUTION.may_load(storage)? {
+        None => {
+            let asset = Asset {
+                info: AssetInfo::NativeToken {
+                    denom: denom.to_string()
+                },
+                amount: amount
+            };
+            Ok(vec![asset.into_msg(config.collector_address.clone())?])
+        },
+        Some(distribution) if distribution.distribute_on_settle => distribute(api, &distribution, denom, amount),
+        Some(_) => {
+            ACCRUED_FEES.update(storage, denom, |x| -> StdResult<Uint128> {
+                Ok(x.unwrap_or_default() + amount)
+            })?;
+            Ok(vec![])
+        }
+    }
+}
+
+pub fn distribute_fees(
+    deps: DepsMut
+) -> Result<Response, ContractError> {
+    if FEE_DISTRIBUTION.may_load(deps.storage)?.is_none() {
+        return Err(ContractError::InvalidConfigChange("fee distribution is not set".to_string()));
+    }
+    let denoms = ACCRUED_FEES
+        .range(deps.storage, None, None, Order::Ascending)
+        .filter(|x| !matches!(x, Ok((_, amount)) if amount.is_zero()))
+        .map(|x| x.map(|(denom, _)| denom))
+        .collect::<StdResult<Vec<String>>>()?;
+    if denoms.is_empty() {
+        return Err(ContractError::InvalidAmount("no accrued fees".to_string()));
+    }
+    let messages = flush_accrued_fees(deps.storage, deps.api)?;
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attribute("action", "distribute_fees")
+        .add_attribute("denoms", denoms.join(","))
+    )
+}
+
+// pay out every accrued fee with the current distribution
+pub fn flush_accrued_fees(
+    storage: &mut dyn Storage,
+    api: &dyn Api
+) -> StdResult<Vec<CosmosMsg>> {
+    let distribution = match FEE_DISTRIBUTION.may_load(storage)? {
+        Some(v) => v,
+        None => return Ok(vec![])
+    };
+    let accrued = ACCRUED_FEES
+        .range(storage, None, None, Order::Ascending)
+        .filter(|x| !matches!(x, Ok((_, amount)) if amount.is_zero()))
+        .collect::<StdResult<Vec<(String, Uint128)>>>()?;
+    let mut messages: Vec<CosmosMsg> = vec![];
+    for (denom, amount) in accrued.iter() {
+        messages.extend(distribute(api, &distribution, denom, *amount)?);
+        ACCRUED_FEES.remove(storage, denom);
+    }
+    Ok(messages)
+}
@@ -3,6 +3,14 @@ mod error;
 pub mod state;
 pub mod auction;
 pub mod querier;
+pub mod migration;
+pub mod stats;
+pub mod oracle;
+pub mod search;
+pub mod activity;
+pub mod order;
+pub mod referral;
+pub mod fee;
 
 pub use crate::error::ContractError;
 
@@ -0,0 +1,286 @@
+use cosmwasm_std::{Addr, Decimal, DepsMut, Env, Order, Response, StdResult, Storage, Uint128};
+use cw_storage_plus::{Bound, Item, Map};
+use schemars::JsonSchema;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use marketplace::auction::{MigrateMsg, Bid};
+
+use crate::error::ContractError;
+use crate::state::{CONFIG, Config, auctions, Auction, bid_history, BID_COUNT_BY_AUCTION_ID};
+use crate::stats::{on_listing_created, on_sale};
+use crate::auction::{validate_config, record_sale, calculate_fee};
+
+// v2.0.0 did not store cw2 contract info
+pub const LEGACY_VERSION: &str = "2.0.0";
+pub const DEFAULT_CONFIG_TIMELOCK: u64 = 86400;
+// storage entries rebuilt per migrate or ContinueMigration call
+pub const MIGRATION_BATCH_SIZE: usize = 200;
+
+// config layout of v2.0.0
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ConfigV2_0_0 {
+    pub owner: Addr,
+    pub protocol_fee: Decimal,
+    pub min_reserve_price: Uint128,
+    pub min_increment: Decimal,
+    pub max_royalty_fee: Decimal,
+    pub duration: u64,
+    pub extension_duration: u64,
+    pub accepted_denom: Vec<String>,
+    pub collector_address: Addr
+}
+
+pub const CONFIG_V2_0_0: Item<ConfigV2_0_0> = Item::new("config");
+// auctions of v2.0.0 without the indexed map indices, same namespace as auctions()
+pub const AUCTIONS_V2_0_0: Map<u128, Auction> = Map::new("dealers");
+// bid history of v2.0.0 without the bidder index, same namespace as bid_history()
+pub const BID_HISTORY_V2_0_0: Map<(u128, u128), Bid> = Map::new("bid_history_by_auction_id");
+// hand maintained indices of v2.0.0
+pub const NFT_AUCTION_MAPS_V2_0_0: Map<(&Addr, String), u128> = Map::new("nft_auction_maps");
+pub const AUCTION_ID_BY_SELLER_V2_0_0: Map<(&Addr, u128), bool> = Map::new("auction_id_by_seller");
+pub const AUCTION_ID_BY_ENDTIME_V2_0_0: Map<(&Addr, u64, u128), bool> = Map::new("auction_id_by_end_time");
+pub const AUCTION_ID_BY_AMOUNT_V2_0_0: Map<(&Addr, u128, u128), bool> = Map::new("auction_id_by_amount");
+pub const AUCTION_ID_BY_BIDDER_V2_0_0: Map<(&Addr, u128), bool> = Map::new("auction_id_by_bidder");
+pub const NOT_STARTED_AUCTION_V2_0_0: Map<(&Addr, u128), bool> = Map::new("not_started_auction");
+
+// rebuild work left by migrate, done in batches so a large store doesn't exceed the gas limit of one transaction
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum MigrationStep {
+    // entries are removed from the start, so no cursor is needed
+    LegacyIndices,
+    Auctions { start_after: Option<u128> },
+    Bids { start_after: Option<(u128, u128)> },
+    Sales { start_after: Option<u128> }
+}
+
+// set while a migration is unfinished, other messages are rejected until it is done
+pub const MIGRATION_STEP: Item<MigrationStep> = Item::new("migration_step");
+
+// run every state migration newer than the stored version, in order
+pub fn migrate_state(
+    deps: DepsMut,
+    _env: &Env,
+    from_version: &Version,
+    msg: &MigrateMsg
+) -> Result<(), ContractError> {
+    if *from_version < Version::new(2, 1, 0) {
+        migrate_config_v2_1_0(deps.storage, msg)?;
+        MIGRATION_STEP.save(deps.storage, &MigrationStep::LegacyIndices)?;
+    }
+    migrate_batch(deps.storage, MIGRATION_BATCH_SIZE)?;
+    Ok(())
+}
+
+pub fn continue_migration(
+    deps: DepsMut
+) -> Result<Response, ContractError> {
+    if MIGRATION_STEP.may_load(deps.storage)?.is_none() {
+        return Err(ContractError::InvalidMigration("no migration in progress".to_string()));
+    }
+    let is_done = migrate_batch(deps.storage, MIGRATION_BATCH_SIZE)?;
+    Ok(Response::new()
+        .add_attribute("action", "continue_migration")
+        .add_attribute("done", is_done.to_string())
+    )
+}
+
+pub fn check_migration_done(
+    storage: &dyn Storage
+) -> Result<(), ContractError> {
+    if MIGRATION_STEP.may_load(storage)?.is_some() {
+        return Err(ContractError::InvalidMigration("migration in progress".to_string()));
+    }
+    Ok(())
+}
+
+// rebuild at most limit entries, true once the migration is done
+pub fn migrate_batch(
+    storage: &mut dyn Storage,
+    limit: usize
+) -> Result<bool, ContractError> {
+    let mut left = limit;
+    while let Some(step) = MIGRATION_STEP.may_load(storage)? {
+        if left == 0 {
+            return Ok(false);
+        }
+        let (used, next) = match step {
+            MigrationStep::LegacyIndices => drop_legacy_indices(storage, left)?,
+            MigrationStep::Auctions { start_after } => reindex_auctions(storage, start_after, left)?,
+            MigrationStep::Bids { start_after } => reindex_bids(storage, start_after, left)?,
+            MigrationStep::Sales { start_after } => backfill_sales(storage, start_after, left)?
+        };
+        left -= used;
+        match next {
+            Some(next) => MIGRATION_STEP.save(storage, &next)?,
+            None => MIGRATION_STEP.remove(storage)
+        }
+    }
+    Ok(true)
+}
+
+// v2.1.0 added config_timelock
+fn migrate_config_v2_1_0(
+    storage: &mut dyn Storage,
+    msg: &MigrateMsg
+) -> Result<(), ContractError> {
+    let legacy = CONFIG_V2_0_0.load(storage)?;
+    let config = Config {
+        owner: legacy.owner,
+        protocol_fee: legacy.protocol_fee,
+        min_reserve_price: legacy.min_reserve_price,
+        min_increment: legacy.min_increment,
+        max_royalty_fee: legacy.max_royalty_fee,
+        duration: legacy.duration,
+        extension_duration: legacy.extension_duration,
+        accepted_denom: legacy.accepted_denom,
+        collector_address: legacy.collector_address,
+        config_timelock: msg.config_timelock.unwrap_or(DEFAULT_CONFIG_TIMELOCK)
+    };
+    validate_config(&config)?;
+    CONFIG.save(storage, &config)?;
+    Ok(())
+}
+
+// hand maintained indices drifted in older versions (ex. cancel did not clean up bid indices),
+// so drop them and let auctions() index every stored auction again
+fn drop_legacy_indices(
+    storage: &mut dyn Storage,
+    limit: usize
+) -> StdResult<(usize, Option<MigrationStep>)> {
+    let mut used = 0;
+    let nft_auction_keys = NFT_AUCTION_MAPS_V2_0_0
+        .keys(storage, None, None, Order::Ascending)
+        .take(limit - used)
+        .collect::<StdResult<Vec<_>>>()?;
+    used += nft_auction_keys.len();
+    for (nft_contract, token_id) in nft_auction_keys {
+        NFT_AUCTION_MAPS_V2_0_0.remove(storage, (&nft_contract, token_id));
+    }
+    let seller_keys = AUCTION_ID_BY_SELLER_V2_0_0
+        .keys(storage, None, None, Order::Ascending)
+        .take(limit - used)
+        .collect::<StdResult<Vec<_>>>()?;
+    used += seller_keys.len();
+    for (seller, auction_id) in seller_keys {
+        AUCTION_ID_BY_SELLER_V2_0_0.remove(storage, (&seller, auction_id));
+    }
+    let end_time_keys = AUCTION_ID_BY_ENDTIME_V2_0_0
+        .keys(storage, None, None, Order::Ascending)
+        .take(limit - used)
+        .collect::<StdResult<Vec<_>>>()?;
+    used += end_time_keys.len();
+    for (nft_contract, end_time, auction_id) in end_time_keys {
+        AUCTION_ID_BY_ENDTIME_V2_0_0.remove(storage, (&nft_contract, end_time, auction_id));
+    }
+    let amount_keys = AUCTION_ID_BY_AMOUNT_V2_0_0
+        .keys(storage, None, None, Order::Ascending)
+        .take(limit - used)
+        .collect::<StdResult<Vec<_>>>()?;
+    used += amount_keys.len();
+    for (nft_contract, amount, auction_id) in amount_keys {
+        AUCTION_ID_BY_AMOUNT_V2_0_0.remove(storage, (&nft_contract, amount, auction_id));
+    }
+    let bidder_keys = AUCTION_ID_BY_BIDDER_V2_0_0
+        .keys(storage, None, None, Order::Ascending)
+        .take(limit - used)
+        .collect::<StdResult<Vec<_>>>()?;
+    used += bidder_keys.len();
+    for (bidder, auction_id) in bidder_keys {
+        AUCTION_ID_BY_BIDDER_V2_0_0.remove(storage, (&bidder, auction_id));
+    }
+    let not_started_keys = NOT_STARTED_AUCTION_V2_0_0
+        .keys(storage, None, None, Order::Ascending)
+        .take(limit - used)
+        .collect::<StdResult<Vec<_>>>()?;
+    used += not_started_keys.len();
+    for (nft_contract, auction_id) in not_started_keys {
+        NOT_STARTED_AUCTION_V2_0_0.remove(storage, (&nft_contract, auction_id));
+    }
+    // a full batch may have left entries behind
+    let next = if used < limit {
+        MigrationStep::Auctions { start_after: None }
+    } else {
+        MigrationStep::LegacyIndices
+    };
+    Ok((used, Some(next)))
+}
+
+// v2.0.0 had no collection stats, listing counters start from the open auctions
+fn reindex_auctions(
+    storage: &mut dyn Storage,
+    start_after: Option<u128>,
+    limit: usize
+) -> StdResult<(usize, Option<MigrationStep>)> {
+    let stored_auctions = AUCTIONS_V2_0_0
+        .range(storage, start_after.map(Bound::exclusive), None, Order::Ascending)
+        .take(limit)
+        .collect::<StdResult<Vec<(u128, Auction)>>>()?;
+    let used = stored_auctions.len();
+    let last = stored_auctions.last().map(|(auction_id, _)| *auction_id);
+    for (auction_id, auction) in stored_auctions {
+        // saving over the same value writes every index entry
+        auctions().save(storage, auction_id, &auction)?;
+        if !auction.is_settled {
+            on_listing_created(storage, &auction)?;
+        }
+    }
+    let next = match last {
+        Some(last) if used == limit => MigrationStep::Auctions { start_after: Some(last) },
+        _ => MigrationStep::Bids { start_after: None }
+    };
+    Ok((used, Some(next)))
+}
+
+// v2.0.0 saved a bid count of 1 after every bid, so later bids overwrote bid number 2.
+// overwritten bids are lost, the count is repaired to the last stored bid number
+fn reindex_bids(
+    storage: &mut dyn Storage,
+    start_after: Option<(u128, u128)>,
+    limit: usize
+) -> StdResult<(usize, Option<MigrationStep>)> {
+    let bids = BID_HISTORY_V2_0_0
+        .range(storage, start_after.map(Bound::exclusive), None, Order::Ascending)
+        .take(limit)
+        .collect::<StdResult<Vec<((u128, u128), Bid)>>>()?;
+    let used = bids.len();
+    let last = bids.last().map(|(key, _)| *key);
+    for ((auction_id, bid_number), bid) in bids {
+        bid_history().save(storage, (auction_id, bid_number), &bid)?;
+        BID_COUNT_BY_AUCTION_ID.save(storage, auction_id, &Uint128::from(bid_number))?;
+    }
+    let next = match last {
+        Some(last) if used == limit => MigrationStep::Bids { start_after: Some(last) },
+        _ => MigrationStep::Sales { start_after: None }
+    };
+    Ok((used, Some(next)))
+}
+
+// sales history was added in v2.1.0, settled auctions become sales so provenance, primary sale detection
+// and collection stats see them. the settlement time was not stored, the end time is used instead
+fn backfill_sales(
+    storage: &mut dyn Storage,
+    start_after: Option<u128>,
+    limit: usize
+) -> Result<(usize, Option<MigrationStep>), ContractError> {
+    let scanned = auctions()
+        .range(storage, start_after.map(Bound::exclusive), None, Order::Ascending)
+        .take(limit)
+        .collect::<StdResult<Vec<(u128, Auction)>>>()?;
+    let used = scanned.len();
+    let last = scanned.last().map(|(auction_id, _)| *auction_id);
+    for (_, auction) in scanned {
+        let bidder = match &auction.bidder {
+            Some(v) if auction.is_settled => v.clone(),
+            _ => continue
+        };
+        let protocol_fee = calculate_fee(auction.protocol_fee, auction.amount)?;
+        let royalty_fee = calculate_fee(auction.royalty_fee, auction.amount)?;
+        let sale = record_sale(storage, &auction, &bidder, protocol_fee, royalty_fee, false, auction.end_time)?;
+        on_sale(storage, &sale)?;
+    }
+    let next = match last {
+        Some(last) if used == limit => Some(MigrationStep::Sales { start_after: Some(last) }),
+        _ => None
+    };
+    Ok((used, next))
+}
@@ -2,11 +2,14 @@ use cosmwasm_std::testing::{MockApi, MockQuerier, MockStorage, MOCK_CONTRACT_ADD
 use cosmwasm_std::{
     from_slice, to_binary, Api, Coin, Decimal, Querier, OwnedDeps,
     QuerierResult, QueryRequest, SystemError, Uint128, WasmQuery, from_binary,
-    SystemResult, ContractResult, Addr, Empty
+    SystemResult, ContractResult, Addr, Empty, ContractInfoResponse
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::marker::PhantomData;
-use cw721::{Cw721QueryMsg, OwnerOfResponse};
+use cw721::{Cw721QueryMsg, OwnerOfResponse, OperatorsResponse, Approval, Expiration};
+use marketplace::auction::{RoyaltyFeeResponse, RoyaltyResponse, AllRoyaltyResponse, AllRoyaltyListResponse};
+use marketplace::royalty_registry::QueryMsg as RoyaltyRegistryQueryMsg;
+use marketplace::royalty::{MinterQueryMsg, MinterResponse};
 
 /// mock_dependencies is a drop-in replacement for cosmwasm_std::testing::mock_dependencies
 /// this uses our CustomQuerier.
@@ -31,16 +34,39 @@ pub struct WasmMockQuerier {
 #[derive(Clone, Default)]
 pub struct NftQuerier {
     owners: HashMap<String, HashMap<String, String>>,
+    // nft contract -> token id -> spenders
+    approvals: HashMap<String, HashMap<String, Vec<String>>>,
+    // nft contract -> owner -> operators
+    operators: HashMap<String, HashMap<String, Vec<String>>>,
+    minters: HashMap<String, String>,
+    admins: HashMap<String, String>,
+    // nft contract -> royalty resolved by the royalty registry
+    registry_royalties: HashMap<String, RoyaltyResponse>,
+    // plain accounts, they have no contract info
+    accounts: HashSet<String>
 }
 
 impl NftQuerier {
     pub fn new() -> Self {
         NftQuerier {
-            owners: HashMap::new()
+            owners: HashMap::new(),
+            approvals: HashMap::new(),
+            operators: HashMap::new(),
+            minters: HashMap::new(),
+            admins: HashMap::new(),
+            registry_royalties: HashMap::new(),
+            accounts: HashSet::new()
         }
     }
 }
 
+fn to_approvals(spenders: Option<&Vec<String>>) -> Vec<Approval> {
+    spenders.cloned().unwrap_or_default().into_iter().map(|spender| Approval {
+        spender: spender,
+        expires: Expiration::Never {}
+    }).collect()
+}
+
 impl Querier for WasmMockQuerier {
     fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
         // MockQuerier doesn't support Custom, so we ignore it completely here
@@ -61,19 +87,66 @@ impl WasmMockQuerier {
     pub fn handle_query(&self, request: &QueryRequest<Empty>) -> QuerierResult {
         match &request {
             QueryRequest::Wasm(WasmQuery::Smart { contract_addr, msg }) => {
+                if let Ok(MinterQueryMsg::Minter {}) = from_binary(msg) {
+                    return match self.nft_querier.minters.get(contract_addr) {
+                        Some(minter) => SystemResult::Ok(ContractResult::Ok(to_binary(&MinterResponse {
+                            minter: minter.clone()
+                        }).unwrap())),
+                        None => SystemResult::Ok(ContractResult::Err("unknown query".to_string()))
+                    };
+                }
+                match from_binary(msg) {
+                    Ok(RoyaltyRegistryQueryMsg::RoyaltyFee { contract_addr, .. }) => {
+                        return SystemResult::Ok(ContractResult::Ok(to_binary(&RoyaltyFeeResponse {
+                            royalty_fee: self.nft_querier.registry_royalties.get(&contract_addr).cloned()
+                        }).unwrap()));
+                    },
+                    Ok(RoyaltyRegistryQueryMsg::AllRoyaltyFee { start_after, limit }) => {
+                        let mut royalty_fees: Vec<AllRoyaltyResponse> = self.nft_querier.registry_royalties.iter()
+                            .filter(|(contract_addr, _)| start_after.as_ref().map_or(true, |x| *contract_addr > x))
+                            .map(|(contract_addr, royalty)| AllRoyaltyResponse {
+                                contract_addr: contract_addr.clone(),
+                                royalty_fee: royalty.royalty_fee,
+                                creator: royalty.creator.clone(),
+                                splits: royalty.splits.clone()
+                            })
+                            .collect();
+                        royalty_fees.sort_by(|a, b| a.contract_addr.cmp(&b.contract_addr));
+                        royalty_fees.truncate(limit.unwrap_or(10) as usize);
+                        return SystemResult::Ok(ContractResult::Ok(to_binary(&AllRoyaltyListResponse {
+                            royalty_fees: royalty_fees
+                        }).unwrap()));
+                    },
+                    _ => {}
+                }
                 match from_binary(msg).unwrap() {
                     Cw721QueryMsg::OwnerOf { token_id, .. } => {
                         let nft_owners = self.nft_querier.owners.get(contract_addr).unwrap();
                         let owner = nft_owners.get(&token_id).unwrap();
+                        let approvals = self.nft_querier.approvals.get(contract_addr).and_then(|x| x.get(&token_id));
                         SystemResult::Ok(ContractResult::Ok(to_binary(&OwnerOfResponse {
                             owner: owner.clone(),
-                            approvals: vec![]
+                            approvals: to_approvals(approvals)
                         }).unwrap()))
                     
                     },
+                    Cw721QueryMsg::AllOperators { owner, .. } => {
+                        let operators = self.nft_querier.operators.get(contract_addr).and_then(|x| x.get(&owner));
+                        SystemResult::Ok(ContractResult::Ok(to_binary(&OperatorsResponse {
+                            operators: to_approvals(operators)
+                        }).unwrap()))
+                    },
                     _ => panic!("DO NOT ENTER HERE")
                 }
             }
+            QueryRequest::Wasm(WasmQuery::ContractInfo { contract_addr }) => {
+                if self.nft_querier.accounts.contains(contract_addr) {
+                    return SystemResult::Err(SystemError::NoSuchContract { addr: contract_addr.clone() });
+                }
+                let mut contract_info = ContractInfoResponse::new(1, "deployer");
+                contract_info.admin = self.nft_querier.admins.get(contract_addr).cloned();
+                SystemResult::Ok(ContractResult::Ok(to_binary(&contract_info).unwrap()))
+            }
             _ => self.base.handle_query(request),
         }
     }
@@ -105,4 +178,36 @@ impl WasmMockQuerier {
         let owner_map = self.nft_querier.owners.get_mut(&nft_address).unwrap();
         owner_map.insert(token_id.clone(), owner.clone());
     }
-}
\ No newline at end of file
+
+    // configure approval of a single nft, none revokes
+    pub fn with_nft_approval(&mut self, nft_address: String, token_id: String, spender: Option<String>) {
+        let approvals = self.nft_querier.approvals.entry(nft_address).or_default();
+        approvals.insert(token_id, spender.into_iter().collect());
+    }
+
+    // configure minter of nft contract
+    pub fn with_nft_minter(&mut self, nft_address: String, minter: String) {
+        self.nft_querier.minters.insert(nft_address, minter);
+    }
+
+    // configure contract admin
+    pub fn with_contract_admin(&mut self, contract_address: String, admin: String) {
+        self.nft_querier.admins.insert(contract_address, admin);
+    }
+
+    // configure an address that is not a contract
+    pub fn with_account(&mut self, address: String) {
+        self.nft_querier.accounts.insert(address);
+    }
+
+    // configure royalty of nft contract in the royalty registry
+    pub fn with_registry_royalty(&mut self, nft_address: String, royalty: RoyaltyResponse) {
+        self.nft_querier.registry_royalties.insert(nft_address, royalty);
+    }
+
+    // configure operator approved for all nfts of owner, none revokes
+    pub fn with_nft_operator(&mut self, nft_address: String, owner: String, operator: Option<String>) {
+        let operators = self.nft_querier.operators.entry(nft_address).or_default();
+        operators.insert(owner, operator.into_iter().collect());
+    }
+}
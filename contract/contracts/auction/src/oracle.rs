@@ -0,0 +1,94 @@
+use cosmwasm_std::{Decimal, StdError, StdResult, Storage, Uint128, Uint256};
+use marketplace::auction::Sale;
+use std::convert::TryFrom;
+
+use crate::state::{OracleConfig, PriceObservation, ORACLE_CONFIG, PRICE_OBSERVATIONS, NEXT_OBSERVATION_ID};
+
+pub const DEFAULT_MAX_OBSERVATIONS: u64 = 100;
+pub const MAX_OBSERVATIONS_LIMIT: u64 = 1000;
+
+pub fn load_oracle_config(
+    storage: &dyn Storage
+) -> StdResult<OracleConfig> {
+    Ok(ORACLE_CONFIG.may_load(storage)?.unwrap_or(OracleConfig {
+        max_observations: DEFAULT_MAX_OBSERVATIONS,
+        outlier_threshold: None
+    }))
+}
+
+pub fn record_price_observation(
+    storage: &mut dyn Storage,
+    sale: &Sale
+) -> StdResult<()> {
+    let config = load_oracle_config(storage)?;
+    let key = (&sale.nft_contract, sale.denom.as_str());
+    let observation_id = NEXT_OBSERVATION_ID.may_load(storage, key)?.unwrap_or(0);
+    PRICE_OBSERVATIONS.save(storage, (key, observation_id), &PriceObservation {
+        price: sale.price,
+        time: sale.time
+    })?;
+    // drop the oldest observation once the buffer is full
+    if observation_id >= config.max_observations {
+        PRICE_OBSERVATIONS.remove(storage, (key, observation_id - config.max_observations));
+    }
+    NEXT_OBSERVATION_ID.save(storage, key, &(observation_id + 1))?;
+    Ok(())
+}
+
+pub fn median(
+    prices: &[Uint128]
+) -> Uint128 {
+    if prices.is_empty() {
+        return Uint128::zero();
+    }
+    let mut sorted = prices.to_vec();
+    sorted.sort();
+    let mid = sorted.len() / 2;
+    if sorted.len() % 2 == 0 {
+        (sorted[mid - 1] + sorted[mid]) / Uint128::from(2u128)
+    } else {
+        sorted[mid]
+    }
+}
+
+pub fn is_outlier(
+    price: Uint128,
+    median: Uint128,
+    threshold: Decimal
+) -> bool {
+    let lower = if threshold < Decimal::one() {
+        median * (Decimal::one() - threshold)
+    } else {
+        Uint128::zero()
+    };
+    let upper = median * (Decimal::one() + threshold);
+    price < lower || price > upper
+}
+
+// each price is weighted by how long it was the last sale price, the last one until now
+pub fn time_weighted_price(
+    observations: &[PriceObservation],
+    now: u64
+) -> StdResult<Uint128> {
+    if observations.is_empty() {
+        return Ok(Uint128::zero());
+    }
+    let mut weighted_sum = Uint256::zero();
+    let mut total_time: u64 = 0;
+    for (i, observation) in observations.iter().enumerate() {
+        let end = match observations.get(i + 1) {
+            Some(next) => next.time,
+            None => now
+        };
+        let elapsed = end.saturating_sub(observation.time);
+        weighted_sum += Uint256::from(observation.price) * Uint256::from(elapsed);
+        total_time += elapsed;
+    }
+    if total_time == 0 {
+        // every observation happened at the current block
+        let sum = observations.iter().fold(Uint128::zero(), |acc, o| acc + o.price);
+        return Ok(sum / Uint128::from(observations.len() as u128));
+    }
+    let twap = weighted_sum / Uint256::from(total_time);
+    Uint128::try_from(twap).map_err(|e| StdError::generic_err(e.to_string()))
+}
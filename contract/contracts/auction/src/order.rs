@@ -0,0 +1,246 @@
+use cosmwasm_std::{Addr, Binary, Coin, Deps, DepsMut, Env, MessageInfo, Response, StdResult, Uint128, to_vec};
+use sha2::{Digest, Sha256};
+use terraswap::asset::{Asset, AssetInfo};
+use marketplace::auction::{AuctionType, SignedOrder, OrderSide, ActivityKind};
+
+use crate::error::ContractError;
+use crate::state::{CONFIG, STATE, Auction, auctions, ORDER_PUBKEYS, ORDER_NONCES, CLOSED_ORDERS, ORDER_DEPOSITS};
+use crate::auction::finalize_sale;
+use crate::querier::{query_nft_approved, load_royalty};
+use crate::stats::on_listing_created;
+use crate::activity::record_activity;
+
+pub fn order_digest(
+    order: &SignedOrder
+) -> StdResult<Vec<u8>> {
+    Ok(Sha256::digest(&to_vec(order)?).to_vec())
+}
+
+pub fn register_pubkey(
+    deps: DepsMut,
+    info: MessageInfo,
+    pubkey: Binary
+) -> Result<Response, ContractError> {
+    // compressed or uncompressed secp256k1 key
+    if pubkey.len() != 33 && pubkey.len() != 65 {
+        return Err(ContractError::InvalidOrder("invalid pubkey".to_string()));
+    }
+    ORDER_PUBKEYS.save(deps.storage, &info.sender, &pubkey)?;
+    Ok(Response::new()
+        .add_attribute("action", "register_pubkey")
+        .add_attribute("address", info.sender)
+    )
+}
+
+pub fn increment_nonce(
+    deps: DepsMut,
+    info: MessageInfo
+) -> Result<Response, ContractError> {
+    let nonce = ORDER_NONCES.may_load(deps.storage, &info.sender)?.unwrap_or(0) + 1;
+    ORDER_NONCES.save(deps.storage, &info.sender, &nonce)?;
+    Ok(Response::new()
+        .add_attribute("action", "increment_nonce")
+        .add_attribute("address", info.sender)
+        .add_attribute("nonce", nonce.to_string())
+    )
+}
+
+pub fn cancel_order(
+    deps: DepsMut,
+    info: MessageInfo,
+    order: SignedOrder
+) -> Result<Response, ContractError> {
+    if deps.api.addr_validate(&order.signer)? != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    let digest = order_digest(&order)?;
+    CLOSED_ORDERS.save(deps.storage, &digest, &true)?;
+    Ok(Response::new()
+        .add_attribute("action", "cancel_order")
+        .add_attribute("digest", Binary::from(digest).to_base64())
+    )
+}
+
+pub fn deposit_order_funds(
+    deps: DepsMut,
+    info: MessageInfo
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if info.funds.is_empty() {
+        return Err(ContractError::InvalidAmount("no fund is sent".to_string()));
+    }
+    for coin in info.funds.iter() {
+        if !config.accepted_denom.contains(&coin.denom) {
+            return Err(ContractError::UnsupportedAsset {});
+        }
+        ORDER_DEPOSITS.update(deps.storage, (&info.sender, &coin.denom), |x| -> StdResult<Uint128> {
+            Ok(x.unwrap_or_default() + coin.amount)
+        })?;
+    }
+    Ok(Response::new()
+        .add_attribute("action", "deposit_order_funds")
+        .add_attribute("address", info.sender)
+    )
+}
+
+pub fn withdraw_order_funds(
+    deps: DepsMut,
+    info: MessageInfo,
+    denom: String,
+    amount: Uint128
+) -> Result<Response, ContractError> {
+    let deposit = ORDER_DEPOSITS.may_load(deps.storage, (&info.sender, &denom))?.unwrap_or_default();
+    if amount.is_zero() || amount > deposit {
+        return Err(ContractError::InvalidAmount("insufficient deposit".to_string()));
+    }
+    ORDER_DEPOSITS.save(deps.storage, (&info.sender, &denom), &(deposit - amount))?;
+    let asset = Asset {
+        info: AssetInfo::NativeToken {
+            denom: denom.clone()
+        },
+        amount: amount
+    };
+    Ok(Response::new()
+        .add_message(asset.into_msg(info.sender.clone())?)
+        .add_attribute("action", "withdraw_order_funds")
+        .add_attribute("address", info.sender)
+        .add_attribute("denom", denom)
+        .add_attribute("amount", amount)
+    )
+}
+
+// signer and every field of the order that doesn't depend on the side
+fn validate_order(
+    deps: Deps,
+    env: &Env,
+    order: &SignedOrder,
+    signature: &Binary,
+    digest: &[u8]
+) -> Result<Addr, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let signer = deps.api.addr_validate(&order.signer)?;
+    if order.chain_id != env.block.chain_id || order.contract_address != env.contract.address.as_str() {
+        return Err(ContractError::InvalidOrder("order is for another contract".to_string()));
+    }
+    if order.expiry <= env.block.time.seconds() {
+        return Err(ContractError::InvalidOrder("order is expired".to_string()));
+    }
+    if order.nonce != ORDER_NONCES.may_load(deps.storage, &signer)?.unwrap_or(0) {
+        return Err(ContractError::InvalidOrder("invalid nonce".to_string()));
+    }
+    if CLOSED_ORDERS.has(deps.storage, digest) {
+        return Err(ContractError::InvalidOrder("order is closed".to_string()));
+    }
+    if !config.accepted_denom.contains(&order.denom) {
+        return Err(ContractError::UnsupportedAsset {});
+    }
+    if order.price < config.min_reserve_price {
+        return Err(ContractError::InvalidAmount("price too low".to_string()));
+    }
+    let pubkey = match ORDER_PUBKEYS.may_load(deps.storage, &signer)? {
+        Some(v) => v,
+        None => return Err(ContractError::InvalidOrder("pubkey is not registered".to_string()))
+    };
+    let verified = deps.api.secp256k1_verify(digest, signature, &pubkey)
+        .map_err(|e| ContractError::InvalidOrder(e.to_string()))?;
+    if !verified {
+        return Err(ContractError::InvalidOrder("invalid signature".to_string()));
+    }
+    Ok(signer)
+}
+
+pub fn fulfill_order(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    order: SignedOrder,
+    signature: Binary
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let mut state = STATE.load(deps.storage)?;
+    if state.is_freeze {
+        return Err(ContractError::AuctionFreeze {});
+    }
+    let digest = order_digest(&order)?;
+    let signer = validate_order(deps.as_ref(), &env, &order, &signature, &digest)?;
+    if signer == info.sender {
+        return Err(ContractError::InvalidOrder("signer can't fill own order".to_string()));
+    }
+    let nft_contract = deps.api.addr_validate(&order.nft_contract)?;
+    let (seller, buyer) = match order.side {
+        OrderSide::Ask => {
+            // buyer pays the exact price with the order
+            if info.funds != vec![Coin { denom: order.denom.clone(), amount: order.price }] {
+                return Err(ContractError::InvalidAmount("fund must equal the price".to_string()));
+            }
+            (signer, info.sender.clone())
+        },
+        OrderSide::Bid => {
+            if !info.funds.is_empty() {
+                return Err(ContractError::InvalidAmount("bid order is paid from deposit".to_string()));
+            }
+            let deposit = ORDER_DEPOSITS.may_load(deps.storage, (&signer, &order.denom))?.unwrap_or_default();
+            if deposit < order.price {
+                return Err(ContractError::InvalidAmount("insufficient deposit".to_string()));
+            }
+            ORDER_DEPOSITS.save(deps.storage, (&signer, &order.denom), &(deposit - order.price))?;
+            (info.sender.clone(), signer)
+        }
+    };
+    // nft is transferred from the seller by this contract as approved operator
+    if !query_nft_approved(deps.as_ref(), &env, &nft_contract, &order.token_id, &seller)? {
+        return Err(ContractError::InvalidAsset("nft is not approved by its owner".to_string()));
+    }
+    CLOSED_ORDERS.save(deps.storage, &digest, &true)?;
+
+    // the fill is recorded as a buy now sale so it shares fees, sales history and stats with listings
+    let royalty = load_royalty(deps.as_ref(), &nft_contract, &order.token_id)?;
+    let mut auction = Auction {
+        auction_id: state.next_auction_id,
+        nft_contract: nft_contract.clone(),
+        token_id: order.token_id.clone(),
+        auction_type: AuctionType::BuyNow,
+        seller: seller.clone(),
+        duration: 0,
+        extension_duration: 0,
+        denom: order.denom.clone(),
+        reserve_price: order.price,
+        end_time: env.block.time.seconds(),
+        bidder: Some(buyer.clone()),
+        amount: order.price,
+        creator_address: royalty.clone().map(|x| x.creator),
+        royalty_fee: royalty.clone().map(|x| x.royalty_fee).unwrap_or_default(),
+        royalty_splits: royalty.map(|x| x.splits).unwrap_or_default(),
+        protocol_fee: config.protocol_fee,
+        is_settled: false,
+        is_approval_listing: true,
+        expires_at: None,
+        recipient: None,
+        refund_to: None,
+        send_msg: None,
+        listing_referrer: None,
+        bid_referrer: None
+    };
+    state.next_auction_id += Uint128::from(1u128);
+    STATE.save(deps.storage, &state)?;
+    auctions().save(deps.storage, auction.auction_id.u128(), &auction)?;
+    on_listing_created(deps.storage, &auction)?;
+    let (messages, sale, _) = finalize_sale(deps.branch(), &env, None, &mut auction)?;
+    if order.side == OrderSide::Bid {
+        record_activity(deps.storage, &buyer, ActivityKind::OfferAccepted, &auction, sale.price, Some(seller.clone()), sale.time)?;
+    }
+
+    Ok(Response::new()
+        .add_submessages(messages)
+        .add_attribute("action", "fulfill_order")
+        .add_attribute("digest", Binary::from(digest).to_base64())
+        .add_attribute("auction_id", auction.auction_id)
+        .add_attribute("nft_contract", auction.nft_contract)
+        .add_attribute("token_id", auction.token_id)
+        .add_attribute("seller", seller)
+        .add_attribute("buyer", buyer)
+        .add_attribute("denom", auction.denom)
+        .add_attribute("amount", auction.amount)
+        .add_attribute("sale_id", sale.sale_id.to_string())
+    )
+}
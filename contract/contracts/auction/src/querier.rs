@@ -1,16 +1,37 @@
 use cosmwasm_std::{
-    Deps, Addr, StdResult, Uint128,  Order, QueryRequest, WasmQuery, to_binary
+    Deps, Env, Addr, StdResult, StdError, Uint128,  Order, QueryRequest, WasmQuery, to_binary, from_binary, Binary, Coin,
+    ContractInfoResponse
 };
 use cw_storage_plus::Bound;
 use cw_utils::maybe_addr;
-use cw721::{Cw721QueryMsg, OwnerOfResponse};
-use marketplace::auction::{ConfigResponse, StateResponse, AuctionResponse, CalculatePriceResponse, RoyaltyFeeResponse, RoyaltyResponse, RoyaltyAdminResponse, AllRoyaltyResponse, AllRoyaltyListResponse, Royalty, Bid, BidHistoryByAuctionIdResponse,  AuctionListResponse ,BidCountResponse };
+use cw721::{Cw721QueryMsg, OwnerOfResponse, OperatorsResponse};
+use marketplace::auction::{ConfigResponse, StateResponse, AuctionResponse, CalculatePriceResponse, RoyaltyFeeResponse, RoyaltyResponse, RoyaltyAdminResponse, AllRoyaltyResponse, AllRoyaltyListResponse, Royalty, Bid, BidHistoryByAuctionIdResponse,  AuctionListResponse ,BidCountResponse, AuctionType,
+    PendingConfigChangeResponse, Sale, SalesResponse, CollectionStatsResponse, CollectionVolumeResponse,
+    TopCollectionsResponse, OracleConfigResponse, PriceObservationResponse, AuctionFilter, AuctionSortBy, AuctionStatus,
+    SearchAuctionsResponse, Activity, ActivityKind, ActivityResponse, BidStatus, BidStatusResponse, BidsByBidderResponse,
+    KeeperRewardResponse, MaxBatchSizeResponse, SignedOrder, OrderAccountResponse, OrderDigestResponse,
+    ReferralTier, ReferralTiersResponse, ReferralStatsResponse, ReferrerResponse, ReferrersResponse, FeeDistributionResponse,
+    AccruedFeesResponse, RoyaltyShare, RoyaltySplit, RoyaltyPayout, RoyaltyRegistryResponse, FailedSettlementResponse,
+    FailedSettlementsResponse };
+use marketplace::royalty_registry::QueryMsg as RoyaltyRegistryQueryMsg;
+use marketplace::royalty::query_nft_minter;
 
-use crate::state::{ CONFIG, STATE, AUCTIONS, ROYALTIES, ROYALTY_ADMINS, 
-    NFT_AUCTION_MAPS, Auction, BID_HISTORY_BY_AUCTION_ID, AUCTION_ID_BY_SELLER,
-    AUCTION_ID_BY_AMOUNT, AUCTION_ID_BY_ENDTIME, BID_COUNT_BY_AUCTION_ID,
-    NOT_STARTED_AUCTION, AUCTION_ID_BY_BIDDER
+use crate::state::{ CONFIG, STATE, auctions, ROYALTIES, ROYALTY_ADMINS,
+    Auction, is_listing_expired, bid_history, BID_COUNT_BY_AUCTION_ID, PENDING_CONFIG_CHANGE,
+    AUCTION_PHASE_OPEN, AUCTION_PHASE_SETTLED, FLOOR_LISTED, ACTIVITIES, KEEPER_REWARD,
+    SALES, SALE_ID_BY_TOKEN, SALE_ID_BY_COLLECTION, SALE_ID_BY_SELLER, SALE_ID_BY_BUYER,
+    COLLECTION_STATS, COLLECTION_VOLUME_BY_HOUR, COLLECTION_BY_VOLUME, PRICE_OBSERVATIONS,
+    PriceObservation, ORDER_PUBKEYS, ORDER_NONCES, CLOSED_ORDERS, ORDER_DEPOSITS,
+    ReferrerInfo, REFERRERS, REFERRAL_TIERS, REFERRAL_STATS, FEE_DISTRIBUTION, ACCRUED_FEES, ROYALTY_REGISTRY,
+    PRIMARY_PROTOCOL_FEE, FAILED_SETTLEMENTS
  };
+use crate::stats::SECONDS_PER_HOUR;
+use crate::auction::{calculate_keeper_reward, load_max_batch_size, royalty_payouts, retry_time};
+use crate::order::order_digest;
+use crate::referral::referrer_fee_share;
+use crate::oracle::{load_oracle_config, median, is_outlier, time_weighted_price};
+use crate::search::{SearchCursor, SearchRange, search_auctions, matches_filter, MAX_SEARCH_SCAN};
+use crate::activity::MAX_ACTIVITY_SCAN;
 use std::marker::PhantomData;
 
 const DEFAULT_LIMIT: u32 = 10;
@@ -29,7 +50,18 @@ pub fn query_config(
         min_increment: config.min_increment,
         extension_duration: config.extension_duration,
         collector_address: config.collector_address.to_string(),
-        max_royalty_fee: config.max_royalty_fee
+        max_royalty_fee: config.max_royalty_fee,
+        config_timelock: config.config_timelock,
+        primary_protocol_fee: PRIMARY_PROTOCOL_FEE.may_load(deps.storage)?
+    })
+}
+
+pub fn query_pending_config_change(
+    deps: Deps,
+) -> StdResult<PendingConfigChangeResponse> {
+    let pending = PENDING_CONFIG_CHANGE.may_load(deps.storage)?;
+    Ok(PendingConfigChangeResponse {
+        pending: pending
     })
 }
 
@@ -59,11 +91,154 @@ pub fn query_nft_owner(
     Ok(deps.api.addr_validate(&owner_response.owner)?)
 }
 
+// seller is the royalty creator or the minter, or the token has not been sold here yet
+pub fn query_primary_sale(
+    deps: Deps,
+    auction: &Auction
+) -> StdResult<bool> {
+    if auction.creator_address.as_ref() == Some(&auction.seller) {
+        return Ok(true);
+    }
+    let is_sold = SALE_ID_BY_TOKEN
+        .prefix((&auction.nft_contract, auction.token_id.clone()))
+        .keys(deps.storage, None, None, Order::Ascending)
+        .next()
+        .is_some();
+    if !is_sold {
+        return Ok(true);
+    }
+    Ok(query_nft_minter(&deps.querier, &auction.nft_contract)?.as_deref() == Some(auction.seller.as_str()))
+}
+
+// royalty of a token from the royalty registry if set, otherwise from the royalties of this contract
+pub fn load_royalty(
+    deps: Deps,
+    nft_contract: &Addr,
+    token_id: &str
+) -> StdResult<Option<Royalty>> {
+    match ROYALTY_REGISTRY.may_load(deps.storage)? {
+        Some(registry) => query_registry_royalty(deps, &registry, nft_contract, Some(token_id.to_string())),
+        None => ROYALTIES.may_load(deps.storage, nft_contract)
+    }
+}
+
+// none token id is the collection royalty of the registry
+fn query_registry_royalty(
+    deps: Deps,
+    registry: &Addr,
+    nft_contract: &Addr,
+    token_id: Option<String>
+) -> StdResult<Option<Royalty>> {
+    let res: RoyaltyFeeResponse = deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: registry.to_string(),
+        msg: to_binary(&RoyaltyRegistryQueryMsg::RoyaltyFee {
+            contract_addr: nft_contract.to_string(),
+            token_id: token_id
+        })?
+    }))?;
+    res.royalty_fee.map(|x| capped_royalty(deps, x)).transpose()
+}
+
+// the registry can't raise a royalty above the max royalty fee of this contract, splits are scaled down with it
+fn capped_royalty(
+    deps: Deps,
+    royalty: RoyaltyResponse
+) -> StdResult<Royalty> {
+    let config = CONFIG.load(deps.storage)?;
+    let royalty_fee = royalty.royalty_fee.min(config.max_royalty_fee);
+    let mut splits: Vec<RoyaltyShare> = vec![];
+    for split in royalty.splits {
+        let share = if royalty_fee < royalty.royalty_fee {
+            split.share * (royalty_fee / royalty.royalty_fee)
+        } else {
+            split.share
+        };
+        splits.push(RoyaltyShare {
+            recipient: deps.api.addr_validate(&split.recipient)?,
+            share: share
+        });
+    }
+    Ok(Royalty {
+        royalty_fee: royalty_fee,
+        creator: deps.api.addr_validate(&royalty.creator)?,
+        splits: splits
+    })
+}
+
+pub fn query_royalty_registry(
+    deps: Deps
+) -> StdResult<RoyaltyRegistryResponse> {
+    Ok(RoyaltyRegistryResponse {
+        address: ROYALTY_REGISTRY.may_load(deps.storage)?.map(|x| x.to_string())
+    })
+}
+
+// listing is valid until it expires, approval listing also needs the seller to own the nft and approve this contract
+pub fn query_listing_valid(
+    deps: Deps,
+    env: &Env,
+    auction: &Auction
+) -> StdResult<bool> {
+    if is_listing_expired(auction, env.block.time.seconds()) {
+        return Ok(false);
+    }
+    if !auction.is_approval_listing {
+        return Ok(true);
+    }
+    query_nft_approved(deps, env, &auction.nft_contract, &auction.token_id, &auction.seller)
+}
+
+// accounts have no contract info
+pub fn query_is_contract(
+    deps: Deps,
+    address: &Addr
+) -> bool {
+    let contract_info: StdResult<ContractInfoResponse> = deps.querier.query(&QueryRequest::Wasm(WasmQuery::ContractInfo {
+        contract_addr: address.to_string()
+    }));
+    contract_info.is_ok()
+}
+
+// owner holds the nft and this contract can transfer it, by token approval or approve all
+pub fn query_nft_approved(
+    deps: Deps,
+    env: &Env,
+    nft_contract: &Addr,
+    token_id: &str,
+    owner: &Addr
+) -> StdResult<bool> {
+    let owner_response: OwnerOfResponse =
+      deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+          contract_addr: nft_contract.to_string(),
+          msg: to_binary(&Cw721QueryMsg::OwnerOf {
+              token_id: token_id.to_string(),
+              include_expired: Some(false)
+          })?,
+      }))?;
+    if owner_response.owner != owner.as_str() {
+        return Ok(false);
+    }
+    if owner_response.approvals.iter().any(|x| x.spender == env.contract.address.as_str()) {
+        return Ok(true);
+    }
+    let operators_response: OperatorsResponse =
+      deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+          contract_addr: nft_contract.to_string(),
+          msg: to_binary(&Cw721QueryMsg::AllOperators {
+              owner: owner.to_string(),
+              include_expired: Some(false),
+              start_after: None,
+              limit: Some(MAX_LIMIT)
+          })?,
+      }))?;
+    Ok(operators_response.operators.iter().any(|x| x.spender == env.contract.address.as_str()))
+}
+
 pub fn query_auction(
     deps: Deps,
     auction_id: Uint128
 ) -> StdResult<AuctionResponse> {
-    let auction = AUCTIONS.load(deps.storage, auction_id.u128())?;
+    let auction = auctions().load(deps.storage, auction_id.u128())?;
     _query_auction(auction)
 }
 
@@ -93,7 +268,14 @@ fn _query_auction(
         amount: auction.amount,
         is_settled: auction.is_settled,
         creator_address: creator_address,
-        royalty_fee: auction.royalty_fee
+        royalty_fee: auction.royalty_fee,
+        is_approval_listing: auction.is_approval_listing,
+        expires_at: auction.expires_at,
+        recipient: auction.recipient.map(|x| x.to_string()),
+        refund_to: auction.refund_to.map(|x| x.to_string()),
+        send_msg: auction.send_msg,
+        listing_referrer: auction.listing_referrer.map(|x| x.to_string()),
+        bid_referrer: auction.bid_referrer.map(|x| x.to_string())
     })
 }
 
@@ -102,11 +284,15 @@ pub fn query_royalty_fee(
     contract_addr: String
 ) -> StdResult<RoyaltyFeeResponse> {
     let contract_addr_raw = deps.api.addr_validate(&contract_addr)?;
-    let royalty = ROYALTIES.may_load(deps.storage, &contract_addr_raw)?;
+    let royalty = match ROYALTY_REGISTRY.may_load(deps.storage)? {
+        Some(registry) => query_registry_royalty(deps, &registry, &contract_addr_raw, None)?,
+        None => ROYALTIES.may_load(deps.storage, &contract_addr_raw)?
+    };
     let royalty_response: Option<RoyaltyResponse> = match royalty {
         Some(royal) => Some(RoyaltyResponse {
             royalty_fee: royal.royalty_fee,
-            creator: royal.creator.to_string()
+            creator: royal.creator.to_string(),
+            splits: royalty_splits(&royal.splits)
         }),
         None => None
     };
@@ -121,72 +307,89 @@ pub fn query_nft_auction_map(
     token_id: String
 ) -> StdResult<AuctionResponse> {
     let nft_contract_addr = deps.api.addr_validate(&nft_contract)?;
-    let auction_id = NFT_AUCTION_MAPS.load(deps.storage, (&nft_contract_addr, token_id.clone()))?;
-    let auction = AUCTIONS.load(deps.storage, auction_id)?;
-    _query_auction(auction)
+    let auction = auctions().idx.nft_token
+        .prefix((nft_contract_addr, token_id, AUCTION_PHASE_OPEN))
+        .range(deps.storage, None, None, Order::Ascending)
+        .next();
+    match auction {
+        Some(v) => _query_auction(v?.1),
+        None => Err(StdError::not_found("auction"))
+    }
 }
 
 pub fn query_auction_by_nft(
     deps: Deps,
     nft_contract: String,
     limit: Option<u32>
-) -> StdResult<Vec<u128>> {
+) -> StdResult<AuctionListResponse> {
     let nft_contract_addr = deps.api.addr_validate(&nft_contract)?;
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
-    let auction_ids = NFT_AUCTION_MAPS
-        .prefix(&nft_contract_addr)
+    let auctions = auctions().idx.collection
+        .prefix((nft_contract_addr, AUCTION_PHASE_OPEN))
         .range(deps.storage, None, None, Order::Ascending)
-        .take(limit)
-        .map(|x| {
-            let (_, auction_id) = x.unwrap();
-            auction_id
-        })
-        .collect::<Vec<u128>>();
-    return  Ok(auction_ids);
+        .take(limit);
+    construct_auction_response(auctions)
 }
 
 pub fn query_auction_by_seller(
     deps: Deps,
     seller: String,
     limit: Option<u32>
-) -> StdResult<Vec<u128>> {
+) -> StdResult<AuctionListResponse> {
     let seller_addr = deps.api.addr_validate(&seller)?;
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
-    let auction_ids = AUCTION_ID_BY_SELLER
-        .prefix(&seller_addr)
+    let auctions = auctions().idx.seller
+        .prefix((seller_addr, AUCTION_PHASE_OPEN))
         .range(deps.storage, None, None, Order::Ascending)
-        .take(limit)
-        .map(|x| {
-            let (auction_id, _) = x.unwrap();
-            auction_id
-        }).collect::<Vec<u128>>();
-
-    return  Ok(auction_ids);
+        .take(limit);
+    construct_auction_response(auctions)
 }
 
+// auctions with bids ending after end_time
 pub fn query_auction_by_end_time(
     deps: Deps,
     nft_contract: String,
     end_time: u64,
     limit: Option<u32>,
     is_desc: Option<bool>
-)  -> StdResult<Vec<u128>> {
+)  -> StdResult<AuctionListResponse> {
     let nft_addr = deps.api.addr_validate(&nft_contract)?;
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
-    let mut order = Order::Ascending;
-    if (is_desc.unwrap_or(false)){
-        order = Order::Descending;
-    }
-    let auction_ids = AUCTION_ID_BY_ENDTIME
-        .sub_prefix(&nft_addr)
-        .range(deps.storage, Some(Bound::exclusive((end_time, 0))), None, order)
-        .take(limit)
-        .map(|x| {
-            let ((_, auction_id), _) = x.unwrap();
-            auction_id
-        }).collect::<Vec<u128>>();
+    let order = sort_order(is_desc);
+    let auctions = auctions().idx.collection_end_time
+        .sub_prefix((nft_addr, AUCTION_PHASE_OPEN))
+        .range(deps.storage, Some(Bound::exclusive((end_time, u128::MAX))), None, order)
+        .filter(|x| match x {
+            Ok((_, auction)) => auction.auction_type == AuctionType::Auction,
+            Err(_) => true
+        })
+        .take(limit);
+    construct_auction_response(auctions)
+}
 
-    return  Ok(auction_ids);
+pub fn query_settleable_auctions(
+    deps: Deps,
+    env: Env,
+    start_after: Option<Uint128>,
+    limit: Option<u32>
+) -> StdResult<AuctionListResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    // auctions are ordered by end time, so start_after is located by its end time
+    let start_after = match start_after {
+        Some(auction_id) => Some(auctions().load(deps.storage, auction_id.u128())?),
+        None => None
+    };
+    let min = match start_after {
+        Some(auction) if auction.end_time > 0 => Bound::exclusive((auction.end_time, auction.auction_id.u128())),
+        // end time is 0 until the first bid
+        _ => Bound::inclusive((1, 0))
+    };
+    let max = Bound::inclusive((env.block.time.seconds(), u128::MAX));
+    let auctions = auctions().idx.end_time
+        .sub_prefix(AUCTION_PHASE_OPEN)
+        .range(deps.storage, Some(min), Some(max), Order::Ascending)
+        .take(limit);
+    construct_auction_response(auctions)
 }
 
 pub fn query_not_started_auctions(
@@ -195,23 +398,22 @@ pub fn query_not_started_auctions(
     start_after: Option<u128>,
     limit: Option<u32>,
     is_desc: Option<bool>
-)  -> StdResult<Vec<u128>> {
+)  -> StdResult<AuctionListResponse> {
     let nft_addr = deps.api.addr_validate(&nft_contract)?;
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
-    let mut order = Order::Ascending;
-    if (is_desc.unwrap_or(false)){
-        order = Order::Descending;
-    }
-    let start_after =  start_after.unwrap_or(0u128);
-    let auction_ids = NOT_STARTED_AUCTION
-        .prefix(&nft_addr)
-        .range(deps.storage, Some(Bound::exclusive((start_after))), None, order)
-        .take(limit)
-        .map(|x| {
-            let (auction_id, _) = x.unwrap();
-            auction_id
-        }).collect::<Vec<u128>>();
-    return Ok(auction_ids);
+    let order = sort_order(is_desc);
+    // start_after is the lower bound when ascending and the upper bound when descending
+    let (min, max) = match (start_after, order) {
+        (Some(v), Order::Ascending) => (Some(Bound::exclusive(v)), None),
+        (Some(v), Order::Descending) => (None, Some(Bound::exclusive(v))),
+        (None, _) => (None, None)
+    };
+    // not started auctions have no end time yet
+    let auctions = auctions().idx.collection_end_time
+        .prefix((nft_addr, AUCTION_PHASE_OPEN, 0))
+        .range(deps.storage, min, max, order)
+        .take(limit);
+    construct_auction_response(auctions)
 }
 
 pub fn query_auction_by_bidder(
@@ -219,63 +421,113 @@ pub fn query_auction_by_bidder(
     bidder: String,
     start_after: Option <u128>,
     limit: Option<u32>,
-) -> StdResult<Vec<u128>>{
+) -> StdResult<AuctionListResponse> {
     let bidder = deps.api.addr_validate(&bidder)?;
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
-    let start_after =  start_after.unwrap_or(0u128);
-    let auction_ids = AUCTION_ID_BY_BIDDER
-        .prefix(&bidder)
-        .range(deps.storage, Some(Bound::exclusive(start_after)), None, Order::Ascending)
-        .take(limit)
-        .map(|x| {
-            let (auction_id, _) = x.unwrap();
-            auction_id
-        }).collect::<Vec<u128>>();
-    return Ok(auction_ids);
+    let min = start_after.map(Bound::exclusive);
+    let auctions = auctions().idx.bidder
+        .prefix((bidder, AUCTION_PHASE_OPEN))
+        .range(deps.storage, min, None, Order::Ascending)
+        .take(limit);
+    construct_auction_response(auctions)
 }
 
+// auctions with bids above amount
 pub fn query_auction_by_amount(
     deps: Deps,
     nft_contract: String,
     amount: Uint128,
     limit: Option<u32>
-)  -> StdResult<Vec<u128>> {
+)  -> StdResult<AuctionListResponse> {
     let nft_addr = deps.api.addr_validate(&nft_contract)?;
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
-    let auction_ids = AUCTION_ID_BY_AMOUNT
-        .sub_prefix(&nft_addr)
-        .range(deps.storage, Some(Bound::exclusive((amount.u128(), 0))), None, Order::Ascending)
-        .take(limit)
-        .map(|x| {
-            let ((_, auction_id), _) = x.unwrap();
-            auction_id
-        }).collect::<Vec<u128>>();
-
-    return  Ok(auction_ids);
+    let auctions = auctions().idx.collection_price
+        .sub_prefix((nft_addr, AUCTION_PHASE_OPEN))
+        .range(deps.storage, Some(Bound::exclusive((amount.u128(), u128::MAX))), None, Order::Ascending)
+        .filter(|x| match x {
+            Ok((_, auction)) => auction.auction_type == AuctionType::Auction && auction.end_time > 0,
+            Err(_) => true
+        })
+        .take(limit);
+    construct_auction_response(auctions)
 }
 
 pub fn query_calculate_price(
     deps: Deps,
+    env: Env,
     nft_contract: String,
     token_id: String,
     amount: Uint128
 ) -> StdResult<CalculatePriceResponse> {
     let config = CONFIG.load(deps.storage)?;
     let nft_contract_addr = deps.api.addr_validate(&nft_contract)?;
-    let royalty = ROYALTIES.may_load(deps.storage, &nft_contract_addr)?;
-    let royalty_amount = match royalty {
-        Some(royal) => royal.royalty_fee * amount,
-        None => Uint128::zero()
+    let open_auction = match auctions().idx.nft_token
+        .prefix((nft_contract_addr.clone(), token_id.clone(), AUCTION_PHASE_OPEN))
+        .range(deps.storage, None, None, Order::Ascending)
+        .next() {
+        Some(v) => Some(v?.1),
+        None => None
+    };
+    // primary sales are known for listed tokens only
+    let primary_protocol_fee = match (PRIMARY_PROTOCOL_FEE.may_load(deps.storage)?, &open_auction) {
+        (Some(fee), Some(auction)) if query_primary_sale(deps, auction)? => Some(fee),
+        _ => None
     };
-    let protocol_amount = config.protocol_fee * amount;
-    let seller_amount = amount - (protocol_amount + royalty_amount);
+    let royalty = match primary_protocol_fee {
+        Some(_) => None,
+        None => load_royalty(deps, &nft_contract_addr, &token_id)?
+    };
+    let (royalty_amount, royalty_payouts) = match royalty {
+        Some(royal) => {
+            let royalty_amount = royal.royalty_fee * amount;
+            let payouts = royalty_payouts(&royal.creator, &royal.splits, amount, royalty_amount)
+                .into_iter()
+                .map(|(recipient, amount)| RoyaltyPayout { recipient: recipient.to_string(), amount: amount })
+                .collect();
+            (royalty_amount, payouts)
+        },
+        None => (Uint128::zero(), vec![])
+    };
+    let protocol_amount = primary_protocol_fee.unwrap_or(config.protocol_fee) * amount;
+    // royalties set before a fee change can take more than the sale price with the new fee
+    let seller_amount = amount.checked_sub(protocol_amount + royalty_amount)
+        .map_err(|_| StdError::generic_err("fees are more than sale price"))?;
+    // flat keeper reward depends on the denom of the listed auction, the delay on its end time
+    let (denom, end_time) = open_auction.map(|x| (x.denom, x.end_time)).unwrap_or_default();
+    let keeper_reward = calculate_keeper_reward(deps.storage, &denom, protocol_amount, end_time, env.block.time.seconds())?;
     Ok(CalculatePriceResponse {
         nft_contract,
         token_id,
         amount,
         seller_amount,
         protocol_fee: protocol_amount,
-        royalty_fee: royalty_amount
+        royalty_fee: royalty_amount,
+        royalty_payouts: royalty_payouts,
+        keeper_reward: keeper_reward,
+        is_primary_sale: primary_protocol_fee.is_some()
+    })
+}
+
+pub fn query_max_batch_size(
+    deps: Deps
+) -> StdResult<MaxBatchSizeResponse> {
+    Ok(MaxBatchSizeResponse {
+        max_batch_size: load_max_batch_size(deps.storage)?
+    })
+}
+
+pub fn query_keeper_reward(
+    deps: Deps
+) -> StdResult<KeeperRewardResponse> {
+    Ok(match KEEPER_REWARD.may_load(deps.storage)? {
+        Some(keeper) => KeeperRewardResponse {
+            reward: Some(keeper.reward),
+            min_delay: keeper.min_delay
+        },
+        None => KeeperRewardResponse {
+            reward: None,
+            min_delay: 0
+        }
     })
 }
 
@@ -283,19 +535,76 @@ pub fn query_calculate_price(
 pub fn query_bid_history_by_auction_id(
     deps: Deps,
     auction_id: Uint128,
-    limit: Option<u32>
+    start_after: Option<Uint128>,
+    limit: Option<u32>,
+    is_desc: Option<bool>
 ) -> StdResult<BidHistoryByAuctionIdResponse> {
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
-    let bid_history = BID_HISTORY_BY_AUCTION_ID
+    let order = sort_order(is_desc);
+    // start_after is the lower bound when ascending and the upper bound when descending
+    let (min, max) = match (start_after, order) {
+        (Some(v), Order::Ascending) => (Some(Bound::exclusive(v.u128())), None),
+        (Some(v), Order::Descending) => (None, Some(Bound::exclusive(v.u128()))),
+        (None, _) => (None, None)
+    };
+    let bids = bid_history()
         .prefix(auction_id.u128())
-        .range(deps.storage, None, None, Order::Ascending)
+        .range(deps.storage, min, max, order)
+        .take(limit)
+        .map(|x| x.map(|(_, bid)| bid))
+        .collect::<StdResult<Vec<Bid>>>()?;
+    Ok(BidHistoryByAuctionIdResponse{ bids: bids })
+}
+
+pub fn query_bids_by_bidder(
+    deps: Deps,
+    bidder: String,
+    start_after: Option<(Uint128, Uint128)>,
+    limit: Option<u32>
+) -> StdResult<BidsByBidderResponse> {
+    let bidder_addr = deps.api.addr_validate(&bidder)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let min = start_after.map(|(auction_id, bid_number)| Bound::exclusive((auction_id.u128(), bid_number.u128())));
+    let bids = bid_history().idx.bidder
+        .prefix(bidder_addr)
+        .range(deps.storage, min, None, Order::Ascending)
         .take(limit)
         .map(|x| {
-            let (_, bid) = x.unwrap(); 
-            bid
+            let ((auction_id, bid_number), bid) = x?;
+            let status = bid_status(deps, auction_id, bid_number, &bid)?;
+            Ok(BidStatusResponse {
+                bid_number: Uint128::from(bid_number),
+                bid: bid,
+                status: status
+            })
         })
-        .collect::<Vec<Bid>>();
-    Ok(BidHistoryByAuctionIdResponse{ bids: bid_history })
+        .collect::<StdResult<Vec<BidStatusResponse>>>()?;
+    Ok(BidsByBidderResponse {
+        bids: bids
+    })
+}
+
+// only the last bid of an auction can lead or win, earlier bids were refunded when outbid
+fn bid_status(
+    deps: Deps,
+    auction_id: u128,
+    bid_number: u128,
+    bid: &Bid
+) -> StdResult<BidStatus> {
+    // cancelled auctions are removed and the leading bid is refunded
+    let auction = match auctions().may_load(deps.storage, auction_id)? {
+        Some(v) => v,
+        None => return Ok(BidStatus::Refunded)
+    };
+    let bid_count = BID_COUNT_BY_AUCTION_ID.may_load(deps.storage, auction_id)?.unwrap_or_default();
+    if bid_number != bid_count.u128() || auction.bidder.as_ref() != Some(&bid.bidder) {
+        return Ok(BidStatus::Outbid);
+    }
+    if auction.is_settled {
+        Ok(BidStatus::Won)
+    } else {
+        Ok(BidStatus::Leading)
+    }
 }
 
 pub fn query_bid_number(
@@ -312,6 +621,27 @@ pub fn query_all_royalty(
     start_after: Option<String>,
     limit: Option<u32>
 ) -> StdResult<AllRoyaltyListResponse> {
+    // collection royalties of the registry, capped like the ones used for sales
+    if let Some(registry) = ROYALTY_REGISTRY.may_load(deps.storage)? {
+        let res: AllRoyaltyListResponse = deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: registry.to_string(),
+            msg: to_binary(&RoyaltyRegistryQueryMsg::AllRoyaltyFee {
+                start_after: start_after,
+                limit: limit
+            })?
+        }))?;
+        let royaltys: StdResult<Vec<_>> = res.royalty_fees.into_iter()
+            .map(|x| {
+                let royalty = capped_royalty(deps, RoyaltyResponse {
+                    royalty_fee: x.royalty_fee,
+                    creator: x.creator,
+                    splits: x.splits
+                })?;
+                parse_royalty(Ok((deps.api.addr_validate(&x.contract_addr)?, royalty)))
+            })
+            .collect();
+        return Ok(AllRoyaltyListResponse { royalty_fees: royaltys? });
+    }
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
     let start_addr = maybe_addr(deps.api, start_after)?;
     let start = start_addr.as_ref().map(Bound::exclusive);
@@ -342,29 +672,505 @@ pub fn query_royalty_admin(
     })
 }
 
-pub fn construct_action_response(
-    deps: Deps,
-    auction_ids: Vec<u128>
+fn construct_auction_response(
+    auctions: impl Iterator<Item = StdResult<(u128, Auction)>>
 ) -> StdResult<AuctionListResponse> {
-    let mut auctions: Vec<AuctionResponse> = vec![];
-    for i in 0..auction_ids.len() {
-        let res = auction_ids.get(i).unwrap();
-        let auction_id = *res;
-        let res = query_auction(deps, Uint128::from(auction_id));
-        match res {
-            Ok(a) => auctions.push(a),
-            Err(_) => ()
-        };
-    }
+    let auctions = auctions
+        .map(|x| _query_auction(x?.1))
+        .collect::<StdResult<Vec<AuctionResponse>>>()?;
     Ok(AuctionListResponse {
         auctions: auctions
     })
 }
 
+pub fn query_sales_history(
+    deps: Deps,
+    nft_contract: Option<String>,
+    seller: Option<String>,
+    buyer: Option<String>,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+    is_desc: Option<bool>
+) -> StdResult<SalesResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let order = sort_order(is_desc);
+    let (min, max) = sale_id_bounds(start_after, order);
+    let sale_ids: StdResult<Vec<u64>> = match (nft_contract, seller, buyer) {
+        (None, None, None) => SALES
+            .keys(deps.storage, min, max, order)
+            .take(limit)
+            .collect(),
+        (Some(nft_contract), None, None) => {
+            let nft_addr = deps.api.addr_validate(&nft_contract)?;
+            SALE_ID_BY_COLLECTION
+                .prefix(&nft_addr)
+                .keys(deps.storage, min, max, order)
+                .take(limit)
+                .collect()
+        },
+        (None, Some(seller), None) => {
+            let seller_addr = deps.api.addr_validate(&seller)?;
+            SALE_ID_BY_SELLER
+                .prefix(&seller_addr)
+                .keys(deps.storage, min, max, order)
+                .take(limit)
+                .collect()
+        },
+        (None, None, Some(buyer)) => {
+            let buyer_addr = deps.api.addr_validate(&buyer)?;
+            SALE_ID_BY_BUYER
+                .prefix(&buyer_addr)
+                .keys(deps.storage, min, max, order)
+                .take(limit)
+                .collect()
+        },
+        _ => return Err(StdError::generic_err("only one of nft_contract, seller and buyer can be set"))
+    };
+    construct_sales_response(deps, sale_ids?)
+}
+
+pub fn query_token_provenance(
+    deps: Deps,
+    nft_contract: String,
+    token_id: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+    is_desc: Option<bool>
+) -> StdResult<SalesResponse> {
+    let nft_addr = deps.api.addr_validate(&nft_contract)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let order = sort_order(is_desc);
+    let (min, max) = sale_id_bounds(start_after, order);
+    let sale_ids = SALE_ID_BY_TOKEN
+        .prefix((&nft_addr, token_id))
+        .keys(deps.storage, min, max, order)
+        .take(limit)
+        .collect::<StdResult<Vec<u64>>>()?;
+    construct_sales_response(deps, sale_ids)
+}
+
+pub fn query_collection_stats(
+    deps: Deps,
+    env: Env,
+    nft_contract: String,
+    denom: String
+) -> StdResult<CollectionStatsResponse> {
+    let nft_addr = deps.api.addr_validate(&nft_contract)?;
+    let stats = COLLECTION_STATS.may_load(deps.storage, (&nft_addr, denom.as_str()))?.unwrap_or_default();
+    // cheapest buy now listing
+    let floor_price = match auctions().idx.floor
+        .sub_prefix(((nft_addr.clone(), denom.clone()), FLOOR_LISTED))
+        .range(deps.storage, None, None, Order::Ascending)
+        .next() {
+        Some(v) => Some(v?.1.amount),
+        None => None
+    };
+    let last_sale = match stats.last_sale_id {
+        Some(sale_id) => Some(SALES.load(deps.storage, sale_id)?),
+        None => None
+    };
+    // volume is tracked per hour, so windows have hourly resolution
+    let current_hour = env.block.time.seconds() / SECONDS_PER_HOUR;
+    let volume_24h = collection_volume_since(deps, &nft_addr, &denom, (current_hour + 1).saturating_sub(24))?;
+    let volume_7d = collection_volume_since(deps, &nft_addr, &denom, (current_hour + 1).saturating_sub(24 * 7))?;
+    Ok(CollectionStatsResponse {
+        nft_contract,
+        denom,
+        floor_price,
+        volume_24h,
+        volume_7d,
+        total_volume: stats.total_volume,
+        num_sales: stats.num_sales,
+        last_sale,
+        active_listings: stats.active_listings
+    })
+}
+
+fn collection_volume_since(
+    deps: Deps,
+    nft_addr: &Addr,
+    denom: &str,
+    start_hour: u64
+) -> StdResult<Uint128> {
+    COLLECTION_VOLUME_BY_HOUR
+        .prefix((nft_addr, denom))
+        .range(deps.storage, Some(Bound::inclusive(start_hour)), None, Order::Ascending)
+        .try_fold(Uint128::zero(), |total, item| {
+            let (_, volume) = item?;
+            Ok(total + volume)
+        })
+}
+
+pub fn query_top_collections_by_volume(
+    deps: Deps,
+    denom: String,
+    start_after: Option<String>,
+    limit: Option<u32>
+) -> StdResult<TopCollectionsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start_addr = maybe_addr(deps.api, start_after)?;
+    // collections are ordered from highest volume, so start_after is the upper bound
+    let max = match &start_addr {
+        Some(addr) => {
+            let stats = COLLECTION_STATS.may_load(deps.storage, (addr, denom.as_str()))?.unwrap_or_default();
+            Some(Bound::exclusive((stats.total_volume.u128(), addr)))
+        },
+        None => None
+    };
+    let collections = COLLECTION_BY_VOLUME
+        .sub_prefix(denom.as_str())
+        .keys(deps.storage, None, max, Order::Descending)
+        .take(limit)
+        .map(|x| {
+            let (_, nft_addr) = x?;
+            let stats = COLLECTION_STATS.load(deps.storage, (&nft_addr, denom.as_str()))?;
+            Ok(CollectionVolumeResponse {
+                nft_contract: nft_addr.to_string(),
+                denom: denom.clone(),
+                total_volume: stats.total_volume,
+                num_sales: stats.num_sales
+            })
+        })
+        .collect::<StdResult<Vec<CollectionVolumeResponse>>>()?;
+    Ok(TopCollectionsResponse {
+        collections: collections
+    })
+}
+
+pub fn query_oracle_config(
+    deps: Deps
+) -> StdResult<OracleConfigResponse> {
+    let config = load_oracle_config(deps.storage)?;
+    Ok(OracleConfigResponse {
+        max_observations: config.max_observations,
+        outlier_threshold: config.outlier_threshold
+    })
+}
+
+pub fn query_price_observation(
+    deps: Deps,
+    env: Env,
+    nft_contract: String,
+    denom: String,
+    window: u64
+) -> StdResult<PriceObservationResponse> {
+    let nft_addr = deps.api.addr_validate(&nft_contract)?;
+    let config = load_oracle_config(deps.storage)?;
+    let now = env.block.time.seconds();
+    let window_start = now.saturating_sub(window);
+    // newest first, the last observation before the window is the price at window start
+    let mut observations: Vec<PriceObservation> = vec![];
+    let mut carry_in: Option<PriceObservation> = None;
+    for item in PRICE_OBSERVATIONS
+        .prefix((&nft_addr, denom.as_str()))
+        .range(deps.storage, None, None, Order::Descending)
+        .take(config.max_observations as usize) {
+        let (_, observation) = item?;
+        if observation.time < window_start {
+            carry_in = Some(observation);
+            break;
+        }
+        observations.push(observation);
+    }
+    observations.reverse();
+
+    if let Some(threshold) = config.outlier_threshold {
+        let prices = observations.iter().map(|o| o.price).collect::<Vec<Uint128>>();
+        let window_median = median(&prices);
+        if !prices.is_empty() {
+            observations.retain(|o| !is_outlier(o.price, window_median, threshold));
+            carry_in = carry_in.filter(|o| !is_outlier(o.price, window_median, threshold));
+        }
+    }
+    let prices = observations.iter().map(|o| o.price).collect::<Vec<Uint128>>();
+    let sample_count = observations.len() as u64;
+
+    let mut weighted = vec![];
+    if let Some(observation) = carry_in {
+        weighted.push(PriceObservation {
+            price: observation.price,
+            time: window_start
+        });
+    }
+    weighted.extend(observations);
+    Ok(PriceObservationResponse {
+        nft_contract,
+        denom,
+        window,
+        twap: time_weighted_price(&weighted, now)?,
+        median: median(&prices),
+        sample_count
+    })
+}
+
+fn construct_sales_response(
+    deps: Deps,
+    sale_ids: Vec<u64>
+) -> StdResult<SalesResponse> {
+    let sales = sale_ids
+        .into_iter()
+        .map(|sale_id| SALES.load(deps.storage, sale_id))
+        .collect::<StdResult<Vec<Sale>>>()?;
+    Ok(SalesResponse {
+        sales: sales
+    })
+}
+
+pub fn query_search_auctions(
+    deps: Deps,
+    env: Env,
+    filter: AuctionFilter,
+    sort_by: Option<AuctionSortBy>,
+    is_desc: Option<bool>,
+    cursor: Option<Binary>,
+    limit: Option<u32>
+) -> StdResult<SearchAuctionsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let block_time = env.block.time.seconds();
+    let nft_addr = maybe_addr(deps.api, filter.nft_contract.clone())?;
+    let sort_by = sort_by.unwrap_or(AuctionSortBy::Id);
+    let order = sort_order(is_desc);
+    let phase = match filter.status {
+        Some(AuctionStatus::Settled) => AUCTION_PHASE_SETTLED,
+        _ => AUCTION_PHASE_OPEN
+    };
+    let mut range = SearchRange::new(&filter, &sort_by, block_time);
+    if let Some(cursor) = cursor {
+        let cursor: SearchCursor = from_binary(&cursor)?;
+        range.resume_after(&cursor, order);
+    }
+
+    let mut auctions: Vec<AuctionResponse> = vec![];
+    let mut last_key: Option<SearchCursor> = None;
+    let mut is_finished = true;
+    if !range.is_empty() {
+        let mut scanned = 0;
+        for item in search_auctions(deps.storage, nft_addr.as_ref(), phase, &sort_by, &range, order) {
+            let (sort_key, auction) = item?;
+            scanned += 1;
+            last_key = Some(SearchCursor {
+                sort_key: Uint128::from(sort_key),
+                auction_id: auction.auction_id
+            });
+            if matches_filter(&auction, &filter, block_time) {
+                auctions.push(_query_auction(auction)?);
+            }
+            // stop at a full page or when the scan budget is spent, the cursor resumes from here
+            if auctions.len() >= limit || scanned >= MAX_SEARCH_SCAN {
+                is_finished = false;
+                break;
+            }
+        }
+    }
+    let next_cursor = match (is_finished, last_key) {
+        (false, Some(key)) => Some(to_binary(&key)?),
+        _ => None
+    };
+    Ok(SearchAuctionsResponse {
+        auctions: auctions,
+        next_cursor: next_cursor
+    })
+}
+
+pub fn query_activity(
+    deps: Deps,
+    address: String,
+    kinds: Option<Vec<ActivityKind>>,
+    start_after: Option<u64>,
+    limit: Option<u32>
+) -> StdResult<ActivityResponse> {
+    let addr = deps.api.addr_validate(&address)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let max = start_after.map(Bound::exclusive);
+    let mut activities: Vec<Activity> = vec![];
+    let mut next_start_after: Option<u64> = None;
+    let mut scanned = 0;
+    for item in ACTIVITIES.prefix(&addr).range(deps.storage, None, max, Order::Descending) {
+        let (seq, activity) = item?;
+        scanned += 1;
+        if kinds.as_ref().map_or(true, |kinds| kinds.contains(&activity.kind)) {
+            activities.push(activity);
+        }
+        // stop at a full page or when the scan budget is spent, the next page starts after this one
+        if activities.len() >= limit || scanned >= MAX_ACTIVITY_SCAN {
+            next_start_after = Some(seq);
+            break;
+        }
+    }
+    Ok(ActivityResponse {
+        activities: activities,
+        next_start_after: next_start_after
+    })
+}
+
+fn sort_order(
+    is_desc: Option<bool>
+) -> Order {
+    if is_desc.unwrap_or(false) {
+        Order::Descending
+    } else {
+        Order::Ascending
+    }
+}
+
+// start_after is the lower bound when ascending and the upper bound when descending
+fn sale_id_bounds<'a>(
+    start_after: Option<u64>,
+    order: Order
+) -> (Option<Bound<'a, u64>>, Option<Bound<'a, u64>>) {
+    let start = start_after.map(Bound::exclusive);
+    match order {
+        Order::Ascending => (start, None),
+        Order::Descending => (None, start)
+    }
+}
+
 fn parse_royalty(item: StdResult<(Addr,Royalty)>) -> StdResult<AllRoyaltyResponse> {
     item.map(|(nft_address, royalty)| AllRoyaltyResponse {
         contract_addr: nft_address.to_string(),
         royalty_fee: royalty.royalty_fee,
-        creator: royalty.creator.to_string()
+        creator: royalty.creator.to_string(),
+        splits: royalty_splits(&royalty.splits)
+    })
+}
+
+fn royalty_splits(splits: &[RoyaltyShare]) -> Vec<RoyaltySplit> {
+    splits.iter().map(|x| RoyaltySplit {
+        recipient: x.recipient.to_string(),
+        share: x.share
+    }).collect()
+}
+
+pub fn query_order_account(
+    deps: Deps,
+    address: String
+) -> StdResult<OrderAccountResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let deposits = ORDER_DEPOSITS
+        .prefix(&address)
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|x| x.map(|(denom, amount)| Coin { denom: denom, amount: amount }))
+        .filter(|x| !matches!(x, Ok(coin) if coin.amount.is_zero()))
+        .collect::<StdResult<Vec<Coin>>>()?;
+    Ok(OrderAccountResponse {
+        pubkey: ORDER_PUBKEYS.may_load(deps.storage, &address)?,
+        nonce: ORDER_NONCES.may_load(deps.storage, &address)?.unwrap_or(0),
+        deposits: deposits
+    })
+}
+
+pub fn query_order_digest(
+    deps: Deps,
+    order: SignedOrder
+) -> StdResult<OrderDigestResponse> {
+    let digest = order_digest(&order)?;
+    Ok(OrderDigestResponse {
+        is_closed: CLOSED_ORDERS.has(deps.storage, &digest),
+        digest: Binary::from(digest)
+    })
+}
+
+pub fn query_referral_tiers(
+    deps: Deps
+) -> StdResult<ReferralTiersResponse> {
+    let tiers = REFERRAL_TIERS
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|x| x.map(|(tier, fee_share)| ReferralTier { tier: tier, fee_share: fee_share }))
+        .collect::<StdResult<Vec<ReferralTier>>>()?;
+    Ok(ReferralTiersResponse { tiers: tiers })
+}
+
+fn referrer_response(
+    deps: Deps,
+    address: Addr,
+    info: ReferrerInfo
+) -> StdResult<ReferrerResponse> {
+    let stats = REFERRAL_STATS
+        .prefix(&address)
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|x| x.map(|(denom, stats)| ReferralStatsResponse {
+            denom: denom,
+            volume: stats.volume,
+            earnings: stats.earnings,
+            num_sales: stats.num_sales
+        }))
+        .collect::<StdResult<Vec<ReferralStatsResponse>>>()?;
+    Ok(ReferrerResponse {
+        fee_share: referrer_fee_share(deps.storage, &address)?,
+        address: address.to_string(),
+        tier: info.tier,
+        is_approved: info.is_approved,
+        stats: stats
+    })
+}
+
+pub fn query_referrer(
+    deps: Deps,
+    address: String
+) -> StdResult<ReferrerResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let info = REFERRERS.load(deps.storage, &address)?;
+    referrer_response(deps, address, info)
+}
+
+// is_approved filters approved or pending referrers
+pub fn query_referrers(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+    is_approved: Option<bool>
+) -> StdResult<ReferrersResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start_addr = maybe_addr(deps.api, start_after)?;
+    let start = start_addr.as_ref().map(Bound::exclusive);
+    let referrers = REFERRERS
+        .range(deps.storage, start, None, Order::Ascending)
+        .filter(|x| match (x, is_approved) {
+            (Ok((_, info)), Some(is_approved)) => info.is_approved == is_approved,
+            _ => true
+        })
+        .take(limit)
+        .map(|x| x.and_then(|(address, info)| referrer_response(deps, address, info)))
+        .collect::<StdResult<Vec<ReferrerResponse>>>()?;
+    Ok(ReferrersResponse { referrers: referrers })
+}
+
+pub fn query_fee_distribution(
+    deps: Deps
+) -> StdResult<FeeDistributionResponse> {
+    Ok(FeeDistributionResponse {
+        distribution: FEE_DISTRIBUTION.may_load(deps.storage)?
     })
 }
+
+pub fn query_accrued_fees(
+    deps: Deps
+) -> StdResult<AccruedFeesResponse> {
+    let fees = ACCRUED_FEES
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|x| x.map(|(denom, amount)| Coin { denom: denom, amount: amount }))
+        .filter(|x| !matches!(x, Ok(coin) if coin.amount.is_zero()))
+        .collect::<StdResult<Vec<Coin>>>()?;
+    Ok(AccruedFeesResponse { fees: fees })
+}
+
+pub fn query_failed_settlements(
+    deps: Deps,
+    start_after: Option<Uint128>,
+    limit: Option<u32>
+) -> StdResult<FailedSettlementsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|x| Bound::exclusive(x.u128()));
+    let settlements = FAILED_SETTLEMENTS
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|x| x.map(|(auction_id, failed)| FailedSettlementResponse {
+            auction_id: Uint128::from(auction_id),
+            retry_time: retry_time(&failed),
+            reason: failed.reason,
+            time: failed.time,
+            attempts: failed.attempts
+        }))
+        .collect::<StdResult<Vec<FailedSettlementResponse>>>()?;
+    Ok(FailedSettlementsResponse { settlements: settlements })
+}
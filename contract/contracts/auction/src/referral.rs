@@ -0,0 +1,150 @@
+use cosmwasm_std::{Addr, Decimal, Deps, DepsMut, Env, MessageInfo, Response, StdResult, Storage, Uint128};
+
+use crate::error::ContractError;
+use crate::auction::{only_owner, calculate_fee};
+use crate::state::{Auction, ReferrerInfo, REFERRERS, REFERRAL_TIERS, REFERRAL_STATS};
+
+pub fn register_referrer(
+    deps: DepsMut,
+    info: MessageInfo
+) -> Result<Response, ContractError> {
+    if REFERRERS.has(deps.storage, &info.sender) {
+        return Err(ContractError::InvalidReferrer("already registered".to_string()));
+    }
+    REFERRERS.save(deps.storage, &info.sender, &ReferrerInfo {
+        tier: 0,
+        is_approved: false
+    })?;
+    Ok(Response::new()
+        .add_attribute("action", "register_referrer")
+        .add_attribute("referrer", info.sender)
+    )
+}
+
+pub fn set_referral_tier(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    tier: u8,
+    fee_share: Option<Decimal>
+) -> Result<Response, ContractError> {
+    only_owner(deps.as_ref(), &env, info)?;
+    match fee_share {
+        Some(fee_share) => {
+            if fee_share > Decimal::one() {
+                return Err(ContractError::InvalidConfigChange("referral share is more than protocol fee".to_string()));
+            }
+            REFERRAL_TIERS.save(deps.storage, tier, &fee_share)?;
+        },
+        None => REFERRAL_TIERS.remove(deps.storage, tier)
+    };
+    Ok(Response::new()
+        .add_attribute("action", "set_referral_tier")
+        .add_attribute("tier", tier.to_string())
+        .add_attribute("fee_share", fee_share.map(|v| v.to_string()).unwrap_or_default())
+    )
+}
+
+pub fn approve_referrer(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    referrer: String,
+    tier: u8
+) -> Result<Response, ContractError> {
+    only_owner(deps.as_ref(), &env, info)?;
+    let referrer = deps.api.addr_validate(&referrer)?;
+    if !REFERRERS.has(deps.storage, &referrer) {
+        return Err(ContractError::InvalidReferrer("referrer is not registered".to_string()));
+    }
+    if !REFERRAL_TIERS.has(deps.storage, tier) {
+        return Err(ContractError::InvalidReferrer("unknown tier".to_string()));
+    }
+    REFERRERS.save(deps.storage, &referrer, &ReferrerInfo {
+        tier: tier,
+        is_approved: true
+    })?;
+    Ok(Response::new()
+        .add_attribute("action", "approve_referrer")
+        .add_attribute("referrer", referrer)
+        .add_attribute("tier", tier.to_string())
+    )
+}
+
+pub fn revoke_referrer(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    referrer: String
+) -> Result<Response, ContractError> {
+    only_owner(deps.as_ref(), &env, info)?;
+    let referrer = deps.api.addr_validate(&referrer)?;
+    let mut referrer_info = match REFERRERS.may_load(deps.storage, &referrer)? {
+        Some(v) => v,
+        None => return Err(ContractError::InvalidReferrer("referrer is not registered".to_string()))
+    };
+    referrer_info.is_approved = false;
+    REFERRERS.save(deps.storage, &referrer, &referrer_info)?;
+    Ok(Response::new()
+        .add_attribute("action", "revoke_referrer")
+        .add_attribute("referrer", referrer)
+    )
+}
+
+// referrer given with a listing or a bid, it must be registered but is paid only once approved
+pub fn validate_referrer(
+    deps: Deps,
+    referrer: Option<String>,
+    sender: &Addr
+) -> Result<Option<Addr>, ContractError> {
+    let referrer = match referrer {
+        Some(v) => deps.api.addr_validate(&v)?,
+        None => return Ok(None)
+    };
+    if &referrer == sender {
+        return Err(ContractError::InvalidReferrer("can't refer yourself".to_string()));
+    }
+    if !REFERRERS.has(deps.storage, &referrer) {
+        return Err(ContractError::InvalidReferrer("referrer is not registered".to_string()));
+    }
+    Ok(Some(referrer))
+}
+
+pub fn referrer_fee_share(
+    storage: &dyn Storage,
+    referrer: &Addr
+) -> StdResult<Decimal> {
+    Ok(match REFERRERS.may_load(storage, referrer)? {
+        Some(info) if info.is_approved => REFERRAL_TIERS.may_load(storage, info.tier)?.unwrap_or_else(Decimal::zero),
+        _ => Decimal::zero()
+    })
+}
+
+// rewards of the listing and bid referrers, paid from what is left of the protocol fee in that order
+pub fn pay_referrers(
+    storage: &mut dyn Storage,
+    auction: &Auction,
+    protocol_fee: Uint128,
+    available: Uint128
+) -> Result<Vec<(Addr, Uint128)>, ContractError> {
+    let mut available = available;
+    let mut rewards: Vec<(Addr, Uint128)> = vec![];
+    for referrer in [&auction.listing_referrer, &auction.bid_referrer].iter().filter_map(|x| x.as_ref()) {
+        let reward = calculate_fee(referrer_fee_share(storage, referrer)?, protocol_fee)?.min(available);
+        if reward.is_zero() {
+            continue;
+        }
+        available -= reward;
+        let key = (referrer, auction.denom.as_str());
+        let mut stats = REFERRAL_STATS.may_load(storage, key)?.unwrap_or_default();
+        // a referrer of both sides counts the sale once
+        if !rewards.iter().any(|(x, _)| x == referrer) {
+            stats.volume += auction.amount;
+            stats.num_sales += 1;
+        }
+        stats.earnings += reward;
+        REFERRAL_STATS.save(storage, key, &stats)?;
+        rewards.push((referrer.clone(), reward));
+    }
+    Ok(rewards)
+}
@@ -0,0 +1,209 @@
+use cosmwasm_std::{Addr, Order, StdResult, Storage, Uint128};
+use cw_storage_plus::Bound;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use marketplace::auction::{AuctionFilter, AuctionSortBy, AuctionStatus};
+
+use crate::state::{Auction, auctions};
+
+// index entries visited per query, filtered out entries included
+// denom and auction type have no index, a page of a rare one can come back empty with a cursor
+pub const MAX_SEARCH_SCAN: usize = 300;
+
+// not started auction has no end time yet, bidding is allowed while block time <= end time
+pub fn auction_status(
+    auction: &Auction,
+    block_time: u64
+) -> AuctionStatus {
+    if auction.is_settled {
+        AuctionStatus::Settled
+    } else if auction.end_time == 0 {
+        AuctionStatus::NotStarted
+    } else if auction.end_time > block_time {
+        AuctionStatus::Live
+    } else {
+        AuctionStatus::Ended
+    }
+}
+
+// position of the last scanned index entry, returned to the caller as an opaque cursor
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SearchCursor {
+    pub sort_key: Uint128,
+    pub auction_id: Uint128
+}
+
+// inclusive (sort key, auction id) bounds of an index scan
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchRange {
+    pub min: Option<(u128, u128)>,
+    pub max: Option<(u128, u128)>
+}
+
+impl SearchRange {
+    // turn range filters on the sorted field into index bounds
+    pub fn new(
+        filter: &AuctionFilter,
+        sort_by: &AuctionSortBy,
+        block_time: u64
+    ) -> Self {
+        let mut range = SearchRange::default();
+        match sort_by {
+            AuctionSortBy::Id => {},
+            AuctionSortBy::Price => {
+                if let Some(min_price) = filter.min_price {
+                    range.above((min_price.u128(), 0));
+                }
+                if let Some(max_price) = filter.max_price {
+                    range.below((max_price.u128(), u128::MAX));
+                }
+            },
+            AuctionSortBy::EndTime => {
+                match filter.status {
+                    Some(AuctionStatus::NotStarted) => range.below((0, u128::MAX)),
+                    Some(AuctionStatus::Live) => range.above((block_time as u128 + 1, 0)),
+                    Some(AuctionStatus::Ended) => {
+                        range.above((1, 0));
+                        range.below((block_time as u128, u128::MAX));
+                    },
+                    _ => {}
+                }
+                if let Some(ending_before) = filter.ending_before {
+                    range.above((1, 0));
+                    range.below(((ending_before as u128).saturating_sub(1), u128::MAX));
+                }
+            }
+        }
+        range
+    }
+
+    pub fn above(&mut self, key: (u128, u128)) {
+        self.min = Some(self.min.map_or(key, |min| min.max(key)));
+    }
+
+    pub fn below(&mut self, key: (u128, u128)) {
+        self.max = Some(self.max.map_or(key, |max| max.min(key)));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match (self.min, self.max) {
+            (Some(min), Some(max)) => min > max,
+            _ => false
+        }
+    }
+
+    // continue strictly after the cursor in the scan direction
+    pub fn resume_after(&mut self, cursor: &SearchCursor, order: Order) {
+        let (sort_key, auction_id) = (cursor.sort_key.u128(), cursor.auction_id.u128());
+        match order {
+            Order::Ascending => match auction_id.checked_add(1) {
+                Some(next_id) => self.above((sort_key, next_id)),
+                None => self.above((sort_key.saturating_add(1), 0))
+            },
+            Order::Descending => match auction_id.checked_sub(1) {
+                Some(prev_id) => self.below((sort_key, prev_id)),
+                None => self.below((sort_key.saturating_sub(1), u128::MAX))
+            }
+        }
+    }
+}
+
+fn end_time_bound(key: (u128, u128)) -> Bound<'static, (u64, u128)> {
+    Bound::inclusive((key.0.min(u64::MAX as u128) as u64, key.1))
+}
+
+fn sort_key(
+    sort_by: &AuctionSortBy,
+    auction: &Auction
+) -> u128 {
+    match sort_by {
+        AuctionSortBy::Id => auction.auction_id.u128(),
+        AuctionSortBy::Price => auction.amount.u128(),
+        AuctionSortBy::EndTime => auction.end_time as u128
+    }
+}
+
+// (sort key, auction) pairs from the index matching the sort field, narrowed to a collection if given
+pub fn search_auctions<'a>(
+    storage: &'a dyn Storage,
+    nft_contract: Option<&Addr>,
+    phase: u8,
+    sort_by: &AuctionSortBy,
+    range: &SearchRange,
+    order: Order
+) -> Box<dyn Iterator<Item = StdResult<(u128, Auction)>> + 'a> {
+    let indexes = auctions().idx;
+    let items = match (sort_by, nft_contract) {
+        (AuctionSortBy::Id, None) => {
+            let min = range.min.map(|key| Bound::inclusive(key.1));
+            let max = range.max.map(|key| Bound::inclusive(key.1));
+            indexes.phase.prefix(phase).range(storage, min, max, order)
+        },
+        (AuctionSortBy::Id, Some(nft_contract)) => {
+            let min = range.min.map(|key| Bound::inclusive(key.1));
+            let max = range.max.map(|key| Bound::inclusive(key.1));
+            indexes.collection.prefix((nft_contract.clone(), phase)).range(storage, min, max, order)
+        },
+        (AuctionSortBy::Price, None) => {
+            let min = range.min.map(Bound::inclusive);
+            let max = range.max.map(Bound::inclusive);
+            indexes.price.sub_prefix(phase).range(storage, min, max, order)
+        },
+        (AuctionSortBy::Price, Some(nft_contract)) => {
+            let min = range.min.map(Bound::inclusive);
+            let max = range.max.map(Bound::inclusive);
+            indexes.collection_price.sub_prefix((nft_contract.clone(), phase)).range(storage, min, max, order)
+        },
+        (AuctionSortBy::EndTime, None) => {
+            let min = range.min.map(end_time_bound);
+            let max = range.max.map(end_time_bound);
+            indexes.end_time.sub_prefix(phase).range(storage, min, max, order)
+        },
+        (AuctionSortBy::EndTime, Some(nft_contract)) => {
+            let min = range.min.map(end_time_bound);
+            let max = range.max.map(end_time_bound);
+            indexes.collection_end_time.sub_prefix((nft_contract.clone(), phase)).range(storage, min, max, order)
+        }
+    };
+    let sort_by = sort_by.clone();
+    Box::new(items.map(move |x| x.map(|(_, auction)| (sort_key(&sort_by, &auction), auction))))
+}
+
+// predicates that are not covered by the index bounds
+pub fn matches_filter(
+    auction: &Auction,
+    filter: &AuctionFilter,
+    block_time: u64
+) -> bool {
+    if let Some(auction_type) = &filter.auction_type {
+        if auction.auction_type != *auction_type {
+            return false;
+        }
+    }
+    if let Some(denom) = &filter.denom {
+        if auction.denom != *denom {
+            return false;
+        }
+    }
+    if let Some(status) = &filter.status {
+        if auction_status(auction, block_time) != *status {
+            return false;
+        }
+    }
+    if let Some(min_price) = filter.min_price {
+        if auction.amount < min_price {
+            return false;
+        }
+    }
+    if let Some(max_price) = filter.max_price {
+        if auction.amount > max_price {
+            return false;
+        }
+    }
+    if let Some(ending_before) = filter.ending_before {
+        if auction.end_time == 0 || auction.end_time >= ending_before {
+            return false;
+        }
+    }
+    true
+}
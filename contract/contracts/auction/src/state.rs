@@ -1,9 +1,10 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{ Uint128, Addr, Decimal };
-use cw_storage_plus::{ Item, Map };
-use marketplace::auction::{ Royalty, AuctionType, Bid };
+use cosmwasm_std::{ Uint128, Addr, Decimal, Binary };
+use cw_storage_plus::{ Item, Map, Index, IndexList, IndexedMap, MultiIndex };
+use marketplace::auction::{ Royalty, AuctionType, Bid, PendingConfigChange, Sale, Activity, KeeperReward, FeeDistribution,
+    RoyaltyShare };
 
 pub const STATE_KEY: &[u8] = b"state";
 
@@ -22,7 +23,8 @@ pub struct Config {
     pub duration: u64,
     pub extension_duration: u64,
     pub accepted_denom: Vec<String>,
-    pub collector_address: Addr
+    pub collector_address: Addr,
+    pub config_timelock: u64 // seconds before a proposed config change can be executed
 }
 
 
@@ -48,23 +50,291 @@ pub struct Auction {
     pub amount: Uint128,
     pub creator_address: Option<Addr>,
     pub royalty_fee: Decimal,
+    // royalty splits when listed, empty pays the whole royalty to the creator
+    #[serde(default)]
+    pub royalty_splits: Vec<RoyaltyShare>,
     pub protocol_fee: Decimal,
-    pub is_settled: bool
+    pub is_settled: bool,
+    // nft is not escrowed, listings stored before approval listings existed are escrowed
+    #[serde(default)]
+    pub is_approval_listing: bool,
+    // no first bid is accepted after this time, none never expires
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    // nft delivery and refund addresses of the leading bid, none means the bidder
+    #[serde(default)]
+    pub recipient: Option<Addr>,
+    #[serde(default)]
+    pub refund_to: Option<Addr>,
+    // hook payload of the leading bid, the nft is delivered with SendNft when set
+    #[serde(default)]
+    pub send_msg: Option<Binary>,
+    // frontends the listing and the leading bid came from
+    #[serde(default)]
+    pub listing_referrer: Option<Addr>,
+    #[serde(default)]
+    pub bid_referrer: Option<Addr>
 }
 
+// auctions are kept after settlement, so every index is partitioned by phase
+pub const AUCTION_PHASE_OPEN: u8 = 0;
+pub const AUCTION_PHASE_SETTLED: u8 = 1;
+// floor index flag of buy now listings that can still be bought
+pub const FLOOR_LISTED: u8 = 1;
+pub const FLOOR_UNLISTED: u8 = 0;
 
+pub fn auction_phase(
+    auction: &Auction
+) -> u8 {
+    if auction.is_settled {
+        AUCTION_PHASE_SETTLED
+    } else {
+        AUCTION_PHASE_OPEN
+    }
+}
+
+// listing that expired before its first bid
+pub fn is_listing_expired(
+    auction: &Auction,
+    block_time: u64
+) -> bool {
+    auction.end_time == 0 && auction.expires_at.map_or(false, |expires_at| expires_at <= block_time)
+}
+
+pub struct AuctionIndexes<'a> {
+    // (nft contract, token id, phase)
+    pub nft_token: MultiIndex<'a, (Addr, String, u8), Auction, u128>,
+    // (nft contract, phase)
+    pub collection: MultiIndex<'a, (Addr, u8), Auction, u128>,
+    // (seller, phase)
+    pub seller: MultiIndex<'a, (Addr, u8), Auction, u128>,
+    // (bidder, phase), listings without a bid are kept under an empty address
+    pub bidder: MultiIndex<'a, (Addr, u8), Auction, u128>,
+    pub phase: MultiIndex<'a, u8, Auction, u128>,
+    // (phase, amount)
+    pub price: MultiIndex<'a, (u8, u128), Auction, u128>,
+    // (phase, end time), end time is 0 until the first bid
+    pub end_time: MultiIndex<'a, (u8, u64), Auction, u128>,
+    pub collection_price: MultiIndex<'a, (Addr, u8, u128), Auction, u128>,
+    pub collection_end_time: MultiIndex<'a, (Addr, u8, u64), Auction, u128>,
+    // ((nft contract, denom), floor flag, price)
+    pub floor: MultiIndex<'a, ((Addr, String), u8, u128), Auction, u128>
+}
+
+impl<'a> IndexList<Auction> for AuctionIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Auction>> + '_> {
+        let v: Vec<&dyn Index<Auction>> = vec![
+            &self.nft_token,
+            &self.collection,
+            &self.seller,
+            &self.bidder,
+            &self.phase,
+            &self.price,
+            &self.end_time,
+            &self.collection_price,
+            &self.collection_end_time,
+            &self.floor
+        ];
+        Box::new(v.into_iter())
+    }
+}
+
+pub fn auctions<'a>() -> IndexedMap<'a, u128, Auction, AuctionIndexes<'a>> {
+    let indexes = AuctionIndexes {
+        nft_token: MultiIndex::new(
+            |a: &Auction| (a.nft_contract.clone(), a.token_id.clone(), auction_phase(a)),
+            "dealers",
+            "dealers__nft_token"
+        ),
+        collection: MultiIndex::new(
+            |a: &Auction| (a.nft_contract.clone(), auction_phase(a)),
+            "dealers",
+            "dealers__collection"
+        ),
+        seller: MultiIndex::new(
+            |a: &Auction| (a.seller.clone(), auction_phase(a)),
+            "dealers",
+            "dealers__seller"
+        ),
+        bidder: MultiIndex::new(
+            |a: &Auction| (a.bidder.clone().unwrap_or_else(|| Addr::unchecked("")), auction_phase(a)),
+            "dealers",
+            "dealers__bidder"
+        ),
+        phase: MultiIndex::new(
+            |a: &Auction| auction_phase(a),
+            "dealers",
+            "dealers__phase"
+        ),
+        price: MultiIndex::new(
+            |a: &Auction| (auction_phase(a), a.amount.u128()),
+            "dealers",
+            "dealers__price"
+        ),
+        end_time: MultiIndex::new(
+            |a: &Auction| (auction_phase(a), a.end_time),
+            "dealers",
+            "dealers__end_time"
+        ),
+        collection_price: MultiIndex::new(
+            |a: &Auction| (a.nft_contract.clone(), auction_phase(a), a.amount.u128()),
+            "dealers",
+            "dealers__collection_price"
+        ),
+        collection_end_time: MultiIndex::new(
+            |a: &Auction| (a.nft_contract.clone(), auction_phase(a), a.end_time),
+            "dealers",
+            "dealers__collection_end_time"
+        ),
+        floor: MultiIndex::new(
+            |a: &Auction| {
+                let listed = !a.is_settled && a.auction_type == AuctionType::BuyNow && a.end_time == 0;
+                let flag = if listed { FLOOR_LISTED } else { FLOOR_UNLISTED };
+                ((a.nft_contract.clone(), a.denom.clone()), flag, a.amount.u128())
+            },
+            "dealers",
+            "dealers__floor"
+        )
+    };
+    IndexedMap::new("dealers", indexes)
+}
+
+pub struct BidIndexes<'a> {
+    pub bidder: MultiIndex<'a, Addr, Bid, (u128, u128)>
+}
+
+impl<'a> IndexList<Bid> for BidIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Bid>> + '_> {
+        let v: Vec<&dyn Index<Bid>> = vec![&self.bidder];
+        Box::new(v.into_iter())
+    }
+}
+
+// Key (auction id, bid number) -> bid, bid numbers start from 1
+pub fn bid_history<'a>() -> IndexedMap<'a, (u128, u128), Bid, BidIndexes<'a>> {
+    let indexes = BidIndexes {
+        bidder: MultiIndex::new(
+            |b: &Bid| b.bidder.clone(),
+            "bid_history_by_auction_id",
+            "bid_history_by_auction_id__bidder"
+        )
+    };
+    IndexedMap::new("bid_history_by_auction_id", indexes)
+}
 
 pub const CONFIG: Item<Config> = Item::new("config");
 pub const STATE: Item<State> = Item::new("state");
-pub const AUCTIONS: Map<u128, Auction> = Map::new("dealers");
+pub const PENDING_CONFIG_CHANGE: Item<PendingConfigChange> = Item::new("pending_config_change");
 pub const ROYALTIES: Map<&Addr, Royalty> = Map::new("royaltys");
 pub const ROYALTY_ADMINS: Map<&Addr, bool> = Map::new("royalty_admins");
-// Key Address -> Bids Id -> Bids
-pub const BID_HISTORY_BY_AUCTION_ID: Map<(u128, u128), Bid> = Map::new("bid_history_by_auction_id");
-pub const AUCTION_ID_BY_SELLER: Map<(&Addr, u128), bool> = Map::new("auction_id_by_seller");
-pub const AUCTION_ID_BY_ENDTIME: Map<(&Addr, u64, u128), bool> = Map::new("auction_id_by_end_time");
-pub const AUCTION_ID_BY_AMOUNT: Map<(&Addr, u128, u128), bool> = Map::new("auction_id_by_amount");
-pub const AUCTION_ID_BY_BIDDER: Map<(&Addr, u128), bool> = Map::new("auction_id_by_bidder");
-pub const NOT_STARTED_AUCTION: Map<(&Addr, u128), bool> = Map::new("not_started_auction");
+// Key nft contract -> last time the royalty fee rate was changed
+pub const ROYALTY_RATE_CHANGED_AT: Map<&Addr, u64> = Map::new("royalty_rate_changed_at");
 pub const BID_COUNT_BY_AUCTION_ID: Map<u128, Uint128> = Map::new("bid_count_by_auction_id");
-pub const NFT_AUCTION_MAPS: Map<(&Addr, String), u128> = Map::new("nft_auction_maps");
\ No newline at end of file
+// sales are append only, they are kept after the auction is settled
+pub const NEXT_SALE_ID: Item<u64> = Item::new("next_sale_id");
+pub const SALES: Map<u64, Sale> = Map::new("sales");
+pub const SALE_ID_BY_TOKEN: Map<(&Addr, String, u64), bool> = Map::new("sale_id_by_token");
+pub const SALE_ID_BY_COLLECTION: Map<(&Addr, u64), bool> = Map::new("sale_id_by_collection");
+pub const SALE_ID_BY_SELLER: Map<(&Addr, u64), bool> = Map::new("sale_id_by_seller");
+pub const SALE_ID_BY_BUYER: Map<(&Addr, u64), bool> = Map::new("sale_id_by_buyer");
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct CollectionStats {
+    pub total_volume: Uint128,
+    pub num_sales: u64,
+    pub last_sale_id: Option<u64>,
+    pub active_listings: u64
+}
+
+// Key (nft contract, denom) -> stats
+pub const COLLECTION_STATS: Map<(&Addr, &str), CollectionStats> = Map::new("collection_stats");
+// Key (nft contract, denom, hour) -> volume sold in that hour
+pub const COLLECTION_VOLUME_BY_HOUR: Map<(&Addr, &str, u64), Uint128> = Map::new("collection_volume_by_hour");
+// Key (denom, total volume, nft contract)
+pub const COLLECTION_BY_VOLUME: Map<(&str, u128, &Addr), bool> = Map::new("collection_by_volume");
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OracleConfig {
+    pub max_observations: u64,
+    // sale prices further than this fraction from the median are ignored
+    pub outlier_threshold: Option<Decimal>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PriceObservation {
+    pub price: Uint128,
+    pub time: u64
+}
+
+pub const ORACLE_CONFIG: Item<OracleConfig> = Item::new("oracle_config");
+// Key ((nft contract, denom), observation id), only the last max_observations are kept
+pub const PRICE_OBSERVATIONS: Map<((&Addr, &str), u64), PriceObservation> = Map::new("price_observations");
+pub const NEXT_OBSERVATION_ID: Map<(&Addr, &str), u64> = Map::new("next_observation_id");
+
+// Key (address, sequence) -> activity, sequence is per address
+pub const ACTIVITIES: Map<(&Addr, u64), Activity> = Map::new("activities");
+pub const NEXT_ACTIVITY_SEQ: Map<&Addr, u64> = Map::new("next_activity_seq");
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct KeeperRewardConfig {
+    pub reward: KeeperReward,
+    pub min_delay: u64
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FailedSettlement {
+    pub reason: String,
+    pub time: u64,
+    pub attempts: u32
+}
+
+// Key auction id -> last failed settlement or close of a batch or tick, removed once the auction is settled or closed
+// tick retries it with a backoff
+pub const FAILED_SETTLEMENTS: Map<u128, FailedSettlement> = Map::new("failed_settlements");
+
+// (end time, auction id) of the last open auction visited by tick, the scan starts over while unset
+pub const TICK_CURSOR: Item<(u64, u128)> = Item::new("tick_cursor");
+
+// default max batch size is used while unset
+pub const MAX_BATCH_SIZE: Item<u64> = Item::new("max_batch_size");
+
+// no reward is paid while unset
+pub const KEEPER_REWARD: Item<KeeperRewardConfig> = Item::new("keeper_reward");
+
+// primary sale detection is off while unset
+pub const PRIMARY_PROTOCOL_FEE: Item<Decimal> = Item::new("primary_protocol_fee");
+
+// royalties are resolved by this contract while unset
+pub const ROYALTY_REGISTRY: Item<Addr> = Item::new("royalty_registry");
+
+// signed orders, an order is valid while its nonce equals the nonce of the signer
+pub const ORDER_PUBKEYS: Map<&Addr, Binary> = Map::new("order_pubkeys");
+pub const ORDER_NONCES: Map<&Addr, u64> = Map::new("order_nonces");
+// Key order digest -> filled or cancelled
+pub const CLOSED_ORDERS: Map<&[u8], bool> = Map::new("closed_orders");
+// Key (bidder, denom) -> funds available to bid orders
+pub const ORDER_DEPOSITS: Map<(&Addr, &str), Uint128> = Map::new("order_deposits");
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ReferrerInfo {
+    pub tier: u8,
+    pub is_approved: bool
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct ReferralStats {
+    pub volume: Uint128,
+    pub earnings: Uint128,
+    pub num_sales: u64
+}
+
+// Key tier -> share of the protocol fee
+pub const REFERRAL_TIERS: Map<u8, Decimal> = Map::new("referral_tiers");
+pub const REFERRERS: Map<&Addr, ReferrerInfo> = Map::new("referrers");
+// Key (referrer, denom) -> stats of the sales the referrer was paid for
+pub const REFERRAL_STATS: Map<(&Addr, &str), ReferralStats> = Map::new("referral_stats");
+
+// protocol fees go to the collector address while unset
+pub const FEE_DISTRIBUTION: Item<FeeDistribution> = Item::new("fee_distribution");
+// Key denom -> protocol fees not yet distributed
+pub const ACCRUED_FEES: Map<&str, Uint128> = Map::new("accrued_fees");
@@ -0,0 +1,48 @@
+use cosmwasm_std::{StdResult, Storage, Uint128};
+use marketplace::auction::Sale;
+
+use crate::state::{Auction, COLLECTION_STATS, COLLECTION_VOLUME_BY_HOUR, COLLECTION_BY_VOLUME};
+
+pub const SECONDS_PER_HOUR: u64 = 3600;
+
+pub fn on_listing_created(
+    storage: &mut dyn Storage,
+    auction: &Auction
+) -> StdResult<()> {
+    let key = (&auction.nft_contract, auction.denom.as_str());
+    let mut stats = COLLECTION_STATS.may_load(storage, key)?.unwrap_or_default();
+    stats.active_listings += 1;
+    COLLECTION_STATS.save(storage, key, &stats)
+}
+
+// listing is closed by cancel or settle
+pub fn on_listing_closed(
+    storage: &mut dyn Storage,
+    auction: &Auction
+) -> StdResult<()> {
+    let key = (&auction.nft_contract, auction.denom.as_str());
+    let mut stats = COLLECTION_STATS.may_load(storage, key)?.unwrap_or_default();
+    stats.active_listings = stats.active_listings.saturating_sub(1);
+    COLLECTION_STATS.save(storage, key, &stats)
+}
+
+pub fn on_sale(
+    storage: &mut dyn Storage,
+    sale: &Sale
+) -> StdResult<()> {
+    let key = (&sale.nft_contract, sale.denom.as_str());
+    let mut stats = COLLECTION_STATS.may_load(storage, key)?.unwrap_or_default();
+    // keep volume ranking in sync
+    COLLECTION_BY_VOLUME.remove(storage, (sale.denom.as_str(), stats.total_volume.u128(), &sale.nft_contract));
+    stats.total_volume += sale.price;
+    stats.num_sales += 1;
+    stats.last_sale_id = Some(sale.sale_id);
+    COLLECTION_STATS.save(storage, key, &stats)?;
+    COLLECTION_BY_VOLUME.save(storage, (sale.denom.as_str(), stats.total_volume.u128(), &sale.nft_contract), &true)?;
+
+    let hour = sale.time / SECONDS_PER_HOUR;
+    let hour_volume = COLLECTION_VOLUME_BY_HOUR.may_load(storage, (&sale.nft_contract, sale.denom.as_str(), hour))?
+        .unwrap_or_else(Uint128::zero);
+    COLLECTION_VOLUME_BY_HOUR.save(storage, (&sale.nft_contract, sale.denom.as_str(), hour), &(hour_volume + sale.price))?;
+    Ok(())
+}
@@ -1,17 +1,35 @@
-use cosmwasm_std::testing::{mock_env, mock_info ,MOCK_CONTRACT_ADDR };
+use cosmwasm_std::testing::{mock_env, mock_info ,MOCK_CONTRACT_ADDR, MockApi, MockStorage };
 use cosmwasm_std::{
-    Api, Querier, StdError, Storage, to_binary, Uint128, Coin, CosmosMsg, WasmMsg, BankMsg, CanonicalAddr, Order, from_binary,
-    DepsMut, Env, Timestamp, Addr, Decimal
+    Api, Querier, StdError, Storage, to_binary, to_vec, Binary, Uint128, Coin, CosmosMsg, WasmMsg, BankMsg, CanonicalAddr, Order, from_binary,
+    DepsMut, Env, Timestamp, Addr, Decimal, OwnedDeps, Reply, ReplyOn, SubMsgResult, SubMsg, Response
 };
-use marketplace::auction::{InstantiateMsg, ConfigResponse, Cw721HookMsg, AuctionResponse, ExecuteMsg,  RoyaltyFeeResponse, RoyaltyResponse, CalculatePriceResponse, AuctionType, RoyaltyAdminResponse, AllRoyaltyListResponse, AllRoyaltyResponse, StateResponse};
+use marketplace::auction::{InstantiateMsg, ConfigResponse, Cw721HookMsg, AuctionResponse, ExecuteMsg,  RoyaltyFeeResponse, RoyaltyResponse, CalculatePriceResponse, AuctionType, RoyaltyAdminResponse, AllRoyaltyListResponse, AllRoyaltyResponse, StateResponse,
+    ConfigChange, PendingConfigChange, PendingConfigChangeResponse, MigrateMsg, Sale, CollectionVolumeResponse,
+    AuctionFilter, AuctionSortBy, AuctionStatus, ActivityKind, Bid, BidStatus, KeeperReward, SudoMsg,
+    SignedOrder, OrderSide, OrderAccountResponse, BatchListItem, PriceUpdate, SweepTarget, ReferralStatsResponse,
+    FeeDistribution, FeeRecipient, RoyaltyPayout, RoyaltySplit, QueryMsg, RoyaltyRegistryResponse, FailedSettlementResponse};
 use cw721::{Cw721ReceiveMsg, Cw721ExecuteMsg};
 use std::str::FromStr;
+use cw2::{get_contract_version, set_contract_version};
 
-use crate::contract::{instantiate, execute, query};
+use crate::contract::{instantiate, execute, query, migrate, sudo, reply, CONTRACT_NAME, CONTRACT_VERSION};
 use crate::error::ContractError;
-use crate::auction::{calculate_fee, calculate_min_bid_amount};
-use crate::mock_querier::mock_dependencies;
-use crate::querier::{query_config, query_auction, query_royalty_admin, query_royalty_fee, query_calculate_price, query_all_royalty, query_state, query_nft_auction_map};
+use crate::auction::{calculate_fee, calculate_min_bid_amount, TICK_RETRY_DELAY};
+use crate::search::MAX_SEARCH_SCAN;
+use crate::activity::MAX_ACTIVITY_SCAN;
+use crate::mock_querier::{mock_dependencies, WasmMockQuerier};
+use crate::state::{Auction, STATE, State, BID_COUNT_BY_AUCTION_ID, ACCRUED_FEES, CONFIG, Config};
+use crate::migration::{AUCTIONS_V2_0_0, BID_HISTORY_V2_0_0, NFT_AUCTION_MAPS_V2_0_0, AUCTION_ID_BY_SELLER_V2_0_0, AUCTION_ID_BY_ENDTIME_V2_0_0,
+    AUCTION_ID_BY_AMOUNT_V2_0_0, AUCTION_ID_BY_BIDDER_V2_0_0, NOT_STARTED_AUCTION_V2_0_0, MIGRATION_STEP, MigrationStep, migrate_batch};
+use crate::querier::{query_config, query_auction, query_royalty_admin, query_royalty_fee, query_calculate_price, query_all_royalty, query_state, query_nft_auction_map,
+    query_pending_config_change, query_sales_history, query_token_provenance, query_collection_stats,
+    query_top_collections_by_volume, query_price_observation, query_oracle_config, query_search_auctions,
+    query_auction_by_end_time, query_auction_by_amount, query_auction_by_bidder, query_not_started_auctions, query_auction_by_seller,
+    query_auction_by_nft, query_activity, query_bid_number, query_bids_by_bidder, query_bid_history_by_auction_id, load_royalty, query_primary_sale,
+    query_settleable_auctions, query_keeper_reward, query_max_batch_size, query_order_account, query_order_digest,
+    query_referrer, query_referrers, query_accrued_fees, query_fee_distribution, query_failed_settlements};
+use crate::order::order_digest;
+use k256::ecdsa::{SigningKey, signature::Signer};
 
 fn setup_contract(deps: DepsMut, accepted_denom: Vec<String>) {
     let msg = InstantiateMsg {
@@ -22,7 +40,8 @@ fn setup_contract(deps: DepsMut, accepted_denom: Vec<String>) {
         extension_duration: 900,
         accepted_denom: accepted_denom,
         collector_address: "collector".to_string(),
-        max_royalty_fee: Decimal::percent(20) // 20%
+        max_royalty_fee: Decimal::percent(20), // 20%
+        config_timelock: 3600
     };
     let info = mock_info("owner", &[]);
     let env = mock_env();
@@ -30,6 +49,50 @@ fn setup_contract(deps: DepsMut, accepted_denom: Vec<String>) {
     assert_eq!(0, res.messages.len());
 }
 
+fn create_listing(deps: DepsMut, seller: &str, token_id: &str, reserve_price: u128, is_instant_sale: bool) {
+    let nft_receive_msg = Cw721ReceiveMsg {
+        sender: seller.into(),
+        token_id: token_id.to_string(),
+        msg: to_binary(&Cw721HookMsg::CreateAuction {
+            denom: "uluna".to_string(),
+            reserve_price: Uint128::from(reserve_price),
+            is_instant_sale: is_instant_sale,
+            referrer: None
+        }).unwrap()
+    };
+    let info = mock_info("nft", &[]);
+    execute(deps, mock_env(), info, ExecuteMsg::ReceiveNft(nft_receive_msg)).unwrap();
+}
+
+fn sell_buy_now(deps: &mut OwnedDeps<MockStorage, MockApi, WasmMockQuerier>, token_id: &str, price: u128, time: u64) {
+    create_listing(deps.as_mut(), "satoshi", token_id, price, true);
+    let auction_id = query_state(deps.as_ref()).unwrap().next_auction_id - Uint128::from(1u128);
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(time);
+    let info = mock_info("buyer", &[Coin::new(price, "uluna")]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::PlaceBid { auction_id: auction_id, recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+    let info = mock_info("random", &[]);
+    execute(deps.as_mut(), env, info, ExecuteMsg::Settle { auction_id: auction_id }).unwrap();
+}
+
+// runs the sub messages this contract sends to itself like the chain would, a failed one is replied to
+fn run_self_calls(deps: &mut OwnedDeps<MockStorage, MockApi, WasmMockQuerier>, env: &Env, messages: &[SubMsg]) -> Vec<Response> {
+    let mut results = vec![];
+    for sub_msg in messages {
+        let msg = match &sub_msg.msg {
+            CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, msg, .. }) if contract_addr == MOCK_CONTRACT_ADDR => msg,
+            _ => continue
+        };
+        match execute(deps.as_mut(), env.clone(), mock_info(MOCK_CONTRACT_ADDR, &[]), from_binary(msg).unwrap()) {
+            Ok(res) => results.push(res),
+            Err(err) => {
+                reply(deps.as_mut(), env.clone(), Reply { id: sub_msg.id, result: SubMsgResult::Err(err.to_string()) }).unwrap();
+            }
+        }
+    }
+    results
+}
+
 #[test]
 fn proper_initialization() {
     let mut deps = mock_dependencies(&[]);
@@ -48,9 +111,130 @@ fn proper_initialization() {
             extension_duration: 900,
             accepted_denom: vec!["uluna".to_string()],
             collector_address: "collector".to_string(),
-            max_royalty_fee: Decimal::percent(20)
+            max_royalty_fee: Decimal::percent(20),
+            config_timelock: 3600,
+            primary_protocol_fee: None
+        }
+    );
+    let version = get_contract_version(deps.as_ref().storage).unwrap();
+    assert_eq!(version.contract, CONTRACT_NAME.to_string());
+    assert_eq!(version.version, CONTRACT_VERSION.to_string());
+}
+
+#[test]
+fn timelock_config_change() {
+    let mut deps = mock_dependencies(&[]);
+    setup_contract(deps.as_mut(), vec!["uluna".to_string()]);
+
+    let propose_msg = ExecuteMsg::ProposeConfigChange(ConfigChange {
+        protocol_fee: Some(Decimal::percent(2)),
+        collector_address: Some("treasury".to_string()),
+        ..ConfigChange::default()
+    });
+    // random guy propose
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(1000);
+    let info = mock_info("random", &[]);
+    let err = execute(deps.as_mut(), env.clone(), info, propose_msg.clone()).unwrap_err();
+    match err {
+        ContractError::Unauthorized { .. } => {}
+        e => panic!("unexcted error: {}", e)
+    }
+    // owner propose
+    let info = mock_info("owner", &[]);
+    execute(deps.as_mut(), env.clone(), info, propose_msg.clone()).unwrap();
+    let pending = query_pending_config_change(deps.as_ref()).unwrap();
+    assert_eq!(
+        pending,
+        PendingConfigChangeResponse {
+            pending: Some(PendingConfigChange {
+                change: ConfigChange {
+                    protocol_fee: Some(Decimal::percent(2)),
+                    collector_address: Some("treasury".to_string()),
+                    ..ConfigChange::default()
+                },
+                eta: 4600
+            })
+        }
+    );
+    // propose again while pending
+    let info = mock_info("owner", &[]);
+    let err = execute(deps.as_mut(), env.clone(), info, propose_msg.clone()).unwrap_err();
+    match err {
+        ContractError::InvalidConfigChange { .. } => {}
+        e => panic!("unexcted error: {}", e)
+    }
+    // execute before eta
+    env.block.time = Timestamp::from_seconds(4599);
+    let info = mock_info("random", &[]);
+    let err = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::ExecuteConfigChange {}).unwrap_err();
+    match err {
+        ContractError::InvalidConfigChange { .. } => {}
+        e => panic!("unexcted error: {}", e)
+    }
+    // pause is still instant
+    let info = mock_info("owner", &[]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::AdminPause {}).unwrap();
+    assert!(query_state(deps.as_ref()).unwrap().is_freeze);
+    // anyone can execute after eta
+    env.block.time = Timestamp::from_seconds(4600);
+    let info = mock_info("random", &[]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::ExecuteConfigChange {}).unwrap();
+    let config = query_config(deps.as_ref()).unwrap();
+    assert_eq!(config.protocol_fee, Decimal::percent(2));
+    assert_eq!(config.collector_address, "treasury".to_string());
+    assert_eq!(config.min_increment, Decimal::percent(10));
+    let pending = query_pending_config_change(deps.as_ref()).unwrap();
+    assert_eq!(pending, PendingConfigChangeResponse { pending: None });
+    // propose and cancel
+    let info = mock_info("owner", &[]);
+    execute(deps.as_mut(), env.clone(), info, propose_msg.clone()).unwrap();
+    let info = mock_info("random", &[]);
+    let err = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::CancelConfigChange {}).unwrap_err();
+    match err {
+        ContractError::Unauthorized { .. } => {}
+        e => panic!("unexcted error: {}", e)
+    }
+    let info = mock_info("owner", &[]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::CancelConfigChange {}).unwrap();
+    env.block.time = Timestamp::from_seconds(10000);
+    let info = mock_info("random", &[]);
+    let err = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::ExecuteConfigChange {}).unwrap_err();
+    match err {
+        ContractError::InvalidConfigChange { .. } => {}
+        e => panic!("unexcted error: {}", e)
+    }
+    // fees above the sale price and a zero timelock are rejected
+    for change in vec![
+        ConfigChange { protocol_fee: Some(Decimal::percent(101)), ..ConfigChange::default() },
+        ConfigChange { max_royalty_fee: Some(Decimal::percent(101)), ..ConfigChange::default() },
+        ConfigChange { protocol_fee: Some(Decimal::percent(90)), ..ConfigChange::default() },
+        ConfigChange { primary_protocol_fee: Some(Decimal::percent(101)), ..ConfigChange::default() },
+        ConfigChange { primary_protocol_fee: Some(Decimal::percent(1)), remove_primary_protocol_fee: true, ..ConfigChange::default() },
+        ConfigChange { config_timelock: Some(0), ..ConfigChange::default() }
+    ] {
+        let err = execute(deps.as_mut(), env.clone(), mock_info("owner", &[]), ExecuteMsg::ProposeConfigChange(change)).unwrap_err();
+        match err {
+            ContractError::InvalidConfigChange { .. } => {}
+            e => panic!("unexcted error: {}", e)
         }
-    )
+    }
+    let mut deps = mock_dependencies(&[]);
+    let err = instantiate(deps.as_mut(), mock_env(), mock_info("owner", &[]), InstantiateMsg {
+        protocol_fee: Decimal::percent(1),
+        min_reserve_price: Uint128::from(1000u128),
+        min_increment: Decimal::percent(10),
+        duration: 86400,
+        extension_duration: 900,
+        accepted_denom: vec!["uluna".to_string()],
+        collector_address: "collector".to_string(),
+        max_royalty_fee: Decimal::percent(20),
+        config_timelock: 0
+    }).unwrap_err();
+    match err {
+        ContractError::InvalidConfigChange { .. } => {}
+        e => panic!("unexcted error: {}", e)
+    }
 }
 
 #[test]
@@ -99,7 +283,8 @@ fn create_buynow() {
         msg: to_binary(&Cw721HookMsg::CreateAuction {
             denom: "uluna".to_string(),
             reserve_price: Uint128::from(1_000000u128),
-            is_instant_sale: true
+            is_instant_sale: true,
+            referrer: None
         }).unwrap()
     };
 
@@ -124,6 +309,13 @@ fn create_buynow() {
             reserve_price: Uint128::from(1000000u128),
             seller: "satoshi".into(),
             is_settled: false,
+            is_approval_listing: false,
+            expires_at: None,
+            recipient: None,
+            refund_to: None,
+            send_msg: None,
+            listing_referrer: None,
+            bid_referrer: None,
             creator_address: None,
             royalty_fee: Decimal::zero()
         }
@@ -146,6 +338,13 @@ fn create_buynow() {
             reserve_price: Uint128::from(1000000u128),
             seller: "satoshi".into(),
             is_settled: false,
+            is_approval_listing: false,
+            expires_at: None,
+            recipient: None,
+            refund_to: None,
+            send_msg: None,
+            listing_referrer: None,
+            bid_referrer: None,
             creator_address: None,
             royalty_fee: Decimal::zero()
         }
@@ -159,7 +358,8 @@ fn create_buynow() {
         msg: to_binary(&Cw721HookMsg::CreateAuction {
             denom: "uluna".to_string(),
             reserve_price: Uint128::from(10u128),
-            is_instant_sale: false
+            is_instant_sale: false,
+            referrer: None
         }).unwrap()
     };
 
@@ -179,7 +379,8 @@ fn create_buynow() {
         msg: to_binary(&Cw721HookMsg::CreateAuction {
             denom: "uthb".to_string(),
             reserve_price: Uint128::from(10u128),
-            is_instant_sale: false
+            is_instant_sale: false,
+            referrer: None
         }).unwrap()
     };
 
@@ -207,7 +408,8 @@ fn create_auction() {
         msg: to_binary(&Cw721HookMsg::CreateAuction {
             denom: "uluna".to_string(),
             reserve_price: Uint128::from(1_000000u128),
-            is_instant_sale: false
+            is_instant_sale: false,
+            referrer: None
         }).unwrap()
     };
 
@@ -232,6 +434,13 @@ fn create_auction() {
             reserve_price: Uint128::from(1000000u128),
             seller: "satoshi".into(),
             is_settled: false,
+            is_approval_listing: false,
+            expires_at: None,
+            recipient: None,
+            refund_to: None,
+            send_msg: None,
+            listing_referrer: None,
+            bid_referrer: None,
             creator_address: None,
             royalty_fee: Decimal::zero()
         }
@@ -254,6 +463,13 @@ fn create_auction() {
             reserve_price: Uint128::from(1000000u128),
             seller: "satoshi".into(),
             is_settled: false,
+            is_approval_listing: false,
+            expires_at: None,
+            recipient: None,
+            refund_to: None,
+            send_msg: None,
+            listing_referrer: None,
+            bid_referrer: None,
             creator_address: None,
             royalty_fee: Decimal::zero()
         }
@@ -274,7 +490,8 @@ fn settle_buynow() {
         msg: to_binary(&Cw721HookMsg::CreateAuction {
             denom: "uluna".to_string(),
             reserve_price: Uint128::from(1_000000u128),
-            is_instant_sale: true
+            is_instant_sale: true,
+            referrer: None
         }).unwrap()
     };
 
@@ -282,7 +499,11 @@ fn settle_buynow() {
     execute(deps.as_mut(), env, info, create_buynow_msg).unwrap();
     // place bid with no fund
     let place_bid_msg = ExecuteMsg::PlaceBid {
-        auction_id: Uint128::zero()
+        auction_id: Uint128::zero(),
+        recipient: None,
+        refund_to: None,
+        send_msg: None,
+        referrer: None
     };
     let env = mock_env();
     let info = mock_info("buyer", &[]);
@@ -332,6 +553,13 @@ fn settle_buynow() {
             reserve_price: Uint128::from(1_000000u128),
             seller: "satoshi".into(),
             is_settled: false,
+            is_approval_listing: false,
+            expires_at: None,
+            recipient: None,
+            refund_to: None,
+            send_msg: None,
+            listing_referrer: None,
+            bid_referrer: None,
             creator_address: None,
             royalty_fee: Decimal::zero()
         }
@@ -411,6 +639,13 @@ fn settle_buynow() {
             reserve_price: Uint128::from(1_000000u128),
             seller: "satoshi".into(),
             is_settled: true,
+            is_approval_listing: false,
+            expires_at: None,
+            recipient: None,
+            refund_to: None,
+            send_msg: None,
+            listing_referrer: None,
+            bid_referrer: None,
             creator_address: None,
             royalty_fee: Decimal::zero()
         }
@@ -450,7 +685,8 @@ fn settle_buynow_with_royalty() {
     let set_royalty_msg = ExecuteMsg::SetRoyaltyFee {
         contract_addr: "nft".to_string(),
         creator: "creator".to_string(),
-        royalty_fee: Decimal::percent(5)
+        royalty_fee: Decimal::percent(5),
+        splits: None
     };
     let info = mock_info("admin", &[]);
     let env = mock_env();
@@ -465,7 +701,8 @@ fn settle_buynow_with_royalty() {
         msg: to_binary(&Cw721HookMsg::CreateAuction {
             denom: "uluna".to_string(),
             reserve_price: Uint128::from(1_000000u128),
-            is_instant_sale: true
+            is_instant_sale: true,
+            referrer: None
         }).unwrap()
     };
 
@@ -473,7 +710,11 @@ fn settle_buynow_with_royalty() {
     execute(deps.as_mut(), env, info, create_buynow_msg).unwrap();
     // place bid with no fund
     let place_bid_msg = ExecuteMsg::PlaceBid {
-        auction_id: Uint128::zero()
+        auction_id: Uint128::zero(),
+        recipient: None,
+        refund_to: None,
+        send_msg: None,
+        referrer: None
     };
     let env = mock_env();
     let info = mock_info("buyer", &[]);
@@ -523,6 +764,13 @@ fn settle_buynow_with_royalty() {
             reserve_price: Uint128::from(1_000000u128),
             seller: "satoshi".into(),
             is_settled: false,
+            is_approval_listing: false,
+            expires_at: None,
+            recipient: None,
+            refund_to: None,
+            send_msg: None,
+            listing_referrer: None,
+            bid_referrer: None,
             creator_address: Some("creator".to_string()),
             royalty_fee: Decimal::percent(5)
         }
@@ -610,6 +858,13 @@ fn settle_buynow_with_royalty() {
             reserve_price: Uint128::from(1_000000u128),
             seller: "satoshi".into(),
             is_settled: true,
+            is_approval_listing: false,
+            expires_at: None,
+            recipient: None,
+            refund_to: None,
+            send_msg: None,
+            listing_referrer: None,
+            bid_referrer: None,
             creator_address: Some("creator".to_string()),
             royalty_fee: Decimal::percent(5)
         }
@@ -646,7 +901,8 @@ fn settle_auction() {
         msg: to_binary(&Cw721HookMsg::CreateAuction {
             denom: "uluna".to_string(),
             reserve_price: Uint128::from(1_000000u128),
-            is_instant_sale: false
+            is_instant_sale: false,
+            referrer: None
         }).unwrap()
     };
 
@@ -654,7 +910,11 @@ fn settle_auction() {
     execute(deps.as_mut(), env, info, create_buynow_msg).unwrap();
     // place bid with no fund
     let place_bid_msg = ExecuteMsg::PlaceBid {
-        auction_id: Uint128::zero()
+        auction_id: Uint128::zero(),
+        recipient: None,
+        refund_to: None,
+        send_msg: None,
+        referrer: None
     };
     let env = mock_env();
     let info = mock_info("buyer", &[]);
@@ -704,6 +964,13 @@ fn settle_auction() {
             reserve_price: Uint128::from(1_000000u128),
             seller: "satoshi".into(),
             is_settled: false,
+            is_approval_listing: false,
+            expires_at: None,
+            recipient: None,
+            refund_to: None,
+            send_msg: None,
+            listing_referrer: None,
+            bid_referrer: None,
             creator_address: None,
             royalty_fee: Decimal::zero()
         }
@@ -753,6 +1020,13 @@ fn settle_auction() {
             reserve_price: Uint128::from(1_000000u128),
             seller: "satoshi".into(),
             is_settled: false,
+            is_approval_listing: false,
+            expires_at: None,
+            recipient: None,
+            refund_to: None,
+            send_msg: None,
+            listing_referrer: None,
+            bid_referrer: None,
             creator_address: None,
             royalty_fee: Decimal::zero()
         }
@@ -787,6 +1061,13 @@ fn settle_auction() {
             reserve_price: Uint128::from(1_000000u128),
             seller: "satoshi".into(),
             is_settled: false,
+            is_approval_listing: false,
+            expires_at: None,
+            recipient: None,
+            refund_to: None,
+            send_msg: None,
+            listing_referrer: None,
+            bid_referrer: None,
             creator_address: None,
             royalty_fee: Decimal::zero()
         }
@@ -866,6 +1147,13 @@ fn settle_auction() {
             reserve_price: Uint128::from(1_000000u128),
             seller: "satoshi".into(),
             is_settled: true,
+            is_approval_listing: false,
+            expires_at: None,
+            recipient: None,
+            refund_to: None,
+            send_msg: None,
+            listing_referrer: None,
+            bid_referrer: None,
             creator_address: None,
             royalty_fee: Decimal::zero()
         }
@@ -902,7 +1190,8 @@ fn check_freeze_and_cancel_auction() {
         msg: to_binary(&Cw721HookMsg::CreateAuction {
             denom: "uluna".to_string(),
             reserve_price: Uint128::from(1_000000u128),
-            is_instant_sale: true
+            is_instant_sale: true,
+            referrer: None
         }).unwrap()
     };
 
@@ -917,7 +1206,8 @@ fn check_freeze_and_cancel_auction() {
         msg: to_binary(&Cw721HookMsg::CreateAuction {
             denom: "uluna".to_string(),
             reserve_price: Uint128::from(1_000000u128),
-            is_instant_sale: true
+            is_instant_sale: true,
+            referrer: None
         }).unwrap()
     };
     let create_buynow_msg = ExecuteMsg::ReceiveNft(nft_receive_msg);
@@ -952,7 +1242,8 @@ fn check_freeze_and_cancel_auction() {
         msg: to_binary(&Cw721HookMsg::CreateAuction {
             denom: "uluna".to_string(),
             reserve_price: Uint128::from(1_000000u128),
-            is_instant_sale: true
+            is_instant_sale: true,
+            referrer: None
         }).unwrap()
     };
 
@@ -966,7 +1257,11 @@ fn check_freeze_and_cancel_auction() {
     let env = mock_env();
     let info = mock_info("buyer", &[Coin::new(1_000000, "uluna")]);
     let place_bid_msg = ExecuteMsg::PlaceBid {
-        auction_id: Uint128::zero()
+        auction_id: Uint128::zero(),
+        recipient: None,
+        refund_to: None,
+        send_msg: None,
+        referrer: None
     };
     let err = execute(deps.as_mut(), env, info, place_bid_msg).unwrap_err();
     match err {
@@ -1036,7 +1331,11 @@ fn check_freeze_and_cancel_auction() {
     let env = mock_env();
     let info = mock_info("buyer", &[Coin::new(1_000000, "uluna")]);
     let place_bid_msg = ExecuteMsg::PlaceBid {
-        auction_id: Uint128::from(1u128)
+        auction_id: Uint128::from(1u128),
+        recipient: None,
+        refund_to: None,
+        send_msg: None,
+        referrer: None
     };
     execute(deps.as_mut(), env, info, place_bid_msg).unwrap();
 }
@@ -1055,7 +1354,8 @@ fn admin_cancel() {
         msg: to_binary(&Cw721HookMsg::CreateAuction {
             denom: "uluna".to_string(),
             reserve_price: Uint128::from(1_000000u128),
-            is_instant_sale: true
+            is_instant_sale: true,
+            referrer: None
         }).unwrap()
     };
 
@@ -1100,7 +1400,8 @@ fn admin_cancel() {
         msg: to_binary(&Cw721HookMsg::CreateAuction {
             denom: "uluna".to_string(),
             reserve_price: Uint128::from(1_000000u128),
-            is_instant_sale: false
+            is_instant_sale: false,
+            referrer: None
         }).unwrap()
     };
 
@@ -1110,7 +1411,11 @@ fn admin_cancel() {
     let env = mock_env();
     let info = mock_info("buyer", &[Coin::new(1_000000, "uluna")]);
     let place_bid_msg = ExecuteMsg::PlaceBid {
-        auction_id: Uint128::from(1u128)
+        auction_id: Uint128::from(1u128),
+        recipient: None,
+        refund_to: None,
+        send_msg: None,
+        referrer: None
     };
     execute(deps.as_mut(), env, info, place_bid_msg).unwrap();
     // owner cancel
@@ -1162,7 +1467,8 @@ fn set_royalty() {
     let set_royalty_msg = ExecuteMsg::SetRoyaltyFee {
         contract_addr: "nft".to_string(),
         creator: "creator".to_string(),
-        royalty_fee: Decimal::from_str("0.05").unwrap()
+        royalty_fee: Decimal::from_str("0.05").unwrap(),
+        splits: None
     };
     // random guy set royalty
     let info = mock_info("random", &[]);
@@ -1207,11 +1513,12 @@ fn set_royalty() {
         RoyaltyFeeResponse {
             royalty_fee: Some(RoyaltyResponse {
                 royalty_fee: Decimal::percent(5),
-                creator: "creator".to_string()
+                creator: "creator".to_string(),
+                splits: vec![]
             })
         }
     );
-    let price = query_calculate_price(deps.as_ref(), "nft".to_string(), "bitcoin".to_string(), Uint128::from(1_000000u128)).unwrap();
+    let price = query_calculate_price(deps.as_ref(), mock_env(), "nft".to_string(), "bitcoin".to_string(), Uint128::from(1_000000u128)).unwrap();
     assert_eq!(
         price,
         CalculatePriceResponse {
@@ -1220,14 +1527,24 @@ fn set_royalty() {
             amount: Uint128::from(1_000000u128),
             protocol_fee: Uint128::from(10000u128),
             royalty_fee: Uint128::from(50000u128),
-            seller_amount: Uint128::from(940000u128)
+            royalty_payouts: vec![RoyaltyPayout { recipient: "creator".to_string(), amount: Uint128::from(50000u128) }],
+            seller_amount: Uint128::from(940000u128),
+            keeper_reward: Uint128::zero(),
+            is_primary_sale: false
         }
     );
+    // fees above the sale price are an error, not an underflow
+    let config = CONFIG.load(&deps.storage).unwrap();
+    CONFIG.save(&mut deps.storage, &Config { protocol_fee: Decimal::percent(99), ..config.clone() }).unwrap();
+    let err = query_calculate_price(deps.as_ref(), mock_env(), "nft".to_string(), "bitcoin".to_string(), Uint128::from(1_000000u128)).unwrap_err();
+    assert_eq!(err, StdError::generic_err("fees are more than sale price"));
+    CONFIG.save(&mut deps.storage, &config).unwrap();
     // add another royalty
     let set_royalty_msg = ExecuteMsg::SetRoyaltyFee {
         contract_addr: "nft2".to_string(),
         creator: "creator".to_string(),
-        royalty_fee: Decimal::from_str("0.1").unwrap()
+        royalty_fee: Decimal::from_str("0.1").unwrap(),
+        splits: None
     };
     let info = mock_info("admin1", &[]);
     let env = mock_env();
@@ -1241,12 +1558,14 @@ fn set_royalty() {
                 AllRoyaltyResponse {
                     contract_addr: "nft".to_string(),
                     creator: "creator".to_string(),
-                    royalty_fee: Decimal::percent(5)
+                    royalty_fee: Decimal::percent(5),
+                    splits: vec![]
                 },
                 AllRoyaltyResponse {
                     contract_addr: "nft2".to_string(),
                     creator: "creator".to_string(),
-                    royalty_fee: Decimal::percent(10)
+                    royalty_fee: Decimal::percent(10),
+                    splits: vec![]
                 }
             )
         }
@@ -1271,7 +1590,8 @@ fn set_royalty() {
     let set_royalty_msg = ExecuteMsg::SetRoyaltyFee {
         contract_addr: "nft".to_string(),
         creator: "creator".to_string(),
-        royalty_fee: Decimal::from_str("0.1").unwrap()
+        royalty_fee: Decimal::from_str("0.1").unwrap(),
+        splits: None
     };
     let info = mock_info("admin1", &[]);
     let env = mock_env();
@@ -1302,7 +1622,8 @@ fn query_multiple_royalties() {
         let set_royalty_msg = ExecuteMsg::SetRoyaltyFee {
             contract_addr,
             creator: "creator".to_string(),
-            royalty_fee: Decimal::percent(i)
+            royalty_fee: Decimal::percent(i),
+            splits: None
         };
         let info = mock_info("admin", &[]);
         let env = mock_env();
@@ -1319,12 +1640,14 @@ fn query_multiple_royalties() {
                 AllRoyaltyResponse {
                     contract_addr: "nft1".to_string(),
                     creator: "creator".to_string(),
-                    royalty_fee: Decimal::percent(1)
+                    royalty_fee: Decimal::percent(1),
+                    splits: vec![]
                 },
                 AllRoyaltyResponse {
                     contract_addr: "nft10".to_string(),
                     creator: "creator".to_string(),
-                    royalty_fee: Decimal::percent(10)
+                    royalty_fee: Decimal::percent(10),
+                    splits: vec![]
                 }
             ]
         }
@@ -1337,27 +1660,32 @@ fn query_multiple_royalties() {
                 AllRoyaltyResponse {
                     contract_addr: "nft2".to_string(),
                     creator: "creator".to_string(),
-                    royalty_fee: Decimal::percent(2)
+                    royalty_fee: Decimal::percent(2),
+                    splits: vec![]
                 },
                 AllRoyaltyResponse {
                     contract_addr: "nft3".to_string(),
                     creator: "creator".to_string(),
-                    royalty_fee: Decimal::percent(3)
+                    royalty_fee: Decimal::percent(3),
+                    splits: vec![]
                 },
                 AllRoyaltyResponse {
                     contract_addr: "nft4".to_string(),
                     creator: "creator".to_string(),
-                    royalty_fee: Decimal::percent(4)
+                    royalty_fee: Decimal::percent(4),
+                    splits: vec![]
                 },
                 AllRoyaltyResponse {
                     contract_addr: "nft5".to_string(),
                     creator: "creator".to_string(),
-                    royalty_fee: Decimal::percent(5)
+                    royalty_fee: Decimal::percent(5),
+                    splits: vec![]
                 },
                 AllRoyaltyResponse {
                     contract_addr: "nft6".to_string(),
                     creator: "creator".to_string(),
-                    royalty_fee: Decimal::percent(6)
+                    royalty_fee: Decimal::percent(6),
+                    splits: vec![]
                 }
             ]
         }
@@ -1370,17 +1698,20 @@ fn query_multiple_royalties() {
                 AllRoyaltyResponse {
                     contract_addr: "nft7".to_string(),
                     creator: "creator".to_string(),
-                    royalty_fee: Decimal::percent(7)
+                    royalty_fee: Decimal::percent(7),
+                    splits: vec![]
                 },
                 AllRoyaltyResponse {
                     contract_addr: "nft8".to_string(),
                     creator: "creator".to_string(),
-                    royalty_fee: Decimal::percent(8)
+                    royalty_fee: Decimal::percent(8),
+                    splits: vec![]
                 },
                 AllRoyaltyResponse {
                     contract_addr: "nft9".to_string(),
                     creator: "creator".to_string(),
-                    royalty_fee: Decimal::percent(9)
+                    royalty_fee: Decimal::percent(9),
+                    splits: vec![]
                 }
             ]
         }
@@ -1392,4 +1723,2106 @@ fn query_multiple_royalties() {
             royalty_fees: vec![]
         }
     );
-}
\ No newline at end of file
+}
+
+// raw config as stored by v2.0.0, before config_timelock and cw2 contract info
+const CONFIG_V2_0_0: &[u8] = br#"{"owner":"owner","protocol_fee":"0.01","min_reserve_price":"1000","min_increment":"0.1","max_royalty_fee":"0.2","duration":86400,"extension_duration":900,"accepted_denom":["uluna"],"collector_address":"collector"}"#;
+
+fn fixture_auction(auction_id: u128, token_id: &str, auction_type: AuctionType, end_time: u64, bidder: Option<&str>) -> Auction {
+    Auction {
+        auction_id: Uint128::from(auction_id),
+        nft_contract: Addr::unchecked("nft"),
+        token_id: token_id.to_string(),
+        auction_type: auction_type,
+        seller: Addr::unchecked("satoshi"),
+        duration: 86400,
+        extension_duration: 900,
+        denom: "uluna".to_string(),
+        reserve_price: Uint128::from(1_000000u128),
+        end_time: end_time,
+        bidder: bidder.map(Addr::unchecked),
+        amount: Uint128::from(1_000000u128),
+        creator_address: None,
+        royalty_fee: Decimal::zero(),
+        royalty_splits: vec![],
+        protocol_fee: Decimal::percent(1),
+        is_settled: false,
+        is_approval_listing: false,
+        expires_at: None,
+        recipient: None,
+        refund_to: None,
+        send_msg: None,
+        listing_referrer: None,
+        bid_referrer: None
+    }
+}
+
+fn setup_v2_0_0_state(deps: DepsMut) {
+    deps.storage.set(b"config", CONFIG_V2_0_0);
+    STATE.save(deps.storage, &State {
+        next_auction_id: Uint128::from(4u128),
+        is_freeze: false
+    }).unwrap();
+    // auction 0 is not started, auction 1 has a bid
+    AUCTIONS_V2_0_0.save(deps.storage, 0, &fixture_auction(0, "bitcoin", AuctionType::BuyNow, 0, None)).unwrap();
+    NFT_AUCTION_MAPS_V2_0_0.save(deps.storage, (&Addr::unchecked("nft"), "bitcoin".to_string()), &0).unwrap();
+    AUCTION_ID_BY_SELLER_V2_0_0.save(deps.storage, (&Addr::unchecked("satoshi"), 0), &true).unwrap();
+    NOT_STARTED_AUCTION_V2_0_0.save(deps.storage, (&Addr::unchecked("nft"), 0), &true).unwrap();
+    AUCTIONS_V2_0_0.save(deps.storage, 1, &fixture_auction(1, "ethereum", AuctionType::Auction, 86500, Some("buyer"))).unwrap();
+    NFT_AUCTION_MAPS_V2_0_0.save(deps.storage, (&Addr::unchecked("nft"), "ethereum".to_string()), &1).unwrap();
+    AUCTION_ID_BY_SELLER_V2_0_0.save(deps.storage, (&Addr::unchecked("satoshi"), 1), &true).unwrap();
+    // auction 1 index is missing from end time index
+    AUCTION_ID_BY_AMOUNT_V2_0_0.save(deps.storage, (&Addr::unchecked("nft"), 1_000000, 1), &true).unwrap();
+    AUCTION_ID_BY_BIDDER_V2_0_0.save(deps.storage, (&Addr::unchecked("buyer"), 1), &true).unwrap();
+    // the bid count of auction 1 was saved as 1 after the second bid
+    let bid = |bidder: &str, amount: u128| Bid {
+        auction_id: Uint128::from(1u128),
+        bidder: Addr::unchecked(bidder),
+        time: 100,
+        denom: "uluna".to_string(),
+        amount: Uint128::from(amount),
+        recipient: None,
+        refund_to: None,
+        send_msg: None,
+        referrer: None
+    };
+    BID_HISTORY_V2_0_0.save(deps.storage, (1, 1), &bid("fliper", 900000)).unwrap();
+    BID_HISTORY_V2_0_0.save(deps.storage, (1, 2), &bid("buyer", 1_000000)).unwrap();
+    BID_COUNT_BY_AUCTION_ID.save(deps.storage, 1, &Uint128::from(1u128)).unwrap();
+    // auction 2 was admin cancelled after a bid, bid indices were left behind
+    AUCTION_ID_BY_ENDTIME_V2_0_0.save(deps.storage, (&Addr::unchecked("nft"), 90000, 2), &true).unwrap();
+    AUCTION_ID_BY_AMOUNT_V2_0_0.save(deps.storage, (&Addr::unchecked("nft"), 2_000000, 2), &true).unwrap();
+    AUCTION_ID_BY_BIDDER_V2_0_0.save(deps.storage, (&Addr::unchecked("fliper"), 2), &true).unwrap();
+    // auction 3 was sold with a royalty
+    AUCTIONS_V2_0_0.save(deps.storage, 3, &Auction {
+        creator_address: Some(Addr::unchecked("artist")),
+        royalty_fee: Decimal::percent(5),
+        is_settled: true,
+        ..fixture_auction(3, "cardano", AuctionType::BuyNow, 50000, Some("whale"))
+    }).unwrap();
+}
+
+#[test]
+fn migrate_from_v2_0_0() {
+    let mut deps = mock_dependencies(&[]);
+    setup_v2_0_0_state(deps.as_mut());
+
+    let env = mock_env();
+    let res = migrate(deps.as_mut(), env, MigrateMsg { config_timelock: Some(7200) }).unwrap();
+    assert_eq!(res.attributes.get(1).unwrap().value, "2.0.0".to_string());
+
+    let version = get_contract_version(deps.as_ref().storage).unwrap();
+    assert_eq!(version.contract, CONTRACT_NAME.to_string());
+    assert_eq!(version.version, CONTRACT_VERSION.to_string());
+    // config is backfilled
+    let config = query_config(deps.as_ref()).unwrap();
+    assert_eq!(
+        config,
+        ConfigResponse {
+            owner: "owner".into(),
+            protocol_fee: Decimal::percent(1),
+            min_reserve_price: Uint128::from(1000u128),
+            min_increment: Decimal::percent(10),
+            duration: 86400,
+            extension_duration: 900,
+            accepted_denom: vec!["uluna".to_string()],
+            collector_address: "collector".to_string(),
+            max_royalty_fee: Decimal::percent(20),
+            config_timelock: 7200,
+            primary_protocol_fee: None
+        }
+    );
+    // auctions are untouched
+    let auction = query_auction(deps.as_ref(), Uint128::from(1u128)).unwrap();
+    assert_eq!(auction.bidder, Some("buyer".to_string()));
+    // indices are rebuilt
+    let ids = |auctions: Vec<AuctionResponse>| auctions.iter().map(|x| x.auction_id.u128()).collect::<Vec<u128>>();
+    let end_times = query_auction_by_end_time(deps.as_ref(), "nft".to_string(), 0, None, None).unwrap();
+    assert_eq!(ids(end_times.auctions), vec![1]);
+    let amounts = query_auction_by_amount(deps.as_ref(), "nft".to_string(), Uint128::zero(), None).unwrap();
+    assert_eq!(ids(amounts.auctions), vec![1]);
+    let bidders = query_auction_by_bidder(deps.as_ref(), "buyer".to_string(), None, None).unwrap();
+    assert_eq!(ids(bidders.auctions), vec![1]);
+    let bidders = query_auction_by_bidder(deps.as_ref(), "fliper".to_string(), None, None).unwrap();
+    assert_eq!(bidders.auctions.len(), 0);
+    let not_started = query_not_started_auctions(deps.as_ref(), "nft".to_string(), None, None, None).unwrap();
+    assert_eq!(ids(not_started.auctions), vec![0]);
+    let sellers = query_auction_by_seller(deps.as_ref(), "satoshi".to_string(), None).unwrap();
+    assert_eq!(ids(sellers.auctions), vec![0, 1]);
+    let bid_count = query_bid_number(deps.as_ref(), Uint128::from(1u128)).unwrap();
+    assert_eq!(bid_count.count, Uint128::from(2u128));
+    let bids = query_bids_by_bidder(deps.as_ref(), "buyer".to_string(), None, None).unwrap();
+    assert_eq!(bids.bids[0].status, BidStatus::Leading);
+    let bids = query_bids_by_bidder(deps.as_ref(), "fliper".to_string(), None, None).unwrap();
+    assert_eq!(bids.bids[0].status, BidStatus::Outbid);
+    // legacy index entries are dropped
+    let legacy_bidders = AUCTION_ID_BY_BIDDER_V2_0_0
+        .keys(deps.as_ref().storage, None, None, Order::Ascending)
+        .collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(legacy_bidders.len(), 0);
+    let auction = query_nft_auction_map(deps.as_ref(), "nft".to_string(), "ethereum".to_string()).unwrap();
+    assert_eq!(auction.auction_id, Uint128::from(1u128));
+    // settled auctions are backfilled as secondary sales, so a resale is not primary
+    let provenance = query_token_provenance(deps.as_ref(), "nft".to_string(), "cardano".to_string(), None, None, None).unwrap();
+    assert_eq!(provenance.sales.len(), 1);
+    assert_eq!(provenance.sales[0].buyer, Addr::unchecked("whale"));
+    assert_eq!(provenance.sales[0].protocol_fee, Uint128::from(10000u128));
+    assert_eq!(provenance.sales[0].royalty_fee, Uint128::from(50000u128));
+    assert_eq!(provenance.sales[0].time, 50000);
+    assert!(!provenance.sales[0].is_primary_sale);
+    let resale = Auction { seller: Addr::unchecked("whale"), ..fixture_auction(4, "cardano", AuctionType::BuyNow, 0, None) };
+    assert!(!query_primary_sale(deps.as_ref(), &resale).unwrap());
+
+    // migrate again is a no-op
+    let env = mock_env();
+    migrate(deps.as_mut(), env, MigrateMsg { config_timelock: None }).unwrap();
+    let config = query_config(deps.as_ref()).unwrap();
+    assert_eq!(config.config_timelock, 7200);
+    let provenance = query_token_provenance(deps.as_ref(), "nft".to_string(), "cardano".to_string(), None, None, None).unwrap();
+    assert_eq!(provenance.sales.len(), 1);
+}
+
+#[test]
+fn migrate_in_batches() {
+    let mut deps = mock_dependencies(&[]);
+    setup_v2_0_0_state(deps.as_mut());
+    MIGRATION_STEP.save(deps.as_mut().storage, &MigrationStep::LegacyIndices).unwrap();
+
+    // other messages wait for the migration
+    let info = mock_info("bidder", &[Coin::new(2_000000, "uluna")]);
+    let err = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::PlaceBid { auction_id: Uint128::zero(), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap_err();
+    match err {
+        ContractError::InvalidMigration { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    let res = execute(deps.as_mut(), mock_env(), mock_info("random", &[]), ExecuteMsg::ContinueMigration {}).unwrap();
+    assert_eq!(res.attributes[1].value, "true".to_string());
+    let err = execute(deps.as_mut(), mock_env(), mock_info("random", &[]), ExecuteMsg::ContinueMigration {}).unwrap_err();
+    match err {
+        ContractError::InvalidMigration { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+
+    // the same result in small batches
+    let mut batched = mock_dependencies(&[]);
+    setup_v2_0_0_state(batched.as_mut());
+    MIGRATION_STEP.save(batched.as_mut().storage, &MigrationStep::LegacyIndices).unwrap();
+    let mut batches = 1;
+    while !migrate_batch(batched.as_mut().storage, 2).unwrap() {
+        batches += 1;
+    }
+    assert!(batches > 5);
+    let ids = |auctions: Vec<AuctionResponse>| auctions.iter().map(|x| x.auction_id.u128()).collect::<Vec<u128>>();
+    for deps in [&deps, &batched] {
+        let end_times = query_auction_by_end_time(deps.as_ref(), "nft".to_string(), 0, None, None).unwrap();
+        assert_eq!(ids(end_times.auctions), vec![1]);
+        assert_eq!(query_bid_number(deps.as_ref(), Uint128::from(1u128)).unwrap().count, Uint128::from(2u128));
+        let legacy_bidders = AUCTION_ID_BY_BIDDER_V2_0_0
+            .keys(deps.as_ref().storage, None, None, Order::Ascending)
+            .collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(legacy_bidders.len(), 0);
+        // backfilled sales count in the collection stats and the volume ranking
+        let stats = query_collection_stats(deps.as_ref(), mock_env(), "nft".to_string(), "uluna".to_string()).unwrap();
+        assert_eq!(stats.num_sales, 1);
+        assert_eq!(stats.total_volume, Uint128::from(1_000000u128));
+        assert_eq!(stats.active_listings, 2);
+        let top = query_top_collections_by_volume(deps.as_ref(), "uluna".to_string(), None, None).unwrap();
+        assert_eq!(top.collections[0].nft_contract, "nft".to_string());
+    }
+}
+
+#[test]
+fn migrate_rejects_downgrade_and_wrong_contract() {
+    let mut deps = mock_dependencies(&[]);
+    setup_contract(deps.as_mut(), vec!["uluna".to_string()]);
+
+    set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "99.0.0").unwrap();
+    let err = migrate(deps.as_mut(), mock_env(), MigrateMsg { config_timelock: None }).unwrap_err();
+    match err {
+        ContractError::InvalidMigration { .. } => {}
+        e => panic!("unexcted error: {}", e)
+    }
+
+    set_contract_version(deps.as_mut().storage, "crates.io:cw20-base", CONTRACT_VERSION).unwrap();
+    let err = migrate(deps.as_mut(), mock_env(), MigrateMsg { config_timelock: None }).unwrap_err();
+    match err {
+        ContractError::InvalidMigration { .. } => {}
+        e => panic!("unexcted error: {}", e)
+    }
+}
+
+#[test]
+fn sales_history() {
+    let mut deps = mock_dependencies(&[]);
+    setup_contract(deps.as_mut(), vec!["uluna".to_string()]);
+
+    // satoshi sells bitcoin to buyer
+    create_listing(deps.as_mut(), "satoshi", "bitcoin", 1_000000, true);
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+    let info = mock_info("buyer", &[Coin::new(1_000000, "uluna")]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::PlaceBid { auction_id: Uint128::zero(), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+    env.block.time = Timestamp::from_seconds(120);
+    let info = mock_info("random", &[]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Settle { auction_id: Uint128::zero() }).unwrap();
+    // buyer auctions bitcoin to fliper
+    create_listing(deps.as_mut(), "buyer", "bitcoin", 2_000000, false);
+    env.block.time = Timestamp::from_seconds(200);
+    let info = mock_info("fliper", &[Coin::new(2_000000, "uluna")]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::PlaceBid { auction_id: Uint128::from(1u128), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+    env.block.time = Timestamp::from_seconds(86600);
+    let info = mock_info("random", &[]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Settle { auction_id: Uint128::from(1u128) }).unwrap();
+
+    let first_sale = Sale {
+        sale_id: 0,
+        auction_id: Uint128::zero(),
+        nft_contract: Addr::unchecked("nft"),
+        token_id: "bitcoin".to_string(),
+        seller: Addr::unchecked("satoshi"),
+        buyer: Addr::unchecked("buyer"),
+        denom: "uluna".to_string(),
+        price: Uint128::from(1_000000u128),
+        protocol_fee: Uint128::from(10000u128),
+        royalty_fee: Uint128::zero(),
+        time: 120,
+        is_primary_sale: false
+    };
+    let second_sale = Sale {
+        sale_id: 1,
+        auction_id: Uint128::from(1u128),
+        nft_contract: Addr::unchecked("nft"),
+        token_id: "bitcoin".to_string(),
+        seller: Addr::unchecked("buyer"),
+        buyer: Addr::unchecked("fliper"),
+        denom: "uluna".to_string(),
+        price: Uint128::from(2_000000u128),
+        protocol_fee: Uint128::from(20000u128),
+        royalty_fee: Uint128::zero(),
+        time: 86600,
+        is_primary_sale: false
+    };
+
+    let provenance = query_token_provenance(deps.as_ref(), "nft".to_string(), "bitcoin".to_string(), None, None, Some(true)).unwrap();
+    assert_eq!(provenance.sales, vec![second_sale.clone(), first_sale.clone()]);
+    let provenance = query_token_provenance(deps.as_ref(), "nft".to_string(), "bitcoin".to_string(), Some(1), None, Some(true)).unwrap();
+    assert_eq!(provenance.sales, vec![first_sale.clone()]);
+    let provenance = query_token_provenance(deps.as_ref(), "nft".to_string(), "ethereum".to_string(), None, None, None).unwrap();
+    assert_eq!(provenance.sales, vec![]);
+
+    let history = query_sales_history(deps.as_ref(), Some("nft".to_string()), None, None, Some(0), None, None).unwrap();
+    assert_eq!(history.sales, vec![second_sale.clone()]);
+    let history = query_sales_history(deps.as_ref(), None, Some("satoshi".to_string()), None, None, None, None).unwrap();
+    assert_eq!(history.sales, vec![first_sale.clone()]);
+    let history = query_sales_history(deps.as_ref(), None, None, Some("fliper".to_string()), None, None, None).unwrap();
+    assert_eq!(history.sales, vec![second_sale.clone()]);
+    let history = query_sales_history(deps.as_ref(), None, None, None, None, Some(1), None).unwrap();
+    assert_eq!(history.sales, vec![first_sale.clone()]);
+    query_sales_history(deps.as_ref(), Some("nft".to_string()), Some("satoshi".to_string()), None, None, None, None).unwrap_err();
+}
+
+#[test]
+fn collection_stats() {
+    let mut deps = mock_dependencies(&[]);
+    setup_contract(deps.as_mut(), vec!["uluna".to_string()]);
+
+    create_listing(deps.as_mut(), "satoshi", "bitcoin", 1_000000, true);
+    create_listing(deps.as_mut(), "vitalik", "ethereum", 2_000000, true);
+    create_listing(deps.as_mut(), "charles", "cardano", 3_000000, false);
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+    let stats = query_collection_stats(deps.as_ref(), env.clone(), "nft".to_string(), "uluna".to_string()).unwrap();
+    assert_eq!(stats.floor_price, Some(Uint128::from(1_000000u128)));
+    assert_eq!(stats.active_listings, 3);
+    assert_eq!(stats.num_sales, 0);
+
+    // buy the floor
+    let info = mock_info("buyer", &[Coin::new(1_000000, "uluna")]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::PlaceBid { auction_id: Uint128::zero(), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+    let stats = query_collection_stats(deps.as_ref(), env.clone(), "nft".to_string(), "uluna".to_string()).unwrap();
+    assert_eq!(stats.floor_price, Some(Uint128::from(2_000000u128)));
+    env.block.time = Timestamp::from_seconds(120);
+    let info = mock_info("random", &[]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Settle { auction_id: Uint128::zero() }).unwrap();
+    // cancel auction
+    let info = mock_info("charles", &[]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::CancelAuction { auction_id: Uint128::from(2u128) }).unwrap();
+
+    let stats = query_collection_stats(deps.as_ref(), env.clone(), "nft".to_string(), "uluna".to_string()).unwrap();
+    assert_eq!(stats.floor_price, Some(Uint128::from(2_000000u128)));
+    assert_eq!(stats.active_listings, 1);
+    assert_eq!(stats.num_sales, 1);
+    assert_eq!(stats.volume_24h, Uint128::from(1_000000u128));
+    assert_eq!(stats.volume_7d, Uint128::from(1_000000u128));
+    assert_eq!(stats.total_volume, Uint128::from(1_000000u128));
+    assert_eq!(stats.last_sale.unwrap().buyer, Addr::unchecked("buyer"));
+    // sale drops out of the 24h window
+    env.block.time = Timestamp::from_seconds(2 * 86400);
+    let stats = query_collection_stats(deps.as_ref(), env.clone(), "nft".to_string(), "uluna".to_string()).unwrap();
+    assert_eq!(stats.volume_24h, Uint128::zero());
+    assert_eq!(stats.volume_7d, Uint128::from(1_000000u128));
+    // another collection with higher volume
+    let nft_receive_msg = Cw721ReceiveMsg {
+        sender: "satoshi".into(),
+        token_id: "punk".to_string(),
+        msg: to_binary(&Cw721HookMsg::CreateAuction {
+            denom: "uluna".to_string(),
+            reserve_price: Uint128::from(5_000000u128),
+            is_instant_sale: true,
+            referrer: None
+        }).unwrap()
+    };
+    let info = mock_info("nft2", &[]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::ReceiveNft(nft_receive_msg)).unwrap();
+    let info = mock_info("buyer", &[Coin::new(5_000000, "uluna")]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::PlaceBid { auction_id: Uint128::from(3u128), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+    let info = mock_info("random", &[]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Settle { auction_id: Uint128::from(3u128) }).unwrap();
+
+    let top = query_top_collections_by_volume(deps.as_ref(), "uluna".to_string(), None, None).unwrap();
+    assert_eq!(
+        top.collections,
+        vec![
+            CollectionVolumeResponse {
+                nft_contract: "nft2".to_string(),
+                denom: "uluna".to_string(),
+                total_volume: Uint128::from(5_000000u128),
+                num_sales: 1
+            },
+            CollectionVolumeResponse {
+                nft_contract: "nft".to_string(),
+                denom: "uluna".to_string(),
+                total_volume: Uint128::from(1_000000u128),
+                num_sales: 1
+            }
+        ]
+    );
+    let top = query_top_collections_by_volume(deps.as_ref(), "uluna".to_string(), Some("nft2".to_string()), None).unwrap();
+    assert_eq!(top.collections.len(), 1);
+    assert_eq!(top.collections[0].nft_contract, "nft".to_string());
+}
+
+#[test]
+fn price_oracle() {
+    let mut deps = mock_dependencies(&[]);
+    setup_contract(deps.as_mut(), vec!["uluna".to_string()]);
+
+    sell_buy_now(&mut deps, "bitcoin", 1_000000, 1000);
+    sell_buy_now(&mut deps, "ethereum", 2_000000, 1500);
+    // wash trade far above the market
+    sell_buy_now(&mut deps, "cardano", 50_000000, 1800);
+
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(2000);
+    let price = query_price_observation(deps.as_ref(), env.clone(), "nft".to_string(), "uluna".to_string(), 1200).unwrap();
+    assert_eq!(price.twap, Uint128::from(11_100000u128));
+    assert_eq!(price.median, Uint128::from(2_000000u128));
+    assert_eq!(price.sample_count, 3);
+    // last price before the window is carried in
+    let price = query_price_observation(deps.as_ref(), env.clone(), "nft".to_string(), "uluna".to_string(), 400).unwrap();
+    assert_eq!(price.twap, Uint128::from(26_000000u128));
+    assert_eq!(price.median, Uint128::from(50_000000u128));
+    assert_eq!(price.sample_count, 1);
+
+    // random guy set oracle config
+    let set_oracle_msg = ExecuteMsg::SetOracleConfig {
+        max_observations: 100,
+        outlier_threshold: Some(Decimal::percent(50))
+    };
+    let info = mock_info("random", &[]);
+    let err = execute(deps.as_mut(), env.clone(), info, set_oracle_msg.clone()).unwrap_err();
+    match err {
+        ContractError::Unauthorized { .. } => {}
+        e => panic!("unexcted error: {}", e)
+    }
+    let info = mock_info("owner", &[]);
+    execute(deps.as_mut(), env.clone(), info, set_oracle_msg).unwrap();
+    let config = query_oracle_config(deps.as_ref()).unwrap();
+    assert_eq!(config.outlier_threshold, Some(Decimal::percent(50)));
+
+    // outlier is filtered
+    let price = query_price_observation(deps.as_ref(), env.clone(), "nft".to_string(), "uluna".to_string(), 1200).unwrap();
+    assert_eq!(price.twap, Uint128::from(1_500000u128));
+    assert_eq!(price.median, Uint128::from(1_500000u128));
+    assert_eq!(price.sample_count, 2);
+    // other denom has no observation
+    let price = query_price_observation(deps.as_ref(), env.clone(), "nft".to_string(), "uusd".to_string(), 1200).unwrap();
+    assert_eq!(price.twap, Uint128::zero());
+    assert_eq!(price.sample_count, 0);
+
+    // only keep the last two observations
+    let info = mock_info("owner", &[]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::SetOracleConfig { max_observations: 2, outlier_threshold: None }).unwrap();
+    sell_buy_now(&mut deps, "solana", 3_000000, 1900);
+    let price = query_price_observation(deps.as_ref(), env.clone(), "nft".to_string(), "uluna".to_string(), 1200).unwrap();
+    assert_eq!(price.sample_count, 2);
+    assert_eq!(price.median, Uint128::from(26_500000u128));
+}
+
+#[test]
+fn search_auctions() {
+    let mut deps = mock_dependencies(&[]);
+    setup_contract(deps.as_mut(), vec!["uluna".to_string()]);
+
+    create_listing(deps.as_mut(), "satoshi", "bitcoin", 1_000000, true);
+    create_listing(deps.as_mut(), "vitalik", "ethereum", 3_000000, true);
+    create_listing(deps.as_mut(), "charles", "cardano", 2_000000, false);
+    create_listing(deps.as_mut(), "gavin", "polkadot", 4_000000, false);
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+    let info = mock_info("bidder", &[Coin::new(2_000000, "uluna")]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::PlaceBid { auction_id: Uint128::from(2u128), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+
+    let ids = |auctions: Vec<AuctionResponse>| auctions.iter().map(|x| x.auction_id.u128()).collect::<Vec<u128>>();
+    // paginate by price
+    let page = query_search_auctions(deps.as_ref(), env.clone(), AuctionFilter::default(), Some(AuctionSortBy::Price), None, None, Some(2)).unwrap();
+    assert_eq!(ids(page.auctions), vec![0, 2]);
+    let page = query_search_auctions(deps.as_ref(), env.clone(), AuctionFilter::default(), Some(AuctionSortBy::Price), None, page.next_cursor, Some(2)).unwrap();
+    assert_eq!(ids(page.auctions), vec![1, 3]);
+    let page = query_search_auctions(deps.as_ref(), env.clone(), AuctionFilter::default(), Some(AuctionSortBy::Price), None, page.next_cursor, Some(2)).unwrap();
+    assert_eq!(page.auctions.len(), 0);
+    assert_eq!(page.next_cursor, None);
+    // status and combined filters
+    let filter = AuctionFilter {
+        status: Some(AuctionStatus::Live),
+        ..AuctionFilter::default()
+    };
+    let page = query_search_auctions(deps.as_ref(), env.clone(), filter, Some(AuctionSortBy::EndTime), None, None, None).unwrap();
+    assert_eq!(ids(page.auctions), vec![2]);
+    assert_eq!(page.next_cursor, None);
+    let filter = AuctionFilter {
+        auction_type: Some(AuctionType::BuyNow),
+        max_price: Some(Uint128::from(2_000000u128)),
+        ..AuctionFilter::default()
+    };
+    let page = query_search_auctions(deps.as_ref(), env.clone(), filter, Some(AuctionSortBy::Price), None, None, None).unwrap();
+    assert_eq!(ids(page.auctions), vec![0]);
+    let filter = AuctionFilter {
+        nft_contract: Some("nft".to_string()),
+        ..AuctionFilter::default()
+    };
+    let page = query_search_auctions(deps.as_ref(), env.clone(), filter, None, Some(true), None, None).unwrap();
+    assert_eq!(ids(page.auctions), vec![3, 2, 1, 0]);
+    let filter = AuctionFilter {
+        ending_before: Some(100 + 86400 + 1),
+        ..AuctionFilter::default()
+    };
+    let page = query_search_auctions(deps.as_ref(), env.clone(), filter, Some(AuctionSortBy::EndTime), None, None, None).unwrap();
+    assert_eq!(ids(page.auctions), vec![2]);
+
+    // settled auctions are only returned when asked for
+    let info = mock_info("buyer", &[Coin::new(1_000000, "uluna")]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::PlaceBid { auction_id: Uint128::zero(), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+    let info = mock_info("random", &[]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Settle { auction_id: Uint128::zero() }).unwrap();
+    let page = query_search_auctions(deps.as_ref(), env.clone(), AuctionFilter::default(), None, None, None, None).unwrap();
+    assert_eq!(ids(page.auctions), vec![1, 2, 3]);
+    let filter = AuctionFilter {
+        status: Some(AuctionStatus::Settled),
+        ..AuctionFilter::default()
+    };
+    let page = query_search_auctions(deps.as_ref(), env.clone(), filter, None, None, None, None).unwrap();
+    assert_eq!(ids(page.auctions), vec![0]);
+    // cancelled auction is removed from the search
+    let info = mock_info("gavin", &[]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::CancelAuction { auction_id: Uint128::from(3u128) }).unwrap();
+    let filter = AuctionFilter {
+        status: Some(AuctionStatus::NotStarted),
+        ..AuctionFilter::default()
+    };
+    let page = query_search_auctions(deps.as_ref(), env.clone(), filter, Some(AuctionSortBy::EndTime), None, None, None).unwrap();
+    assert_eq!(ids(page.auctions), vec![1]);
+}
+
+#[test]
+fn search_scan_limit() {
+    let mut deps = mock_dependencies(&[]);
+    setup_contract(deps.as_mut(), vec!["uluna".to_string()]);
+    for i in 0..MAX_SEARCH_SCAN {
+        create_listing(deps.as_mut(), "satoshi", &format!("token{}", i), 1_000000, true);
+    }
+    create_listing(deps.as_mut(), "satoshi", "bitcoin", 1_000000, false);
+
+    // the scan stops at the limit, the page is empty but the cursor continues it
+    let filter = AuctionFilter {
+        auction_type: Some(AuctionType::Auction),
+        ..AuctionFilter::default()
+    };
+    let page = query_search_auctions(deps.as_ref(), mock_env(), filter.clone(), None, None, None, None).unwrap();
+    assert!(page.auctions.is_empty());
+    assert!(page.next_cursor.is_some());
+    let page = query_search_auctions(deps.as_ref(), mock_env(), filter, None, None, page.next_cursor, None).unwrap();
+    assert_eq!(page.auctions[0].auction_id, Uint128::from(MAX_SEARCH_SCAN as u128));
+    assert_eq!(page.next_cursor, None);
+}
+
+#[test]
+fn auction_indices_follow_saves() {
+    let mut deps = mock_dependencies(&[]);
+    setup_contract(deps.as_mut(), vec!["uluna".to_string()]);
+
+    create_listing(deps.as_mut(), "satoshi", "bitcoin", 1_000000, false);
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+    let info = mock_info("alice", &[Coin::new(1_000000, "uluna")]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::PlaceBid { auction_id: Uint128::zero(), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+    let info = mock_info("bob", &[Coin::new(2_000000, "uluna")]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::PlaceBid { auction_id: Uint128::zero(), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+    // outbid bidder is moved off the auction
+    let auctions = query_auction_by_bidder(deps.as_ref(), "alice".to_string(), None, None).unwrap();
+    assert_eq!(auctions.auctions.len(), 0);
+    let auctions = query_auction_by_bidder(deps.as_ref(), "bob".to_string(), None, None).unwrap();
+    assert_eq!(auctions.auctions[0].amount, Uint128::from(2_000000u128));
+    let auctions = query_auction_by_amount(deps.as_ref(), "nft".to_string(), Uint128::from(1_500000u128), None).unwrap();
+    assert_eq!(auctions.auctions.len(), 1);
+    let auctions = query_not_started_auctions(deps.as_ref(), "nft".to_string(), None, None, None).unwrap();
+    assert_eq!(auctions.auctions.len(), 0);
+
+    // settled auction leaves the open indices and the token can be listed again
+    env.block.time = Timestamp::from_seconds(100 + 86400);
+    let info = mock_info("random", &[]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Settle { auction_id: Uint128::zero() }).unwrap();
+    let auctions = query_auction_by_seller(deps.as_ref(), "satoshi".to_string(), None).unwrap();
+    assert_eq!(auctions.auctions.len(), 0);
+    let auctions = query_auction_by_bidder(deps.as_ref(), "bob".to_string(), None, None).unwrap();
+    assert_eq!(auctions.auctions.len(), 0);
+    create_listing(deps.as_mut(), "bob", "bitcoin", 3_000000, true);
+    let auction = query_nft_auction_map(deps.as_ref(), "nft".to_string(), "bitcoin".to_string()).unwrap();
+    assert_eq!(auction.auction_id, Uint128::from(1u128));
+    // cancel removes every entry
+    let info = mock_info("bob", &[]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::CancelAuction { auction_id: Uint128::from(1u128) }).unwrap();
+    let err = query_nft_auction_map(deps.as_ref(), "nft".to_string(), "bitcoin".to_string()).unwrap_err();
+    match err {
+        StdError::NotFound { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    let auctions = query_auction_by_nft(deps.as_ref(), "nft".to_string(), None).unwrap();
+    assert_eq!(auctions.auctions.len(), 0);
+}
+
+#[test]
+fn activity_feed() {
+    let mut deps = mock_dependencies(&[]);
+    setup_contract(deps.as_mut(), vec!["uluna".to_string()]);
+
+    create_listing(deps.as_mut(), "satoshi", "bitcoin", 1_000000, false);
+    create_listing(deps.as_mut(), "satoshi", "ethereum", 1_000000, true);
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+    let info = mock_info("alice", &[Coin::new(1_000000, "uluna")]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::PlaceBid { auction_id: Uint128::zero(), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+    let info = mock_info("bob", &[Coin::new(2_000000, "uluna")]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::PlaceBid { auction_id: Uint128::zero(), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+    env.block.time = Timestamp::from_seconds(100 + 86400);
+    let info = mock_info("random", &[]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Settle { auction_id: Uint128::zero() }).unwrap();
+    let info = mock_info("satoshi", &[]);
+    execute(deps.as_mut(), env.clone(), info.clone(), ExecuteMsg::BatchUpdatePrice {
+        items: vec![PriceUpdate { auction_id: Uint128::from(1u128), reserve_price: Uint128::from(2_000000u128) }]
+    }).unwrap();
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::CancelAuction { auction_id: Uint128::from(1u128) }).unwrap();
+
+    let kinds = |address: &str| query_activity(deps.as_ref(), address.to_string(), None, None, None).unwrap()
+        .activities.iter().map(|x| x.kind.clone()).collect::<Vec<ActivityKind>>();
+    // newest first
+    assert_eq!(kinds("satoshi"), vec![ActivityKind::Cancelled, ActivityKind::PriceChanged, ActivityKind::Sold, ActivityKind::ListingCreated, ActivityKind::ListingCreated]);
+    assert_eq!(kinds("alice"), vec![ActivityKind::Outbid, ActivityKind::BidPlaced]);
+    assert_eq!(kinds("bob"), vec![ActivityKind::Won, ActivityKind::BidPlaced]);
+    let res = query_activity(deps.as_ref(), "alice".to_string(), Some(vec![ActivityKind::Outbid]), None, None).unwrap();
+    assert_eq!(res.activities.len(), 1);
+    assert_eq!(res.activities[0].counterparty, Some(Addr::unchecked("bob")));
+    assert_eq!(res.activities[0].amount, Uint128::from(1_000000u128));
+    // paginate
+    let res = query_activity(deps.as_ref(), "satoshi".to_string(), None, None, Some(3)).unwrap();
+    assert_eq!(res.activities.len(), 3);
+    assert_eq!(res.next_start_after, Some(res.activities[2].seq));
+    let res = query_activity(deps.as_ref(), "satoshi".to_string(), None, res.next_start_after, None).unwrap();
+    assert_eq!(res.activities.len(), 2);
+    assert_eq!(res.activities[1].seq, 0);
+    assert_eq!(res.activities[1].token_id, "bitcoin".to_string());
+    assert_eq!(res.next_start_after, None);
+}
+
+#[test]
+fn activity_scan_limit() {
+    let mut deps = mock_dependencies(&[]);
+    setup_contract(deps.as_mut(), vec!["uluna".to_string()]);
+    create_listing(deps.as_mut(), "satoshi", "bitcoin", 1_000000, true);
+    execute(deps.as_mut(), mock_env(), mock_info("satoshi", &[]), ExecuteMsg::CancelAuction { auction_id: Uint128::zero() }).unwrap();
+    for i in 0..MAX_ACTIVITY_SCAN {
+        create_listing(deps.as_mut(), "satoshi", &format!("token{}", i), 1_000000, true);
+    }
+
+    // the read stops at the limit, the page is empty but the next one continues it
+    let res = query_activity(deps.as_ref(), "satoshi".to_string(), Some(vec![ActivityKind::Cancelled]), None, None).unwrap();
+    assert!(res.activities.is_empty());
+    assert_eq!(res.next_start_after, Some(2));
+    let res = query_activity(deps.as_ref(), "satoshi".to_string(), Some(vec![ActivityKind::Cancelled]), res.next_start_after, None).unwrap();
+    assert_eq!(res.activities[0].seq, 1);
+    assert_eq!(res.next_start_after, None);
+}
+
+#[test]
+fn bid_history_and_bids_by_bidder() {
+    let mut deps = mock_dependencies(&[]);
+    setup_contract(deps.as_mut(), vec!["uluna".to_string()]);
+
+    create_listing(deps.as_mut(), "satoshi", "bitcoin", 1_000000, false);
+    create_listing(deps.as_mut(), "satoshi", "ethereum", 1_000000, false);
+    create_listing(deps.as_mut(), "satoshi", "cardano", 1_000000, true);
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+    // alice and bob bid on auction 0 in turns, bob wins auction 1, carol buys auction 2
+    for (bidder, amount) in vec![("alice", 1_000000), ("bob", 2_000000), ("alice", 3_000000), ("bob", 4_000000)] {
+        let info = mock_info(bidder, &[Coin::new(amount, "uluna")]);
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::PlaceBid { auction_id: Uint128::zero(), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+    }
+    let info = mock_info("bob", &[Coin::new(1_000000, "uluna")]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::PlaceBid { auction_id: Uint128::from(1u128), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+    let info = mock_info("carol", &[Coin::new(1_000000, "uluna")]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::PlaceBid { auction_id: Uint128::from(2u128), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+    env.block.time = Timestamp::from_seconds(100 + 86400);
+    let info = mock_info("random", &[]);
+    execute(deps.as_mut(), env.clone(), info.clone(), ExecuteMsg::Settle { auction_id: Uint128::from(1u128) }).unwrap();
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Settle { auction_id: Uint128::from(2u128) }).unwrap();
+
+    // every bid is kept
+    let bid_count = query_bid_number(deps.as_ref(), Uint128::zero()).unwrap();
+    assert_eq!(bid_count.count, Uint128::from(4u128));
+    let amounts = |bids: Vec<Bid>| bids.iter().map(|x| x.amount.u128()).collect::<Vec<u128>>();
+    let history = query_bid_history_by_auction_id(deps.as_ref(), Uint128::zero(), None, Some(3), None).unwrap();
+    assert_eq!(amounts(history.bids), vec![1_000000, 2_000000, 3_000000]);
+    let history = query_bid_history_by_auction_id(deps.as_ref(), Uint128::zero(), Some(Uint128::from(3u128)), None, None).unwrap();
+    assert_eq!(amounts(history.bids), vec![4_000000]);
+    let history = query_bid_history_by_auction_id(deps.as_ref(), Uint128::zero(), Some(Uint128::from(3u128)), None, Some(true)).unwrap();
+    assert_eq!(amounts(history.bids), vec![2_000000, 1_000000]);
+
+    let statuses = |bidder: &str| query_bids_by_bidder(deps.as_ref(), bidder.to_string(), None, None).unwrap()
+        .bids.iter().map(|x| x.status.clone()).collect::<Vec<BidStatus>>();
+    assert_eq!(statuses("alice"), vec![BidStatus::Outbid, BidStatus::Outbid]);
+    assert_eq!(statuses("bob"), vec![BidStatus::Outbid, BidStatus::Leading, BidStatus::Won]);
+    let bids = query_bids_by_bidder(deps.as_ref(), "bob".to_string(), Some((Uint128::zero(), Uint128::from(4u128))), None).unwrap();
+    assert_eq!(bids.bids.len(), 1);
+    assert_eq!(bids.bids[0].bid.auction_id, Uint128::from(1u128));
+    // buy now purchases are bids too
+    assert_eq!(query_bid_number(deps.as_ref(), Uint128::from(2u128)).unwrap().count, Uint128::from(1u128));
+    let bids = query_bids_by_bidder(deps.as_ref(), "carol".to_string(), None, None).unwrap();
+    assert_eq!(bids.bids.len(), 1);
+    assert_eq!(bids.bids[0].bid.auction_id, Uint128::from(2u128));
+    assert_eq!(bids.bids[0].status, BidStatus::Won);
+}
+
+#[test]
+fn settleable_auctions_and_batch_settle() {
+    let mut deps = mock_dependencies(&[]);
+    setup_contract(deps.as_mut(), vec!["uluna".to_string()]);
+
+    create_listing(deps.as_mut(), "satoshi", "bitcoin", 1_000000, false);
+    create_listing(deps.as_mut(), "satoshi", "ethereum", 1_000000, true);
+    create_listing(deps.as_mut(), "satoshi", "cardano", 1_000000, false);
+    create_listing(deps.as_mut(), "satoshi", "solana", 1_000000, false);
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(200);
+    let info = mock_info("buyer", &[Coin::new(1_000000, "uluna")]);
+    execute(deps.as_mut(), env.clone(), info.clone(), ExecuteMsg::PlaceBid { auction_id: Uint128::from(1u128), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+    execute(deps.as_mut(), env.clone(), info.clone(), ExecuteMsg::PlaceBid { auction_id: Uint128::from(3u128), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+    env.block.time = Timestamp::from_seconds(100);
+    execute(deps.as_mut(), env.clone(), info.clone(), ExecuteMsg::PlaceBid { auction_id: Uint128::zero(), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+
+    let ids = |auctions: Vec<AuctionResponse>| auctions.iter().map(|x| x.auction_id.u128()).collect::<Vec<u128>>();
+    // only the bought buy now listing has ended
+    env.block.time = Timestamp::from_seconds(300);
+    let res = query_settleable_auctions(deps.as_ref(), env.clone(), None, None).unwrap();
+    assert_eq!(ids(res.auctions), vec![1]);
+    // ordered by end time across collections
+    env.block.time = Timestamp::from_seconds(200 + 86400);
+    let res = query_settleable_auctions(deps.as_ref(), env.clone(), None, Some(2)).unwrap();
+    assert_eq!(ids(res.auctions), vec![1, 0]);
+    let res = query_settleable_auctions(deps.as_ref(), env.clone(), Some(Uint128::zero()), None).unwrap();
+    assert_eq!(ids(res.auctions), vec![3]);
+
+    // auction 2 has no bid, the others are settled in their own sub messages
+    let res = execute(deps.as_mut(), env.clone(), mock_info("keeper", &[]), ExecuteMsg::BatchSettle {
+        auction_ids: vec![Uint128::zero(), Uint128::from(3u128), Uint128::from(2u128), Uint128::from(1u128)]
+    }).unwrap();
+    assert_eq!(res.attributes[1].value, "3".to_string());
+    assert_eq!(res.events.len(), 1);
+    assert_eq!(res.events[0].ty, "settle_skipped".to_string());
+    assert_eq!(res.events[0].attributes[0].value, "2".to_string());
+    assert_eq!(res.messages.len(), 3);
+    assert_eq!(res.messages[1].reply_on, ReplyOn::Error);
+    assert_eq!(res.messages[1].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: MOCK_CONTRACT_ADDR.to_string(),
+        msg: to_binary(&ExecuteMsg::SettleBatchItem {
+            auction_id: Uint128::from(3u128),
+            keeper: Some("keeper".to_string())
+        }).unwrap(),
+        funds: vec![]
+    }));
+    let failing_reply_id = res.messages[1].id;
+
+    // only this contract runs the items
+    let item = |auction_id: u128| ExecuteMsg::SettleBatchItem {
+        auction_id: Uint128::from(auction_id),
+        keeper: Some("keeper".to_string())
+    };
+    let err = execute(deps.as_mut(), env.clone(), mock_info("keeper", &[]), item(0)).unwrap_err();
+    match err {
+        ContractError::Unauthorized { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    // each settlement pays protocol fee and seller and transfers the nft with its settle hook
+    let res = execute(deps.as_mut(), env.clone(), mock_info(MOCK_CONTRACT_ADDR, &[]), item(0)).unwrap();
+    assert_eq!(res.messages.len(), 4);
+    // the receiver of auction 3 rejects the nft, only that item is reverted and it is recorded
+    let res = reply(deps.as_mut(), env.clone(), Reply {
+        id: failing_reply_id,
+        result: SubMsgResult::Err("receiver rejected the nft".to_string())
+    }).unwrap();
+    assert_eq!(res.attributes[0].value, "settle_failed".to_string());
+    assert_eq!(res.attributes[1].value, "3".to_string());
+    execute(deps.as_mut(), env.clone(), mock_info(MOCK_CONTRACT_ADDR, &[]), item(1)).unwrap();
+    let res = query_failed_settlements(deps.as_ref(), None, None).unwrap();
+    assert_eq!(res.settlements, vec![FailedSettlementResponse {
+        auction_id: Uint128::from(3u128),
+        reason: "receiver rejected the nft".to_string(),
+        time: env.block.time.seconds(),
+        attempts: 1,
+        retry_time: env.block.time.seconds() + TICK_RETRY_DELAY
+    }]);
+    let res = query_settleable_auctions(deps.as_ref(), env.clone(), None, None).unwrap();
+    assert_eq!(ids(res.auctions), vec![3]);
+
+    // a later settlement clears the failure
+    execute(deps.as_mut(), env.clone(), mock_info("keeper", &[]), ExecuteMsg::Settle { auction_id: Uint128::from(3u128) }).unwrap();
+    assert!(query_failed_settlements(deps.as_ref(), None, None).unwrap().settlements.is_empty());
+
+    let info = mock_info("keeper", &[]);
+    let err = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::BatchSettle { auction_ids: vec![] }).unwrap_err();
+    match err {
+        ContractError::InvalidAuction { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+}
+
+#[test]
+fn keeper_reward() {
+    let mut deps = mock_dependencies(&[]);
+    setup_contract(deps.as_mut(), vec!["uluna".to_string()]);
+
+    // only owner can set the reward, share can not exceed protocol fee
+    let msg = ExecuteMsg::SetKeeperReward {
+        reward: Some(KeeperReward::ProtocolFeeShare { share: Decimal::percent(50) }),
+        min_delay: 3600
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info("keeper", &[]), msg.clone()).unwrap_err();
+    match err {
+        ContractError::Unauthorized { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    let err = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), ExecuteMsg::SetKeeperReward {
+        reward: Some(KeeperReward::ProtocolFeeShare { share: Decimal::percent(150) }),
+        min_delay: 3600
+    }).unwrap_err();
+    match err {
+        ContractError::InvalidConfigChange { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+    let res = query_keeper_reward(deps.as_ref()).unwrap();
+    assert_eq!(res.min_delay, 3600);
+    let res = query_calculate_price(deps.as_ref(), mock_env(), "nft".to_string(), "bitcoin".to_string(), Uint128::from(1_000000u128)).unwrap();
+    assert_eq!(res.keeper_reward, Uint128::from(5000u128));
+
+    create_listing(deps.as_mut(), "satoshi", "bitcoin", 1_000000, true);
+    create_listing(deps.as_mut(), "satoshi", "ethereum", 1_000000, true);
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(1000);
+    let info = mock_info("buyer", &[Coin::new(1_000000, "uluna")]);
+    execute(deps.as_mut(), env.clone(), info.clone(), ExecuteMsg::PlaceBid { auction_id: Uint128::zero(), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::PlaceBid { auction_id: Uint128::from(1u128), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+    // settled by the seller right away, nothing is paid to the keeper
+    let res = execute(deps.as_mut(), env.clone(), mock_info("satoshi", &[]), ExecuteMsg::Settle { auction_id: Uint128::zero() }).unwrap();
+    assert_eq!(res.attributes.last().unwrap().value, "0".to_string());
+    assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: "collector".to_string(),
+        amount: vec![Coin::new(10000, "uluna")]
+    }));
+
+    // the preview pays nothing until the delay after the end time has passed
+    let preview = |deps: &OwnedDeps<MockStorage, MockApi, WasmMockQuerier>, env: Env| query_calculate_price(deps.as_ref(), env,
+        "nft".to_string(), "ethereum".to_string(), Uint128::from(1_000000u128)).unwrap().keeper_reward;
+    assert_eq!(preview(&deps, env.clone()), Uint128::zero());
+
+    // half of the protocol fee goes to the keeper after the delay
+    env.block.time = Timestamp::from_seconds(1000 + 3600);
+    assert_eq!(preview(&deps, env.clone()), Uint128::from(5000u128));
+    let res = execute(deps.as_mut(), env.clone(), mock_info("keeper", &[]), ExecuteMsg::Settle { auction_id: Uint128::from(1u128) }).unwrap();
+    assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: "collector".to_string(),
+        amount: vec![Coin::new(5000, "uluna")]
+    }));
+    assert_eq!(res.messages[1].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: "keeper".to_string(),
+        amount: vec![Coin::new(5000, "uluna")]
+    }));
+
+    // flat reward is paid in the denom of the listing and capped at the protocol fee
+    execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), ExecuteMsg::SetKeeperReward {
+        reward: Some(KeeperReward::Flat { amounts: vec![Coin::new(50000, "uluna")] }),
+        min_delay: 0
+    }).unwrap();
+    let res = query_calculate_price(deps.as_ref(), mock_env(), "nft".to_string(), "cardano".to_string(), Uint128::from(1_000000u128)).unwrap();
+    assert_eq!(res.keeper_reward, Uint128::zero());
+    create_listing(deps.as_mut(), "satoshi", "cardano", 1_000000, true);
+    let res = query_calculate_price(deps.as_ref(), mock_env(), "nft".to_string(), "cardano".to_string(), Uint128::from(1_000000u128)).unwrap();
+    assert_eq!(res.keeper_reward, Uint128::from(10000u128));
+
+    // closing an expired listing is not rewarded, the seller sets its price and expiry
+    execute(deps.as_mut(), mock_env(), mock_info("satoshi", &[]), ExecuteMsg::UpdateListing {
+        auction_id: Uint128::from(2u128),
+        reserve_price: None,
+        denom: None,
+        buy_now_price: None,
+        expires_at: Some(mock_env().block.time.seconds() + 100)
+    }).unwrap();
+    let err = execute(deps.as_mut(), mock_env(), mock_info("keeper", &[]), ExecuteMsg::ReclaimExpired { auction_id: Uint128::from(2u128) }).unwrap_err();
+    match err {
+        ContractError::InvalidAuction { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    ACCRUED_FEES.save(deps.as_mut().storage, "uluna", &Uint128::from(4000u128)).unwrap();
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(100);
+    let res = execute(deps.as_mut(), env, mock_info("keeper", &[]), ExecuteMsg::ReclaimExpired { auction_id: Uint128::from(2u128) }).unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(res.messages[0].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: "nft".into(),
+        msg: to_binary(&Cw721ExecuteMsg::TransferNft {
+            token_id: "cardano".to_string(),
+            recipient: "satoshi".into()
+        }).unwrap(),
+        funds: vec![]
+    }));
+    assert_eq!(query_accrued_fees(deps.as_ref()).unwrap().fees, vec![Coin::new(4000, "uluna")]);
+    assert!(query_auction(deps.as_ref(), Uint128::from(2u128)).is_err());
+    execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), ExecuteMsg::SetKeeperReward { reward: None, min_delay: 0 }).unwrap();
+    let res = query_keeper_reward(deps.as_ref()).unwrap();
+    assert_eq!(res.reward, None);
+}
+
+#[test]
+fn sudo_tick() {
+    let mut deps = mock_dependencies(&[]);
+    setup_contract(deps.as_mut(), vec!["uluna".to_string()]);
+    // keepers are not paid for chain settlement
+    execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), ExecuteMsg::SetKeeperReward {
+        reward: Some(KeeperReward::ProtocolFeeShare { share: Decimal::percent(50) }),
+        min_delay: 0
+    }).unwrap();
+
+    // 12 bought buy now listings and one running auction
+    let info = mock_info("buyer", &[Coin::new(1_000000, "uluna")]);
+    for i in 0..12u64 {
+        create_listing(deps.as_mut(), "satoshi", &format!("token{}", i), 1_000000, true);
+        let mut env = mock_env();
+        env.block.time = Timestamp::from_seconds(100 + i);
+        execute(deps.as_mut(), env, info.clone(), ExecuteMsg::PlaceBid { auction_id: Uint128::from(i as u128), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+    }
+    create_listing(deps.as_mut(), "satoshi", "bitcoin", 1_000000, false);
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::PlaceBid { auction_id: Uint128::from(12u128), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+
+    env.block.time = Timestamp::from_seconds(1000);
+    let res = sudo(deps.as_mut(), env.clone(), SudoMsg::Tick {}).unwrap();
+    assert_eq!(res.attributes[1].value, "10".to_string());
+    assert_eq!(res.messages.len(), 10);
+    assert!(res.messages.iter().all(|x| x.reply_on == ReplyOn::Error));
+    let settled = run_self_calls(&mut deps, &env, &res.messages);
+    // protocol fee, seller, nft transfer and settle hook for each auction
+    assert_eq!(settled.len(), 10);
+    assert_eq!(settled[0].messages.len(), 4);
+    assert_eq!(settled[0].messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: "collector".to_string(),
+        amount: vec![Coin::new(10000, "uluna")]
+    }));
+    assert!(query_auction(deps.as_ref(), Uint128::from(9u128)).unwrap().is_settled);
+    assert!(!query_auction(deps.as_ref(), Uint128::from(10u128)).unwrap().is_settled);
+
+    // the next tick goes on from the cursor
+    let res = sudo(deps.as_mut(), env.clone(), SudoMsg::Tick {}).unwrap();
+    assert_eq!(res.attributes[1].value, "2".to_string());
+    run_self_calls(&mut deps, &env, &res.messages);
+    // the running auction is left alone
+    let res = sudo(deps.as_mut(), env.clone(), SudoMsg::Tick {}).unwrap();
+    assert_eq!(res.attributes[1].value, "0".to_string());
+    assert!(res.messages.is_empty());
+    assert!(!query_auction(deps.as_ref(), Uint128::from(12u128)).unwrap().is_settled);
+
+    env.block.time = Timestamp::from_seconds(100 + 86400);
+    let res = sudo(deps.as_mut(), env.clone(), SudoMsg::Tick {}).unwrap();
+    assert_eq!(res.attributes[1].value, "1".to_string());
+    run_self_calls(&mut deps, &env, &res.messages);
+
+    // an expired listing, an approval listing whose approval is revoked and a bought listing that fails to settle
+    create_listing(deps.as_mut(), "satoshi", "expired", 1_000000, true);
+    execute(deps.as_mut(), env.clone(), mock_info("satoshi", &[]), ExecuteMsg::UpdateListing {
+        auction_id: Uint128::from(13u128),
+        reserve_price: None,
+        denom: None,
+        buy_now_price: None,
+        expires_at: Some(env.block.time.seconds() + 100)
+    }).unwrap();
+    deps.querier.with_nft_owner("nft".to_string(), "approved".to_string(), "satoshi".to_string());
+    deps.querier.with_nft_approval("nft".to_string(), "approved".to_string(), Some(MOCK_CONTRACT_ADDR.to_string()));
+    execute(deps.as_mut(), env.clone(), mock_info("satoshi", &[]), ExecuteMsg::CreateApprovalListing {
+        nft_contract: "nft".to_string(),
+        token_id: "approved".to_string(),
+        denom: "uluna".to_string(),
+        reserve_price: Uint128::from(1_000000u128),
+        is_instant_sale: true,
+        referrer: None
+    }).unwrap();
+    deps.querier.with_nft_approval("nft".to_string(), "approved".to_string(), None);
+    create_listing(deps.as_mut(), "satoshi", "failing", 1_000000, true);
+    let info = mock_info("buyer", &[Coin::new(1_000000, "uluna")]);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::PlaceBid { auction_id: Uint128::from(15u128), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+
+    env.block.time = env.block.time.plus_seconds(100);
+    let res = sudo(deps.as_mut(), env.clone(), SudoMsg::Tick {}).unwrap();
+    assert_eq!(res.attributes[1].value, "1".to_string());
+    assert_eq!(res.attributes[2].value, "2".to_string());
+    assert_eq!(res.messages[0].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: MOCK_CONTRACT_ADDR.to_string(),
+        msg: to_binary(&ExecuteMsg::ReclaimExpired { auction_id: Uint128::from(13u128) }).unwrap(),
+        funds: vec![]
+    }));
+    assert_eq!(res.messages[1].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: MOCK_CONTRACT_ADDR.to_string(),
+        msg: to_binary(&ExecuteMsg::InvalidateListing { auction_id: Uint128::from(14u128) }).unwrap(),
+        funds: vec![]
+    }));
+    let closed = run_self_calls(&mut deps, &env, &res.messages[..2]);
+    // the escrowed nft goes back to the seller, the approval listing has nothing to return
+    assert_eq!(closed[0].messages[0].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: "nft".into(),
+        msg: to_binary(&Cw721ExecuteMsg::TransferNft {
+            token_id: "expired".to_string(),
+            recipient: "satoshi".into()
+        }).unwrap(),
+        funds: vec![]
+    }));
+    assert!(closed[1].messages.is_empty());
+    assert!(query_auction(deps.as_ref(), Uint128::from(13u128)).is_err());
+    assert!(query_auction(deps.as_ref(), Uint128::from(14u128)).is_err());
+    reply(deps.as_mut(), env.clone(), Reply {
+        id: res.messages[2].id,
+        result: SubMsgResult::Err("receiver rejected the nft".to_string())
+    }).unwrap();
+
+    // the failed settlement is left alone until the retry delay has passed
+    let res = sudo(deps.as_mut(), env.clone(), SudoMsg::Tick {}).unwrap();
+    assert_eq!(res.attributes[1].value, "0".to_string());
+    assert!(res.messages.is_empty());
+    env.block.time = env.block.time.plus_seconds(TICK_RETRY_DELAY);
+    let res = sudo(deps.as_mut(), env.clone(), SudoMsg::Tick {}).unwrap();
+    assert_eq!(res.attributes[1].value, "1".to_string());
+    reply(deps.as_mut(), env.clone(), Reply {
+        id: res.messages[0].id,
+        result: SubMsgResult::Err("receiver rejected the nft".to_string())
+    }).unwrap();
+    // a second failure doubles the delay
+    let failed = query_failed_settlements(deps.as_ref(), None, None).unwrap().settlements;
+    assert_eq!(failed[0].attempts, 2);
+    assert_eq!(failed[0].retry_time, env.block.time.seconds() + 2 * TICK_RETRY_DELAY);
+    env.block.time = env.block.time.plus_seconds(TICK_RETRY_DELAY);
+    let res = sudo(deps.as_mut(), env.clone(), SudoMsg::Tick {}).unwrap();
+    assert!(res.messages.is_empty());
+    execute(deps.as_mut(), env, mock_info("keeper", &[]), ExecuteMsg::Settle { auction_id: Uint128::from(15u128) }).unwrap();
+    assert!(query_failed_settlements(deps.as_ref(), None, None).unwrap().settlements.is_empty());
+}
+
+#[test]
+fn approval_listing() {
+    let mut deps = mock_dependencies(&[]);
+    setup_contract(deps.as_mut(), vec!["uluna".to_string()]);
+    for token_id in ["bitcoin", "ethereum", "cardano"] {
+        deps.querier.with_nft_owner("nft".to_string(), token_id.to_string(), "satoshi".to_string());
+    }
+    let listing = |token_id: &str, is_instant_sale: bool| ExecuteMsg::CreateApprovalListing {
+        nft_contract: "nft".to_string(),
+        token_id: token_id.to_string(),
+        denom: "uluna".to_string(),
+        reserve_price: Uint128::from(1_000000u128),
+        is_instant_sale: is_instant_sale,
+        referrer: None
+    };
+
+    // only the owner can list, and only after approving the marketplace
+    let err = execute(deps.as_mut(), mock_env(), mock_info("buyer", &[]), listing("bitcoin", true)).unwrap_err();
+    match err {
+        ContractError::Unauthorized { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    let err = execute(deps.as_mut(), mock_env(), mock_info("satoshi", &[]), listing("bitcoin", true)).unwrap_err();
+    match err {
+        ContractError::InvalidAsset { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    deps.querier.with_nft_approval("nft".to_string(), "bitcoin".to_string(), Some(MOCK_CONTRACT_ADDR.to_string()));
+    execute(deps.as_mut(), mock_env(), mock_info("satoshi", &[]), listing("bitcoin", true)).unwrap();
+    assert!(query_auction(deps.as_ref(), Uint128::zero()).unwrap().is_approval_listing);
+
+    // nft moves from the seller to the buyer on settlement
+    let info = mock_info("buyer", &[Coin::new(1_000000, "uluna")]);
+    execute(deps.as_mut(), mock_env(), info.clone(), ExecuteMsg::PlaceBid { auction_id: Uint128::zero(), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+    let res = execute(deps.as_mut(), mock_env(), mock_info("random", &[]), ExecuteMsg::Settle { auction_id: Uint128::zero() }).unwrap();
+    assert_eq!(res.messages[2].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: "nft".into(),
+        msg: to_binary(&Cw721ExecuteMsg::TransferNft {
+            token_id: "bitcoin".to_string(),
+            recipient: "buyer".into()
+        }).unwrap(),
+        funds: vec![]
+    }));
+
+    // approve all also allows listing, the listing breaks once it is revoked
+    deps.querier.with_nft_operator("nft".to_string(), "satoshi".to_string(), Some(MOCK_CONTRACT_ADDR.to_string()));
+    execute(deps.as_mut(), mock_env(), mock_info("satoshi", &[]), listing("ethereum", false)).unwrap();
+    execute(deps.as_mut(), mock_env(), info.clone(), ExecuteMsg::PlaceBid { auction_id: Uint128::from(1u128), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+    let err = execute(deps.as_mut(), mock_env(), mock_info("random", &[]), ExecuteMsg::InvalidateListing { auction_id: Uint128::from(1u128) }).unwrap_err();
+    match err {
+        ContractError::InvalidAuction { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    deps.querier.with_nft_operator("nft".to_string(), "satoshi".to_string(), None);
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(env.block.time.seconds() + 86400);
+    let err = execute(deps.as_mut(), env.clone(), mock_info("random", &[]), ExecuteMsg::Settle { auction_id: Uint128::from(1u128) }).unwrap_err();
+    match err {
+        ContractError::InvalidAuction { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    // the bidder gets the bid back
+    let res = execute(deps.as_mut(), env, mock_info("random", &[]), ExecuteMsg::InvalidateListing { auction_id: Uint128::from(1u128) }).unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: "buyer".to_string(),
+        amount: vec![Coin::new(1_000000, "uluna")]
+    }));
+    assert!(query_auction(deps.as_ref(), Uint128::from(1u128)).is_err());
+
+    // a listing whose nft was transferred away can't be bought
+    deps.querier.with_nft_approval("nft".to_string(), "cardano".to_string(), Some(MOCK_CONTRACT_ADDR.to_string()));
+    execute(deps.as_mut(), mock_env(), mock_info("satoshi", &[]), listing("cardano", true)).unwrap();
+    deps.querier.with_nft_owner("nft".to_string(), "cardano".to_string(), "fliper".to_string());
+    let err = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::PlaceBid { auction_id: Uint128::from(2u128), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap_err();
+    match err {
+        ContractError::InvalidAuction { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    // nothing to send back when the seller cancels
+    let res = execute(deps.as_mut(), mock_env(), mock_info("satoshi", &[]), ExecuteMsg::CancelAuction { auction_id: Uint128::from(2u128) }).unwrap();
+    assert!(res.messages.is_empty());
+}
+
+fn sign_order(key: &SigningKey, order: &SignedOrder) -> Binary {
+    // the key hashes the serialized order with sha256, which is the order digest
+    let signature: k256::ecdsa::Signature = key.sign(&to_vec(order).unwrap());
+    Binary::from(signature.as_ref())
+}
+
+#[test]
+fn signed_orders() {
+    let mut deps = mock_dependencies(&[]);
+    setup_contract(deps.as_mut(), vec!["uluna".to_string()]);
+    let seller_key = SigningKey::from_bytes(&[1u8; 32]).unwrap();
+    let buyer_key = SigningKey::from_bytes(&[2u8; 32]).unwrap();
+    for token_id in ["bitcoin", "ethereum", "cardano"] {
+        deps.querier.with_nft_owner("nft".to_string(), token_id.to_string(), "satoshi".to_string());
+    }
+    deps.querier.with_nft_operator("nft".to_string(), "satoshi".to_string(), Some(MOCK_CONTRACT_ADDR.to_string()));
+
+    let err = execute(deps.as_mut(), mock_env(), mock_info("satoshi", &[]), ExecuteMsg::RegisterPubkey { pubkey: Binary::from(vec![1u8; 20]) }).unwrap_err();
+    match err {
+        ContractError::InvalidOrder { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    for (address, key) in [("satoshi", &seller_key), ("buyer", &buyer_key)] {
+        let pubkey = Binary::from(key.verifying_key().to_bytes().as_slice());
+        execute(deps.as_mut(), mock_env(), mock_info(address, &[]), ExecuteMsg::RegisterPubkey { pubkey: pubkey }).unwrap();
+    }
+    let order = |side: OrderSide, signer: &str, token_id: &str| SignedOrder {
+        side: side,
+        signer: signer.to_string(),
+        nft_contract: "nft".to_string(),
+        token_id: token_id.to_string(),
+        denom: "uluna".to_string(),
+        price: Uint128::from(1_000000u128),
+        expiry: mock_env().block.time.seconds() + 3600,
+        nonce: 0,
+        chain_id: mock_env().block.chain_id,
+        contract_address: MOCK_CONTRACT_ADDR.to_string()
+    };
+
+    // ask signed by the seller, filled by the buyer with the price
+    let ask = order(OrderSide::Ask, "satoshi", "bitcoin");
+    let info = mock_info("buyer", &[Coin::new(1_000000, "uluna")]);
+    let err = execute(deps.as_mut(), mock_env(), info.clone(), ExecuteMsg::FulfillOrder { order: ask.clone(), signature: sign_order(&buyer_key, &ask) }).unwrap_err();
+    match err {
+        ContractError::InvalidOrder { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), ExecuteMsg::FulfillOrder { order: ask.clone(), signature: sign_order(&seller_key, &ask) }).unwrap();
+    assert_eq!(res.messages.len(), 4);
+    assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: "collector".to_string(),
+        amount: vec![Coin::new(10000, "uluna")]
+    }));
+    assert_eq!(res.messages[1].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: "satoshi".to_string(),
+        amount: vec![Coin::new(990000, "uluna")]
+    }));
+    let res = query_order_digest(deps.as_ref(), ask.clone()).unwrap();
+    assert_eq!(res.digest, Binary::from(order_digest(&ask).unwrap()));
+    assert!(res.is_closed);
+    assert_eq!(query_sales_history(deps.as_ref(), None, None, None, None, None, None).unwrap().sales.len(), 1);
+    // an order is filled once
+    let err = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::FulfillOrder { order: ask.clone(), signature: sign_order(&seller_key, &ask) }).unwrap_err();
+    match err {
+        ContractError::InvalidOrder { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+
+    // bid signed by the buyer is paid from the deposit, filled by the owner
+    let info = mock_info("buyer", &[Coin::new(2_000000, "uluna")]);
+    execute(deps.as_mut(), mock_env(), info, ExecuteMsg::DepositOrderFunds {}).unwrap();
+    let bid = order(OrderSide::Bid, "buyer", "ethereum");
+    let res = execute(deps.as_mut(), mock_env(), mock_info("satoshi", &[]), ExecuteMsg::FulfillOrder { order: bid.clone(), signature: sign_order(&buyer_key, &bid) }).unwrap();
+    assert_eq!(res.messages[2].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: "nft".into(),
+        msg: to_binary(&Cw721ExecuteMsg::TransferNft {
+            token_id: "ethereum".to_string(),
+            recipient: "buyer".into()
+        }).unwrap(),
+        funds: vec![]
+    }));
+    assert_eq!(query_order_account(deps.as_ref(), "buyer".to_string()).unwrap(), OrderAccountResponse {
+        pubkey: Some(Binary::from(buyer_key.verifying_key().to_bytes().as_slice())),
+        nonce: 0,
+        deposits: vec![Coin::new(1_000000, "uluna")]
+    });
+    let res = query_activity(deps.as_ref(), "buyer".to_string(), Some(vec![ActivityKind::OfferAccepted]), None, None).unwrap();
+    assert_eq!(res.activities.len(), 1);
+    assert_eq!(res.activities[0].counterparty, Some(Addr::unchecked("satoshi")));
+
+    // incrementing the nonce cancels every open order of the signer
+    let bid = order(OrderSide::Bid, "buyer", "cardano");
+    execute(deps.as_mut(), mock_env(), mock_info("buyer", &[]), ExecuteMsg::IncrementNonce {}).unwrap();
+    let err = execute(deps.as_mut(), mock_env(), mock_info("satoshi", &[]), ExecuteMsg::FulfillOrder { order: bid.clone(), signature: sign_order(&buyer_key, &bid) }).unwrap_err();
+    match err {
+        ContractError::InvalidOrder { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    // single order cancellation, only by its signer
+    let ask = order(OrderSide::Ask, "satoshi", "cardano");
+    let err = execute(deps.as_mut(), mock_env(), mock_info("buyer", &[]), ExecuteMsg::CancelOrder { order: ask.clone() }).unwrap_err();
+    match err {
+        ContractError::Unauthorized { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    execute(deps.as_mut(), mock_env(), mock_info("satoshi", &[]), ExecuteMsg::CancelOrder { order: ask.clone() }).unwrap();
+    let info = mock_info("buyer", &[Coin::new(1_000000, "uluna")]);
+    let err = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::FulfillOrder { order: ask.clone(), signature: sign_order(&seller_key, &ask) }).unwrap_err();
+    match err {
+        ContractError::InvalidOrder { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("buyer", &[]), ExecuteMsg::WithdrawOrderFunds {
+        denom: "uluna".to_string(),
+        amount: Uint128::from(1_000000u128)
+    }).unwrap();
+    assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: "buyer".to_string(),
+        amount: vec![Coin::new(1_000000, "uluna")]
+    }));
+    assert!(query_order_account(deps.as_ref(), "buyer".to_string()).unwrap().deposits.is_empty());
+}
+
+#[test]
+fn batch_listing() {
+    let mut deps = mock_dependencies(&[]);
+    setup_contract(deps.as_mut(), vec!["uluna".to_string()]);
+    for (nft_contract, token_id) in [("nft", "bitcoin"), ("nft", "ethereum"), ("punk", "cardano")] {
+        deps.querier.with_nft_owner(nft_contract.to_string(), token_id.to_string(), "satoshi".to_string());
+        deps.querier.with_nft_operator(nft_contract.to_string(), "satoshi".to_string(), Some(MOCK_CONTRACT_ADDR.to_string()));
+    }
+    deps.querier.with_nft_owner("nft".to_string(), "solana".to_string(), "hal".to_string());
+    execute(deps.as_mut(), mock_env(), mock_info("frontend", &[]), ExecuteMsg::RegisterReferrer {}).unwrap();
+    let item = |nft_contract: &str, token_id: &str, reserve_price: u128, is_instant_sale: bool| BatchListItem {
+        nft_contract: nft_contract.to_string(),
+        token_id: token_id.to_string(),
+        denom: "uluna".to_string(),
+        reserve_price: Uint128::from(reserve_price),
+        is_instant_sale: is_instant_sale,
+        referrer: None
+    };
+    // solana is not owned by the seller
+    let items = vec![
+        item("nft", "bitcoin", 1_000000, false),
+        item("nft", "ethereum", 2_000000, true),
+        item("nft", "solana", 2_000000, true),
+        BatchListItem { referrer: Some("frontend".to_string()), ..item("punk", "cardano", 3_000000, true) }
+    ];
+
+    // max batch size is set by the owner
+    let err = execute(deps.as_mut(), mock_env(), mock_info("satoshi", &[]), ExecuteMsg::SetMaxBatchSize { max_batch_size: 2 }).unwrap_err();
+    match err {
+        ContractError::Unauthorized { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    let err = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), ExecuteMsg::SetMaxBatchSize { max_batch_size: 0 }).unwrap_err();
+    match err {
+        ContractError::InvalidConfigChange { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), ExecuteMsg::SetMaxBatchSize { max_batch_size: 2 }).unwrap();
+    assert_eq!(query_max_batch_size(deps.as_ref()).unwrap().max_batch_size, 2);
+    let err = execute(deps.as_mut(), mock_env(), mock_info("satoshi", &[]), ExecuteMsg::BatchList { items: items.clone() }).unwrap_err();
+    match err {
+        ContractError::InvalidAuction { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+
+    // listings across collections with their own prices, invalid items are skipped
+    execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), ExecuteMsg::SetMaxBatchSize { max_batch_size: 4 }).unwrap();
+    let res = execute(deps.as_mut(), mock_env(), mock_info("satoshi", &[]), ExecuteMsg::BatchList { items: items }).unwrap();
+    assert_eq!(res.events.len(), 4);
+    assert_eq!(res.events[2].ty, "list_skipped".to_string());
+    assert_eq!(res.events[2].attributes[1].value, "solana".to_string());
+    assert_eq!(res.attributes[1].value, "3".to_string());
+    let auction = query_auction(deps.as_ref(), Uint128::from(2u128)).unwrap();
+    assert_eq!(auction.nft_contract, "punk".to_string());
+    assert_eq!(auction.amount, Uint128::from(3_000000u128));
+    assert_eq!(auction.listing_referrer, Some("frontend".to_string()));
+    assert!(auction.is_approval_listing);
+
+    // prices of listings without a bid can be updated by the seller
+    let info = mock_info("buyer", &[Coin::new(3_000000, "uluna")]);
+    execute(deps.as_mut(), mock_env(), info, ExecuteMsg::PlaceBid { auction_id: Uint128::from(2u128), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+    let update = |auction_id: u128, reserve_price: u128| PriceUpdate {
+        auction_id: Uint128::from(auction_id),
+        reserve_price: Uint128::from(reserve_price)
+    };
+    // listings of someone else, with a bid or below the min reserve price are skipped
+    let res = execute(deps.as_mut(), mock_env(), mock_info("buyer", &[]), ExecuteMsg::BatchUpdatePrice { items: vec![update(0, 5_000000)] }).unwrap();
+    assert_eq!(res.events[0].ty, "price_update_skipped".to_string());
+    assert_eq!(res.attributes[1].value, "0".to_string());
+    let res = execute(deps.as_mut(), mock_env(), mock_info("satoshi", &[]), ExecuteMsg::BatchUpdatePrice {
+        items: vec![update(0, 5_000000), update(2, 5_000000), update(1, 6_000000), update(0, 0)]
+    }).unwrap();
+    assert_eq!(res.events.iter().map(|x| x.ty.as_str()).collect::<Vec<&str>>(),
+        vec!["price_change", "price_update_skipped", "price_change", "price_update_skipped"]);
+    assert_eq!(res.attributes[1].value, "2".to_string());
+    let auction = query_auction(deps.as_ref(), Uint128::from(0u128)).unwrap();
+    assert_eq!(auction.amount, Uint128::from(5_000000u128));
+    let auction = query_auction(deps.as_ref(), Uint128::from(1u128)).unwrap();
+    assert_eq!(auction.reserve_price, Uint128::from(6_000000u128));
+    assert_eq!(auction.amount, Uint128::from(6_000000u128));
+
+    // approval listings have no nft to return on cancel, started auctions are skipped
+    let res = execute(deps.as_mut(), mock_env(), mock_info("satoshi", &[]), ExecuteMsg::BatchCancel {
+        auction_ids: vec![Uint128::zero(), Uint128::from(2u128), Uint128::from(1u128)]
+    }).unwrap();
+    assert_eq!(res.events.len(), 3);
+    assert_eq!(res.events[1].ty, "cancel_skipped".to_string());
+    assert_eq!(res.attributes[1].value, "2".to_string());
+    assert!(res.messages.is_empty());
+    assert!(query_auction(deps.as_ref(), Uint128::from(2u128)).is_ok());
+    assert!(query_auction(deps.as_ref(), Uint128::zero()).is_err());
+    assert!(query_auction(deps.as_ref(), Uint128::from(1u128)).is_err());
+}
+
+#[test]
+fn update_listing() {
+    let mut deps = mock_dependencies(&[]);
+    setup_contract(deps.as_mut(), vec!["uluna".to_string(), "uusd".to_string()]);
+    create_listing(deps.as_mut(), "satoshi", "bitcoin", 1_000000, true);
+    create_listing(deps.as_mut(), "satoshi", "ethereum", 1_000000, false);
+    let update = |auction_id: u128, reserve_price: Option<u128>, buy_now_price: Option<u128>| ExecuteMsg::UpdateListing {
+        auction_id: Uint128::from(auction_id),
+        reserve_price: reserve_price.map(Uint128::from),
+        denom: None,
+        buy_now_price: buy_now_price.map(Uint128::from),
+        expires_at: None
+    };
+
+    let err = execute(deps.as_mut(), mock_env(), mock_info("buyer", &[]), update(0, None, Some(2_000000))).unwrap_err();
+    match err {
+        ContractError::Unauthorized { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    let err = execute(deps.as_mut(), mock_env(), mock_info("satoshi", &[]), update(0, Some(2_000000), None)).unwrap_err();
+    match err {
+        ContractError::InvalidAuctionType { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+
+    // repriced in place, price index follows the listing
+    let res = execute(deps.as_mut(), mock_env(), mock_info("satoshi", &[]), update(0, None, Some(2_000000))).unwrap();
+    assert_eq!(res.events[0].ty, "price_change".to_string());
+    assert_eq!(res.events[0].attributes[1].value, "1000000".to_string());
+    assert_eq!(res.events[0].attributes[3].value, "2000000".to_string());
+    let filter = AuctionFilter {
+        min_price: Some(Uint128::from(1_500000u128)),
+        ..AuctionFilter::default()
+    };
+    let page = query_search_auctions(deps.as_ref(), mock_env(), filter, Some(AuctionSortBy::Price), None, None, None).unwrap();
+    assert_eq!(page.auctions.iter().map(|x| x.auction_id.u128()).collect::<Vec<u128>>(), vec![0]);
+
+    // denom moves the listing to the stats of the new denom
+    let res = execute(deps.as_mut(), mock_env(), mock_info("satoshi", &[]), ExecuteMsg::UpdateListing {
+        auction_id: Uint128::from(1u128),
+        reserve_price: Some(Uint128::from(3_000000u128)),
+        denom: Some("uusd".to_string()),
+        buy_now_price: None,
+        expires_at: Some(mock_env().block.time.seconds() + 100)
+    }).unwrap();
+    assert_eq!(res.events[0].attributes[2].value, "uluna".to_string());
+    assert_eq!(res.events[0].attributes[4].value, "uusd".to_string());
+    let auction = query_auction(deps.as_ref(), Uint128::from(1u128)).unwrap();
+    assert_eq!(auction.denom, "uusd".to_string());
+    assert_eq!(auction.expires_at, Some(mock_env().block.time.seconds() + 100));
+    assert_eq!(auction.reserve_price, Uint128::from(3_000000u128));
+    assert_eq!(query_collection_stats(deps.as_ref(), mock_env(), "nft".to_string(), "uluna".to_string()).unwrap().active_listings, 1);
+    assert_eq!(query_collection_stats(deps.as_ref(), mock_env(), "nft".to_string(), "uusd".to_string()).unwrap().active_listings, 1);
+
+    // zero clears the expiry
+    let expiry = |expires_at: u64| ExecuteMsg::UpdateListing {
+        auction_id: Uint128::from(1u128),
+        reserve_price: None,
+        denom: None,
+        buy_now_price: None,
+        expires_at: Some(expires_at)
+    };
+    execute(deps.as_mut(), mock_env(), mock_info("satoshi", &[]), expiry(0)).unwrap();
+    assert_eq!(query_auction(deps.as_ref(), Uint128::from(1u128)).unwrap().expires_at, None);
+    execute(deps.as_mut(), mock_env(), mock_info("satoshi", &[]), expiry(mock_env().block.time.seconds() + 100)).unwrap();
+
+    // expired listing takes no bid and can be closed by anyone
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(env.block.time.seconds() + 100);
+    let info = mock_info("buyer", &[Coin::new(3_000000, "uusd")]);
+    let err = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::PlaceBid { auction_id: Uint128::from(1u128), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap_err();
+    match err {
+        ContractError::InvalidAuction { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    let res = execute(deps.as_mut(), env, mock_info("random", &[]), ExecuteMsg::InvalidateListing { auction_id: Uint128::from(1u128) }).unwrap();
+    assert_eq!(res.messages[0].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: "nft".into(),
+        msg: to_binary(&Cw721ExecuteMsg::TransferNft {
+            token_id: "ethereum".to_string(),
+            recipient: "satoshi".into()
+        }).unwrap(),
+        funds: vec![]
+    }));
+
+    // terms are fixed once there is a bid
+    let info = mock_info("buyer", &[Coin::new(2_000000, "uluna")]);
+    execute(deps.as_mut(), mock_env(), info, ExecuteMsg::PlaceBid { auction_id: Uint128::zero(), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+    let err = execute(deps.as_mut(), mock_env(), mock_info("satoshi", &[]), update(0, None, Some(1_000000))).unwrap_err();
+    match err {
+        ContractError::InvalidAuction { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+}
+
+#[test]
+fn sweep() {
+    let mut deps = mock_dependencies(&[]);
+    setup_contract(deps.as_mut(), vec!["uluna".to_string()]);
+    create_listing(deps.as_mut(), "satoshi", "bitcoin", 3_000000, true);
+    create_listing(deps.as_mut(), "satoshi", "ethereum", 1_000000, true);
+    create_listing(deps.as_mut(), "satoshi", "cardano", 2_000000, true);
+    create_listing(deps.as_mut(), "satoshi", "solana", 1_000000, false);
+    let auction_ids = |auction_ids: Vec<u128>| SweepTarget::AuctionIds {
+        auction_ids: auction_ids.into_iter().map(Uint128::from).collect()
+    };
+
+    // two cheapest buy now listings, the rest is refunded
+    let info = mock_info("buyer", &[Coin::new(5_000000, "uluna")]);
+    let res = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Sweep {
+        target: SweepTarget::Cheapest { nft_contract: "nft".to_string(), max_count: 2 },
+        max_total_price: Uint128::from(5_000000u128),
+        allow_partial: false,
+        referrer: None
+    }).unwrap();
+    assert_eq!(res.events.len(), 3);
+    assert_eq!(res.events[0].attributes[0].value, "1".to_string());
+    assert_eq!(res.events[1].attributes[0].value, "2".to_string());
+    // protocol fee, seller, nft transfer and settle hook for each listing
+    assert_eq!(res.messages.len(), 9);
+    assert_eq!(res.messages[8].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: "buyer".to_string(),
+        amount: vec![Coin::new(2_000000, "uluna")]
+    }));
+    assert!(query_auction(deps.as_ref(), Uint128::from(1u128)).unwrap().is_settled);
+    assert!(query_auction(deps.as_ref(), Uint128::from(2u128)).unwrap().is_settled);
+
+    // a sold listing or an auction fails the sweep unless partial fills are allowed
+    let info = mock_info("buyer", &[Coin::new(3_000000, "uluna")]);
+    let msg = |ids: Vec<u128>, allow_partial: bool| ExecuteMsg::Sweep {
+        target: auction_ids(ids),
+        max_total_price: Uint128::from(3_000000u128),
+        allow_partial: allow_partial,
+        referrer: None
+    };
+    let err = execute(deps.as_mut(), mock_env(), info.clone(), msg(vec![1, 0], false)).unwrap_err();
+    match err {
+        ContractError::InvalidAuction { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    let res = execute(deps.as_mut(), mock_env(), info, msg(vec![0, 1, 3], true)).unwrap();
+    assert_eq!(res.events.iter().map(|x| x.ty.as_str()).collect::<Vec<&str>>(),
+        vec!["sweep_item", "sweep_skipped", "sweep_skipped", "sweep_summary"]);
+    assert_eq!(res.messages.len(), 4);
+
+    // listings over the max total price are not bought
+    create_listing(deps.as_mut(), "satoshi", "polkadot", 2_000000, true);
+    let info = mock_info("buyer", &[Coin::new(2_000000, "uluna")]);
+    let res = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Sweep {
+        target: auction_ids(vec![4]),
+        max_total_price: Uint128::from(1_000000u128),
+        allow_partial: true,
+        referrer: None
+    }).unwrap();
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: "buyer".to_string(),
+        amount: vec![Coin::new(2_000000, "uluna")]
+    }));
+    assert!(!query_auction(deps.as_ref(), Uint128::from(4u128)).unwrap().is_settled);
+
+    // a cheaper expired listing doesn't take the place of a buyable one
+    create_listing(deps.as_mut(), "satoshi", "solana2", 500000, true);
+    let mut env = mock_env();
+    execute(deps.as_mut(), env.clone(), mock_info("satoshi", &[]), ExecuteMsg::UpdateListing {
+        auction_id: Uint128::from(5u128),
+        reserve_price: None,
+        denom: None,
+        buy_now_price: None,
+        expires_at: Some(env.block.time.seconds() + 10)
+    }).unwrap();
+    env.block.time = env.block.time.plus_seconds(100);
+    let info = mock_info("buyer", &[Coin::new(2_000000, "uluna")]);
+    let res = execute(deps.as_mut(), env, info, ExecuteMsg::Sweep {
+        target: SweepTarget::Cheapest { nft_contract: "nft".to_string(), max_count: 1 },
+        max_total_price: Uint128::from(2_000000u128),
+        allow_partial: false,
+        referrer: None
+    }).unwrap();
+    assert_eq!(res.events[0].attributes[0].value, "4".to_string());
+    assert!(query_auction(deps.as_ref(), Uint128::from(4u128)).unwrap().is_settled);
+}
+
+#[test]
+fn bid_on_behalf() {
+    let mut deps = mock_dependencies(&[]);
+    setup_contract(deps.as_mut(), vec!["uluna".to_string()]);
+    create_listing(deps.as_mut(), "satoshi", "bitcoin", 1_000000, false);
+    let bid = |recipient: &str, refund_to: &str| ExecuteMsg::PlaceBid {
+        auction_id: Uint128::zero(),
+        recipient: Some(recipient.to_string()),
+        refund_to: Some(refund_to.to_string()),
+        send_msg: None,
+        referrer: None
+    };
+
+    // payer bids for a friend, the bid keeps both addresses
+    let info = mock_info("payer", &[Coin::new(1_000000, "uluna")]);
+    execute(deps.as_mut(), mock_env(), info, bid("friend", "wallet")).unwrap();
+    let auction = query_auction(deps.as_ref(), Uint128::zero()).unwrap();
+    assert_eq!(auction.bidder, Some("payer".to_string()));
+    assert_eq!(auction.recipient, Some("friend".to_string()));
+    assert_eq!(auction.refund_to, Some("wallet".to_string()));
+
+    // outbid refund goes to refund_to instead of the bidder
+    let info = mock_info("buyer", &[Coin::new(2_000000, "uluna")]);
+    let res = execute(deps.as_mut(), mock_env(), info, bid("gift", "buyer")).unwrap();
+    assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: "wallet".to_string(),
+        amount: vec![Coin::new(1_000000, "uluna")]
+    }));
+
+    // nft is delivered to the recipient and the settle hook checks the recipient owns it
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(env.block.time.seconds() + 86400);
+    let res = execute(deps.as_mut(), env, mock_info("random", &[]), ExecuteMsg::Settle { auction_id: Uint128::zero() }).unwrap();
+    let len = res.messages.len();
+    assert_eq!(res.messages[len - 2].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: "nft".into(),
+        msg: to_binary(&Cw721ExecuteMsg::TransferNft {
+            token_id: "bitcoin".to_string(),
+            recipient: "gift".into()
+        }).unwrap(),
+        funds: vec![]
+    }));
+    assert_eq!(res.messages[len - 1].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: MOCK_CONTRACT_ADDR.into(),
+        msg: to_binary(&ExecuteMsg::SettleHook {
+            nft_contract: "nft".to_string(),
+            token_id: "bitcoin".to_string(),
+            owner: "gift".to_string()
+        }).unwrap(),
+        funds: vec![]
+    }));
+}
+
+#[test]
+fn bid_with_send_msg() {
+    let mut deps = mock_dependencies(&[]);
+    setup_contract(deps.as_mut(), vec!["uluna".to_string()]);
+    create_listing(deps.as_mut(), "satoshi", "bitcoin", 1_000000, true);
+    let send_msg = Binary::from(b"{\"deposit\":{}}".to_vec());
+
+    // accounts can't take the hook, whether they are the recipient or the bidder
+    deps.querier.with_account("buyer".to_string());
+    deps.querier.with_account("wallet".to_string());
+    for recipient in [Some("wallet".to_string()), None] {
+        let info = mock_info("buyer", &[Coin::new(1_000000, "uluna")]);
+        let err = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::PlaceBid {
+            auction_id: Uint128::zero(),
+            recipient: recipient,
+            refund_to: None,
+            send_msg: Some(send_msg.clone()),
+            referrer: None
+        }).unwrap_err();
+        match err {
+            ContractError::InvalidAsset { .. } => {}
+            e => panic!("unexpected error: {}", e)
+        }
+    }
+
+    // the vault contract is notified of the nft with SendNft
+    let info = mock_info("buyer", &[Coin::new(1_000000, "uluna")]);
+    execute(deps.as_mut(), mock_env(), info, ExecuteMsg::PlaceBid {
+        auction_id: Uint128::zero(),
+        recipient: Some("vault".to_string()),
+        refund_to: None,
+        send_msg: Some(send_msg.clone()),
+        referrer: None
+    }).unwrap();
+    assert_eq!(query_auction(deps.as_ref(), Uint128::zero()).unwrap().send_msg, Some(send_msg.clone()));
+    let res = execute(deps.as_mut(), mock_env(), mock_info("random", &[]), ExecuteMsg::Settle { auction_id: Uint128::zero() }).unwrap();
+    let len = res.messages.len();
+    assert_eq!(res.messages[len - 2].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: "nft".into(),
+        msg: to_binary(&Cw721ExecuteMsg::SendNft {
+            contract: "vault".to_string(),
+            token_id: "bitcoin".to_string(),
+            msg: send_msg
+        }).unwrap(),
+        funds: vec![]
+    }));
+    assert_eq!(res.messages[len - 2].reply_on, ReplyOn::Error);
+    assert_eq!(res.messages[len - 1].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: MOCK_CONTRACT_ADDR.into(),
+        msg: to_binary(&ExecuteMsg::SettleHook {
+            nft_contract: "nft".to_string(),
+            token_id: "bitcoin".to_string(),
+            owner: "vault".to_string()
+        }).unwrap(),
+        funds: vec![]
+    }));
+
+    // the vault rejects the hook, the nft is transferred to it without one before the settle hook runs
+    let res = reply(deps.as_mut(), mock_env(), Reply {
+        id: res.messages[len - 2].id,
+        result: SubMsgResult::Err("unknown hook".to_string())
+    }).unwrap();
+    assert_eq!(res.attributes[0].value, "send_nft_failed".to_string());
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(res.messages[0].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: "nft".into(),
+        msg: to_binary(&Cw721ExecuteMsg::TransferNft {
+            recipient: "vault".to_string(),
+            token_id: "bitcoin".to_string()
+        }).unwrap(),
+        funds: vec![]
+    }));
+    // it is not a failed settlement
+    assert!(query_failed_settlements(deps.as_ref(), None, None).unwrap().settlements.is_empty());
+}
+
+#[test]
+fn referrals() {
+    let mut deps = mock_dependencies(&[]);
+    setup_contract(deps.as_mut(), vec!["uluna".to_string()]);
+    let owner = mock_info("owner", &[]);
+    let bid = |auction_id: u128, referrer: &str| ExecuteMsg::PlaceBid {
+        auction_id: Uint128::from(auction_id),
+        recipient: None,
+        refund_to: None,
+        send_msg: None,
+        referrer: Some(referrer.to_string())
+    };
+    let list = |token_id: &str, referrer: Option<&str>| ExecuteMsg::ReceiveNft(Cw721ReceiveMsg {
+        sender: "satoshi".into(),
+        token_id: token_id.to_string(),
+        msg: to_binary(&Cw721HookMsg::CreateAuction {
+            denom: "uluna".to_string(),
+            reserve_price: Uint128::from(100_000000u128),
+            is_instant_sale: true,
+            referrer: referrer.map(|x| x.to_string())
+        }).unwrap()
+    });
+
+    // only the admin sets tiers and approves, referrers must register first
+    let err = execute(deps.as_mut(), mock_env(), mock_info("frontend", &[]), ExecuteMsg::SetReferralTier { tier: 1, fee_share: Some(Decimal::percent(50)) }).unwrap_err();
+    match err {
+        ContractError::Unauthorized { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    execute(deps.as_mut(), mock_env(), owner.clone(), ExecuteMsg::SetReferralTier { tier: 1, fee_share: Some(Decimal::percent(50)) }).unwrap();
+    execute(deps.as_mut(), mock_env(), owner.clone(), ExecuteMsg::SetReferralTier { tier: 2, fee_share: Some(Decimal::percent(20)) }).unwrap();
+    let err = execute(deps.as_mut(), mock_env(), owner.clone(), ExecuteMsg::ApproveReferrer { referrer: "frontend".to_string(), tier: 1 }).unwrap_err();
+    match err {
+        ContractError::InvalidReferrer { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    execute(deps.as_mut(), mock_env(), mock_info("frontend", &[]), ExecuteMsg::RegisterReferrer {}).unwrap();
+    execute(deps.as_mut(), mock_env(), mock_info("gallery", &[]), ExecuteMsg::RegisterReferrer {}).unwrap();
+    execute(deps.as_mut(), mock_env(), owner.clone(), ExecuteMsg::ApproveReferrer { referrer: "frontend".to_string(), tier: 1 }).unwrap();
+    let res = query_referrers(deps.as_ref(), None, None, Some(false)).unwrap();
+    assert_eq!(res.referrers.len(), 1);
+    assert_eq!(res.referrers[0].address, "gallery".to_string());
+    execute(deps.as_mut(), mock_env(), owner.clone(), ExecuteMsg::ApproveReferrer { referrer: "gallery".to_string(), tier: 2 }).unwrap();
+
+    // unregistered referrers and self referral are rejected
+    execute(deps.as_mut(), mock_env(), mock_info("nft", &[]), list("bitcoin", Some("gallery"))).unwrap();
+    let info = mock_info("buyer", &[Coin::new(100_000000, "uluna")]);
+    let err = execute(deps.as_mut(), mock_env(), info.clone(), bid(0, "random")).unwrap_err();
+    match err {
+        ContractError::InvalidReferrer { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    let err = execute(deps.as_mut(), mock_env(), info.clone(), bid(0, "buyer")).unwrap_err();
+    match err {
+        ContractError::InvalidReferrer { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+
+    // 1% protocol fee, the listing referrer gets 20% of it and the bid referrer 50%
+    execute(deps.as_mut(), mock_env(), info.clone(), bid(0, "frontend")).unwrap();
+    let res = execute(deps.as_mut(), mock_env(), mock_info("random", &[]), ExecuteMsg::Settle { auction_id: Uint128::zero() }).unwrap();
+    assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: "collector".to_string(),
+        amount: vec![Coin::new(300000, "uluna")]
+    }));
+    assert_eq!(res.messages[1].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: "gallery".to_string(),
+        amount: vec![Coin::new(200000, "uluna")]
+    }));
+    assert_eq!(res.messages[2].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: "frontend".to_string(),
+        amount: vec![Coin::new(500000, "uluna")]
+    }));
+    let res = query_referrer(deps.as_ref(), "frontend".to_string()).unwrap();
+    assert_eq!(res.fee_share, Decimal::percent(50));
+    assert_eq!(res.stats, vec![ReferralStatsResponse {
+        denom: "uluna".to_string(),
+        volume: Uint128::from(100_000000u128),
+        earnings: Uint128::from(500000u128),
+        num_sales: 1
+    }]);
+
+    // revoked referrers keep their stats but are no longer paid
+    execute(deps.as_mut(), mock_env(), owner, ExecuteMsg::RevokeReferrer { referrer: "frontend".to_string() }).unwrap();
+    execute(deps.as_mut(), mock_env(), mock_info("nft", &[]), list("ethereum", None)).unwrap();
+    execute(deps.as_mut(), mock_env(), info, bid(1, "frontend")).unwrap();
+    let res = execute(deps.as_mut(), mock_env(), mock_info("random", &[]), ExecuteMsg::Settle { auction_id: Uint128::from(1u128) }).unwrap();
+    assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: "collector".to_string(),
+        amount: vec![Coin::new(1_000000, "uluna")]
+    }));
+    let res = query_referrer(deps.as_ref(), "frontend".to_string()).unwrap();
+    assert!(!res.is_approved);
+    assert_eq!(res.fee_share, Decimal::zero());
+    assert_eq!(res.stats[0].num_sales, 1);
+}
+
+#[test]
+fn fee_distribution() {
+    let mut deps = mock_dependencies(&[]);
+    setup_contract(deps.as_mut(), vec!["uluna".to_string()]);
+    let owner = mock_info("owner", &[]);
+    let distribution = |burn_weight: u64, distribute_on_settle: bool| FeeDistribution {
+        recipients: vec![
+            FeeRecipient { address: "treasury".to_string(), weight: 50 },
+            FeeRecipient { address: "staking".to_string(), weight: 30 },
+            FeeRecipient { address: "devfund".to_string(), weight: 10 }
+        ],
+        burn_weight: burn_weight,
+        distribute_on_settle: distribute_on_settle
+    };
+    let change = |distribution: FeeDistribution| ExecuteMsg::ProposeConfigChange(ConfigChange {
+        fee_distribution: Some(distribution),
+        ..ConfigChange::default()
+    });
+    let mut env = mock_env();
+
+    // a distribution without weight is rejected
+    let err = execute(deps.as_mut(), env.clone(), owner.clone(), change(FeeDistribution {
+        recipients: vec![],
+        burn_weight: 0,
+        distribute_on_settle: true
+    })).unwrap_err();
+    match err {
+        ContractError::InvalidConfigChange { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+
+    // the distribution is timelocked like the collector address
+    execute(deps.as_mut(), env.clone(), owner.clone(), change(distribution(10, true))).unwrap();
+    env.block.time = Timestamp::from_seconds(env.block.time.seconds() + 3600);
+    execute(deps.as_mut(), env.clone(), owner.clone(), ExecuteMsg::ExecuteConfigChange {}).unwrap();
+    assert_eq!(query_fee_distribution(deps.as_ref()).unwrap().distribution, Some(distribution(10, true)));
+
+    // split at settlement, 1% protocol fee of 100 luna
+    create_listing(deps.as_mut(), "satoshi", "bitcoin", 100_000000, true);
+    let info = mock_info("buyer", &[Coin::new(100_000000, "uluna")]);
+    execute(deps.as_mut(), env.clone(), info.clone(), ExecuteMsg::PlaceBid { auction_id: Uint128::zero(), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+    let res = execute(deps.as_mut(), env.clone(), mock_info("random", &[]), ExecuteMsg::Settle { auction_id: Uint128::zero() }).unwrap();
+    let expected = vec![("treasury", 500000u128), ("staking", 300000), ("devfund", 100000)];
+    for (i, (address, amount)) in expected.into_iter().enumerate() {
+        assert_eq!(res.messages[i].msg, CosmosMsg::Bank(BankMsg::Send {
+            to_address: address.to_string(),
+            amount: vec![Coin::new(amount, "uluna")]
+        }));
+    }
+    assert_eq!(res.messages[3].msg, CosmosMsg::Bank(BankMsg::Burn {
+        amount: vec![Coin::new(100000, "uluna")]
+    }));
+
+    // fees accrue until they are distributed
+    execute(deps.as_mut(), env.clone(), owner.clone(), change(distribution(0, false))).unwrap();
+    env.block.time = Timestamp::from_seconds(env.block.time.seconds() + 3600);
+    execute(deps.as_mut(), env.clone(), owner, ExecuteMsg::ExecuteConfigChange {}).unwrap();
+    create_listing(deps.as_mut(), "satoshi", "ethereum", 100_000000, true);
+    execute(deps.as_mut(), env.clone(), info.clone(), ExecuteMsg::PlaceBid { auction_id: Uint128::from(1u128), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+    let res = execute(deps.as_mut(), env.clone(), mock_info("random", &[]), ExecuteMsg::Settle { auction_id: Uint128::from(1u128) }).unwrap();
+    assert_eq!(res.messages.len(), 3);
+    assert_eq!(query_accrued_fees(deps.as_ref()).unwrap().fees, vec![Coin::new(1_000000, "uluna")]);
+
+    // weights 50/30/10, the rounding remainder goes to the first recipient
+    let res = execute(deps.as_mut(), env.clone(), mock_info("random", &[]), ExecuteMsg::DistributeFees {}).unwrap();
+    let expected = vec![("treasury", 555556u128), ("staking", 333333), ("devfund", 111111)];
+    for (i, (address, amount)) in expected.into_iter().enumerate() {
+        assert_eq!(res.messages[i].msg, CosmosMsg::Bank(BankMsg::Send {
+            to_address: address.to_string(),
+            amount: vec![Coin::new(amount, "uluna")]
+        }));
+    }
+    assert!(query_accrued_fees(deps.as_ref()).unwrap().fees.is_empty());
+    let err = execute(deps.as_mut(), env.clone(), mock_info("random", &[]), ExecuteMsg::DistributeFees {}).unwrap_err();
+    match err {
+        ContractError::InvalidAmount { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+
+    // removing the distribution pays out accrued fees, then fees go to the collector again
+    create_listing(deps.as_mut(), "satoshi", "cardano", 100_000000, true);
+    execute(deps.as_mut(), env.clone(), info.clone(), ExecuteMsg::PlaceBid { auction_id: Uint128::from(2u128), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+    execute(deps.as_mut(), env.clone(), mock_info("random", &[]), ExecuteMsg::Settle { auction_id: Uint128::from(2u128) }).unwrap();
+    let err = execute(deps.as_mut(), env.clone(), mock_info("owner", &[]), ExecuteMsg::ProposeConfigChange(ConfigChange {
+        fee_distribution: Some(distribution(0, true)),
+        remove_fee_distribution: true,
+        ..ConfigChange::default()
+    })).unwrap_err();
+    match err {
+        ContractError::InvalidConfigChange { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    execute(deps.as_mut(), env.clone(), mock_info("owner", &[]), ExecuteMsg::ProposeConfigChange(ConfigChange {
+        remove_fee_distribution: true,
+        ..ConfigChange::default()
+    })).unwrap();
+    env.block.time = Timestamp::from_seconds(env.block.time.seconds() + 3600);
+    let res = execute(deps.as_mut(), env.clone(), mock_info("random", &[]), ExecuteMsg::ExecuteConfigChange {}).unwrap();
+    assert_eq!(res.messages.len(), 3);
+    assert!(query_accrued_fees(deps.as_ref()).unwrap().fees.is_empty());
+    assert_eq!(query_fee_distribution(deps.as_ref()).unwrap().distribution, None);
+    create_listing(deps.as_mut(), "satoshi", "solana", 100_000000, true);
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::PlaceBid { auction_id: Uint128::from(3u128), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+    let res = execute(deps.as_mut(), env, mock_info("random", &[]), ExecuteMsg::Settle { auction_id: Uint128::from(3u128) }).unwrap();
+    assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: "collector".to_string(),
+        amount: vec![Coin::new(1_000000, "uluna")]
+    }));
+}
+
+#[test]
+fn royalty_splits() {
+    let mut deps = mock_dependencies(&[]);
+    setup_contract(deps.as_mut(), vec!["uluna".to_string()]);
+    execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), ExecuteMsg::SetRoyaltyAdmin { address: "admin".to_string(), enable: true }).unwrap();
+    let admin = mock_info("admin", &[]);
+    let split = |recipient: &str, percent: u64| RoyaltySplit { recipient: recipient.to_string(), share: Decimal::percent(percent) };
+    let set_royalty = |splits: Vec<RoyaltySplit>| ExecuteMsg::SetRoyaltyFee {
+        contract_addr: "nft".to_string(),
+        creator: "studio".to_string(),
+        royalty_fee: Decimal::percent(10),
+        splits: Some(splits)
+    };
+
+    // shares must sum to the royalty fee, each recipient once
+    let err = execute(deps.as_mut(), mock_env(), admin.clone(), set_royalty(vec![split("artist", 4), split("studio", 2)])).unwrap_err();
+    match err {
+        ContractError::InvalidConfigChange { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    let err = execute(deps.as_mut(), mock_env(), admin.clone(), set_royalty(vec![split("artist", 5), split("artist", 5)])).unwrap_err();
+    match err {
+        ContractError::InvalidConfigChange { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    let splits = vec![split("artist", 4), split("painter", 3), split("studio", 2), split("charity", 1)];
+    execute(deps.as_mut(), mock_env(), admin, set_royalty(splits.clone())).unwrap();
+    assert_eq!(query_royalty_fee(deps.as_ref(), "nft".to_string()).unwrap().royalty_fee.unwrap().splits, splits);
+    assert_eq!(query_all_royalty(deps.as_ref(), None, None).unwrap().royalty_fees[0].splits, splits);
+
+    // 10% of 999999 is 99999, the 3 left by rounding each share go to the creator
+    let expected = vec![("artist", 39999u128), ("painter", 29999), ("studio", 20002), ("charity", 9999)];
+    let price = query_calculate_price(deps.as_ref(), mock_env(), "nft".to_string(), "bitcoin".to_string(), Uint128::from(999999u128)).unwrap();
+    assert_eq!(price.royalty_fee, Uint128::from(99999u128));
+    assert_eq!(price.royalty_payouts, expected.iter().map(|(recipient, amount)| RoyaltyPayout {
+        recipient: recipient.to_string(),
+        amount: Uint128::from(*amount)
+    }).collect::<Vec<RoyaltyPayout>>());
+
+    create_listing(deps.as_mut(), "satoshi", "bitcoin", 999999, true);
+    let info = mock_info("buyer", &[Coin::new(999999, "uluna")]);
+    execute(deps.as_mut(), mock_env(), info, ExecuteMsg::PlaceBid { auction_id: Uint128::zero(), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+    let res = execute(deps.as_mut(), mock_env(), mock_info("random", &[]), ExecuteMsg::Settle { auction_id: Uint128::zero() }).unwrap();
+    for (i, (recipient, amount)) in expected.into_iter().enumerate() {
+        assert_eq!(res.messages[i + 1].msg, CosmosMsg::Bank(BankMsg::Send {
+            to_address: recipient.to_string(),
+            amount: vec![Coin::new(amount, "uluna")]
+        }));
+    }
+}
+
+#[test]
+fn self_service_royalty() {
+    let mut deps = mock_dependencies(&[]);
+    setup_contract(deps.as_mut(), vec!["uluna".to_string()]);
+    deps.querier.with_nft_minter("nft".to_string(), "minter".to_string());
+    deps.querier.with_contract_admin("nft2".to_string(), "dao".to_string());
+    let set_royalty = |nft_contract: &str, percent: u64| ExecuteMsg::SetRoyaltyFee {
+        contract_addr: nft_contract.to_string(),
+        creator: "creator".to_string(),
+        royalty_fee: Decimal::percent(percent),
+        splits: None
+    };
+    let mut env = mock_env();
+
+    // only the minter or the contract admin of the collection
+    let err = execute(deps.as_mut(), env.clone(), mock_info("random", &[]), set_royalty("nft", 5)).unwrap_err();
+    match err {
+        ContractError::Unauthorized { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    let err = execute(deps.as_mut(), env.clone(), mock_info("minter", &[]), set_royalty("nft2", 5)).unwrap_err();
+    match err {
+        ContractError::Unauthorized { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    execute(deps.as_mut(), env.clone(), mock_info("minter", &[]), set_royalty("nft", 5)).unwrap();
+    execute(deps.as_mut(), env.clone(), mock_info("dao", &[]), set_royalty("nft2", 5)).unwrap();
+    assert_eq!(query_royalty_fee(deps.as_ref(), "nft2".to_string()).unwrap().royalty_fee.unwrap().royalty_fee, Decimal::percent(5));
+
+    // within max royalty fee
+    let err = execute(deps.as_mut(), env.clone(), mock_info("minter", &[]), set_royalty("nft", 21)).unwrap_err();
+    match err {
+        ContractError::InvalidConfigChange { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+
+    // the rate can't change again before the cooldown, other fields can
+    let err = execute(deps.as_mut(), env.clone(), mock_info("minter", &[]), set_royalty("nft", 10)).unwrap_err();
+    match err {
+        ContractError::InvalidConfigChange { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    execute(deps.as_mut(), env.clone(), mock_info("minter", &[]), ExecuteMsg::SetRoyaltyFee {
+        contract_addr: "nft".to_string(),
+        creator: "studio".to_string(),
+        royalty_fee: Decimal::percent(5),
+        splits: None
+    }).unwrap();
+    env.block.time = Timestamp::from_seconds(env.block.time.seconds() + 7 * 86400);
+    execute(deps.as_mut(), env.clone(), mock_info("minter", &[]), set_royalty("nft", 10)).unwrap();
+    assert_eq!(query_royalty_fee(deps.as_ref(), "nft".to_string()).unwrap().royalty_fee.unwrap().royalty_fee, Decimal::percent(10));
+
+    // royalty admins are not limited by the cooldown, but are by the max royalty fee
+    execute(deps.as_mut(), env.clone(), mock_info("owner", &[]), ExecuteMsg::SetRoyaltyAdmin { address: "admin".to_string(), enable: true }).unwrap();
+    execute(deps.as_mut(), env.clone(), mock_info("admin", &[]), set_royalty("nft", 15)).unwrap();
+    let err = execute(deps.as_mut(), env, mock_info("admin", &[]), set_royalty("nft", 30)).unwrap_err();
+    match err {
+        ContractError::InvalidConfigChange { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+}
+
+#[test]
+fn royalty_registry() {
+    let mut deps = mock_dependencies(&[]);
+    setup_contract(deps.as_mut(), vec!["uluna".to_string()]);
+    execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), ExecuteMsg::SetRoyaltyAdmin { address: "admin".to_string(), enable: true }).unwrap();
+    execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), ExecuteMsg::SetRoyaltyFee {
+        contract_addr: "nft".to_string(),
+        creator: "creator".to_string(),
+        royalty_fee: Decimal::percent(10),
+        splits: None
+    }).unwrap();
+    deps.querier.with_registry_royalty("nft".to_string(), RoyaltyResponse {
+        royalty_fee: Decimal::percent(5),
+        creator: "artist".to_string(),
+        splits: vec![]
+    });
+
+    // only owner
+    let err = execute(deps.as_mut(), mock_env(), mock_info("random", &[]), ExecuteMsg::SetRoyaltyRegistry { address: Some("registry".to_string()) }).unwrap_err();
+    match err {
+        ContractError::Unauthorized { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), ExecuteMsg::SetRoyaltyRegistry { address: Some("registry".to_string()) }).unwrap();
+    let res: RoyaltyRegistryResponse = from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::RoyaltyRegistry {}).unwrap()).unwrap();
+    assert_eq!(res.address, Some("registry".to_string()));
+
+    // prices and new listings use the royalty of the registry
+    let price = query_calculate_price(deps.as_ref(), mock_env(), "nft".to_string(), "bitcoin".to_string(), Uint128::from(100000u128)).unwrap();
+    assert_eq!(price.royalty_fee, Uint128::from(5000u128));
+    create_listing(deps.as_mut(), "satoshi", "bitcoin", 100000, true);
+    let auction = query_auction(deps.as_ref(), Uint128::zero()).unwrap();
+    assert_eq!(auction.royalty_fee, Decimal::percent(5));
+    assert_eq!(auction.creator_address, Some("artist".to_string()));
+
+    // collections unknown to the registry have no royalty
+    let price = query_calculate_price(deps.as_ref(), mock_env(), "nft2".to_string(), "bitcoin".to_string(), Uint128::from(100000u128)).unwrap();
+    assert_eq!(price.royalty_fee, Uint128::zero());
+
+    // registry royalty above the max royalty fee is capped
+    deps.querier.with_registry_royalty("nft2".to_string(), RoyaltyResponse {
+        royalty_fee: Decimal::percent(40),
+        creator: "artist".to_string(),
+        splits: vec![
+            RoyaltySplit { recipient: "artist".to_string(), share: Decimal::percent(30) },
+            RoyaltySplit { recipient: "partner".to_string(), share: Decimal::percent(10) }
+        ]
+    });
+    let royalty = load_royalty(deps.as_ref(), &Addr::unchecked("nft2"), "bitcoin").unwrap().unwrap();
+    assert_eq!(royalty.royalty_fee, Decimal::percent(20));
+    assert_eq!(royalty.splits[0].share, Decimal::percent(15));
+    assert_eq!(royalty.splits[1].share, Decimal::percent(5));
+
+    // royalty queries read the registry too
+    let royalty = query_royalty_fee(deps.as_ref(), "nft".to_string()).unwrap().royalty_fee.unwrap();
+    assert_eq!(royalty.creator, "artist".to_string());
+    let royalties = query_all_royalty(deps.as_ref(), None, None).unwrap().royalty_fees;
+    assert_eq!(royalties.iter().map(|x| (x.contract_addr.as_str(), x.royalty_fee)).collect::<Vec<(&str, Decimal)>>(),
+        vec![("nft", Decimal::percent(5)), ("nft2", Decimal::percent(20))]);
+    assert_eq!(query_all_royalty(deps.as_ref(), Some("nft".to_string()), None).unwrap().royalty_fees.len(), 1);
+
+    // unset falls back to the royalties of this contract
+    execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), ExecuteMsg::SetRoyaltyRegistry { address: None }).unwrap();
+    let price = query_calculate_price(deps.as_ref(), mock_env(), "nft".to_string(), "bitcoin".to_string(), Uint128::from(100000u128)).unwrap();
+    assert_eq!(price.royalty_fee, Uint128::from(10000u128));
+    assert_eq!(query_all_royalty(deps.as_ref(), None, None).unwrap().royalty_fees[0].creator, "creator".to_string());
+}
+
+#[test]
+fn primary_sale() {
+    let mut deps = mock_dependencies(&[]);
+    setup_contract(deps.as_mut(), vec!["uluna".to_string()]);
+    deps.querier.with_nft_minter("nft".to_string(), "minter".to_string());
+    execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), ExecuteMsg::SetRoyaltyAdmin { address: "admin".to_string(), enable: true }).unwrap();
+    execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), ExecuteMsg::SetRoyaltyFee {
+        contract_addr: "nft".to_string(),
+        creator: "creator".to_string(),
+        royalty_fee: Decimal::percent(5),
+        splits: None
+    }).unwrap();
+    let sell = |deps: &mut OwnedDeps<MockStorage, MockApi, WasmMockQuerier>, seller: &str, auction_id: u128| {
+        create_listing(deps.as_mut(), seller, "bitcoin", 1_000000, true);
+        let info = mock_info("buyer", &[Coin::new(1_000000, "uluna")]);
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::PlaceBid { auction_id: Uint128::from(auction_id), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+        execute(deps.as_mut(), mock_env(), mock_info("random", &[]), ExecuteMsg::Settle { auction_id: Uint128::from(auction_id) }).unwrap()
+    };
+
+    // every sale is secondary until a primary protocol fee is set
+    sell(&mut deps, "satoshi", 0);
+    let provenance = query_token_provenance(deps.as_ref(), "nft".to_string(), "bitcoin".to_string(), None, None, None).unwrap();
+    assert!(!provenance.sales[0].is_primary_sale);
+    assert_eq!(provenance.sales[0].royalty_fee, Uint128::from(50000u128));
+
+    let mut env = mock_env();
+    execute(deps.as_mut(), env.clone(), mock_info("owner", &[]), ExecuteMsg::ProposeConfigChange(ConfigChange {
+        primary_protocol_fee: Some(Decimal::permille(5)),
+        ..ConfigChange::default()
+    })).unwrap();
+    env.block.time = Timestamp::from_seconds(env.block.time.seconds() + 3600);
+    execute(deps.as_mut(), env.clone(), mock_info("owner", &[]), ExecuteMsg::ExecuteConfigChange {}).unwrap();
+    assert_eq!(query_config(deps.as_ref()).unwrap().primary_protocol_fee, Some(Decimal::permille(5)));
+
+    // a resale is secondary, a sale by the minter or the royalty creator is primary
+    sell(&mut deps, "satoshi", 1);
+    create_listing(deps.as_mut(), "minter", "bitcoin", 1_000000, true);
+    let price = query_calculate_price(deps.as_ref(), mock_env(), "nft".to_string(), "bitcoin".to_string(), Uint128::from(1_000000u128)).unwrap();
+    assert!(price.is_primary_sale);
+    assert_eq!(price.protocol_fee, Uint128::from(5000u128));
+    assert_eq!(price.royalty_fee, Uint128::zero());
+    let info = mock_info("buyer", &[Coin::new(1_000000, "uluna")]);
+    execute(deps.as_mut(), mock_env(), info, ExecuteMsg::PlaceBid { auction_id: Uint128::from(2u128), recipient: None, refund_to: None, send_msg: None, referrer: None }).unwrap();
+    let res = execute(deps.as_mut(), mock_env(), mock_info("random", &[]), ExecuteMsg::Settle { auction_id: Uint128::from(2u128) }).unwrap();
+    // protocol fee and seller only
+    assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: "collector".to_string(),
+        amount: vec![Coin::new(5000, "uluna")]
+    }));
+    assert_eq!(res.messages[1].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: "minter".to_string(),
+        amount: vec![Coin::new(995000, "uluna")]
+    }));
+    sell(&mut deps, "creator", 3);
+
+    let provenance = query_token_provenance(deps.as_ref(), "nft".to_string(), "bitcoin".to_string(), None, None, None).unwrap();
+    let classes = provenance.sales.iter().map(|x| (x.is_primary_sale, x.protocol_fee, x.royalty_fee)).collect::<Vec<_>>();
+    assert_eq!(classes, vec![
+        (false, Uint128::from(10000u128), Uint128::from(50000u128)),
+        (false, Uint128::from(10000u128), Uint128::from(50000u128)),
+        (true, Uint128::from(5000u128), Uint128::zero()),
+        (true, Uint128::from(5000u128), Uint128::zero())
+    ]);
+
+    // the first sale of a token here is primary
+    create_listing(deps.as_mut(), "satoshi", "ethereum", 1_000000, true);
+    let price = query_calculate_price(deps.as_ref(), mock_env(), "nft".to_string(), "ethereum".to_string(), Uint128::from(1_000000u128)).unwrap();
+    assert!(price.is_primary_sale);
+
+    // removing the primary protocol fee makes every sale secondary again
+    execute(deps.as_mut(), env.clone(), mock_info("owner", &[]), ExecuteMsg::ProposeConfigChange(ConfigChange {
+        remove_primary_protocol_fee: true,
+        ..ConfigChange::default()
+    })).unwrap();
+    env.block.time = Timestamp::from_seconds(env.block.time.seconds() + 3600);
+    execute(deps.as_mut(), env, mock_info("owner", &[]), ExecuteMsg::ExecuteConfigChange {}).unwrap();
+    assert_eq!(query_config(deps.as_ref()).unwrap().primary_protocol_fee, None);
+    let price = query_calculate_price(deps.as_ref(), mock_env(), "nft".to_string(), "ethereum".to_string(), Uint128::from(1_000000u128)).unwrap();
+    assert!(!price.is_primary_sale);
+}
@@ -0,0 +1,66 @@
+use cosmwasm_std::{
+    Binary, Decimal, Deps, DepsMut, Env, MessageInfo, Response, StdResult, to_binary, entry_point
+};
+use marketplace::royalty_registry::{InstantiateMsg, ExecuteMsg, QueryMsg, MigrateMsg};
+use cw2::{get_contract_version, set_contract_version};
+
+use crate::error::ContractError;
+use crate::state::{CONFIG, Config};
+use crate::registry::{set_royalty_fee, remove_royalty_fee, set_royalty_admin, update_config};
+use crate::querier::{query_config, query_royalty_fee, query_all_royalty, query_token_royalty_fees, query_royalty_admin};
+
+pub const CONTRACT_NAME: &str = "crates.io:royalty-registry";
+pub const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn instantiate(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    msg: InstantiateMsg,
+) -> Result<Response, ContractError> {
+    if msg.max_royalty_fee > Decimal::one() {
+        return Err(ContractError::InvalidRoyalty("max royalty fee is more than sale price".to_string()));
+    }
+    CONFIG.save(deps.storage, &Config {
+        owner: info.sender,
+        max_royalty_fee: msg.max_royalty_fee
+    })?;
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    Ok(Response::default())
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> Result<Response, ContractError> {
+    match msg {
+        ExecuteMsg::SetRoyaltyFee { contract_addr, token_id, creator, royalty_fee, splits } =>
+            set_royalty_fee(deps, env, info, contract_addr, token_id, creator, royalty_fee, splits),
+        ExecuteMsg::RemoveRoyaltyFee { contract_addr, token_id } => remove_royalty_fee(deps, env, info, contract_addr, token_id),
+        ExecuteMsg::SetRoyaltyAdmin { address, enable } => set_royalty_admin(deps, info, address, enable),
+        ExecuteMsg::UpdateConfig { owner, max_royalty_fee } => update_config(deps, info, owner, max_royalty_fee)
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::Config {} => to_binary(&query_config(deps)?),
+        QueryMsg::RoyaltyFee { contract_addr, token_id } => to_binary(&query_royalty_fee(deps, contract_addr, token_id)?),
+        QueryMsg::AllRoyaltyFee { start_after, limit } => to_binary(&query_all_royalty(deps, start_after, limit)?),
+        QueryMsg::TokenRoyaltyFees { contract_addr, start_after, limit } => to_binary(&query_token_royalty_fees(deps, contract_addr, start_after, limit)?),
+        QueryMsg::RoyaltyAdmin { address } => to_binary(&query_royalty_admin(deps, address)?)
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+    let stored = get_contract_version(deps.storage)?;
+    if stored.contract != CONTRACT_NAME {
+        return Err(ContractError::InvalidMigration(format!("cannot migrate from {}", stored.contract)));
+    }
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    Ok(Response::new()
+        .add_attribute("action", "migrate")
+        .add_attribute("from_version", stored.version)
+        .add_attribute("to_version", CONTRACT_VERSION))
+}
@@ -0,0 +1,15 @@
+use cosmwasm_std::StdError;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ContractError {
+    #[error("{0}")]
+    Std(#[from] StdError),
+
+    #[error("Unauthorized")]
+    Unauthorized {},
+    #[error("invalid royalty: {0}")]
+    InvalidRoyalty(String),
+    #[error("invalid migration: {0}")]
+    InvalidMigration(String)
+}
@@ -0,0 +1,10 @@
+pub mod contract;
+mod error;
+pub mod state;
+pub mod registry;
+pub mod querier;
+
+pub use crate::error::ContractError;
+
+#[cfg(test)]
+mod testing;
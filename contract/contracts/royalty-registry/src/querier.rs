@@ -0,0 +1,171 @@
+use cosmwasm_std::{Addr, Decimal, Deps, Order, QueryRequest, StdResult, Uint128, WasmQuery, to_binary};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use cw_storage_plus::Bound;
+use cw_utils::maybe_addr;
+use marketplace::auction::{Royalty, RoyaltyFeeResponse, RoyaltyResponse, RoyaltySplit, RoyaltyAdminResponse,
+    AllRoyaltyResponse, AllRoyaltyListResponse};
+use marketplace::royalty_registry::{ConfigResponse, TokenRoyaltyResponse, TokenRoyaltyListResponse};
+
+use crate::state::{CONFIG, ROYALTY_ADMINS, COLLECTION_ROYALTIES, TOKEN_ROYALTIES};
+
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 100;
+// cw2981 returns an amount for a sale price, the fee is derived from this price
+const CW2981_REFERENCE_PRICE: u128 = 1_000_000_000_000_000_000;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Cw2981QueryMsg {
+    Extension { msg: Cw2981ExtensionMsg }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Cw2981ExtensionMsg {
+    RoyaltyInfo {
+        token_id: String,
+        sale_price: Uint128
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RoyaltiesInfoResponse {
+    pub address: String,
+    pub royalty_amount: Uint128
+}
+
+pub fn query_config(
+    deps: Deps
+) -> StdResult<ConfigResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    Ok(ConfigResponse {
+        owner: config.owner.to_string(),
+        max_royalty_fee: config.max_royalty_fee
+    })
+}
+
+pub fn query_royalty_fee(
+    deps: Deps,
+    contract_addr: String,
+    token_id: Option<String>
+) -> StdResult<RoyaltyFeeResponse> {
+    let nft_contract = deps.api.addr_validate(&contract_addr)?;
+    let royalty = match &token_id {
+        Some(token_id) => match TOKEN_ROYALTIES.may_load(deps.storage, (&nft_contract, token_id))? {
+            Some(v) => Some(v),
+            None => match COLLECTION_ROYALTIES.may_load(deps.storage, &nft_contract)? {
+                Some(v) => Some(v),
+                None => query_cw2981_royalty(deps, &nft_contract, token_id)?
+            }
+        },
+        None => COLLECTION_ROYALTIES.may_load(deps.storage, &nft_contract)?
+    };
+    Ok(RoyaltyFeeResponse {
+        royalty_fee: royalty.map(|royal| RoyaltyResponse {
+            royalty_fee: royal.royalty_fee,
+            creator: royal.creator.to_string(),
+            splits: royalty_splits(&royal)
+        })
+    })
+}
+
+// royalty info of a cw2981 token, nft contracts without the extension have no royalty
+fn query_cw2981_royalty(
+    deps: Deps,
+    nft_contract: &Addr,
+    token_id: &str
+) -> StdResult<Option<Royalty>> {
+    let sale_price = Uint128::new(CW2981_REFERENCE_PRICE);
+    let royalty_info: StdResult<RoyaltiesInfoResponse> = deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: nft_contract.to_string(),
+        msg: to_binary(&Cw2981QueryMsg::Extension {
+            msg: Cw2981ExtensionMsg::RoyaltyInfo {
+                token_id: token_id.to_string(),
+                sale_price
+            }
+        })?
+    }));
+    let royalty_info = match royalty_info {
+        Ok(v) if !v.royalty_amount.is_zero() && !v.address.is_empty() => v,
+        _ => return Ok(None)
+    };
+    // royalty above the max royalty fee is capped
+    let config = CONFIG.load(deps.storage)?;
+    let royalty_fee = Decimal::from_ratio(royalty_info.royalty_amount, sale_price).min(config.max_royalty_fee);
+    Ok(Some(Royalty {
+        royalty_fee,
+        creator: deps.api.addr_validate(&royalty_info.address)?,
+        splits: vec![]
+    }))
+}
+
+pub fn query_all_royalty(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>
+) -> StdResult<AllRoyaltyListResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start_addr = maybe_addr(deps.api, start_after)?;
+    let start = start_addr.as_ref().map(Bound::exclusive);
+
+    let royalty_fees = COLLECTION_ROYALTIES
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let (contract_addr, royalty) = item?;
+            Ok(AllRoyaltyResponse {
+                contract_addr: contract_addr.to_string(),
+                royalty_fee: royalty.royalty_fee,
+                creator: royalty.creator.to_string(),
+                splits: royalty_splits(&royalty)
+            })
+        })
+        .collect::<StdResult<Vec<AllRoyaltyResponse>>>()?;
+    Ok(AllRoyaltyListResponse { royalty_fees })
+}
+
+pub fn query_token_royalty_fees(
+    deps: Deps,
+    contract_addr: String,
+    start_after: Option<String>,
+    limit: Option<u32>
+) -> StdResult<TokenRoyaltyListResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let nft_contract = deps.api.addr_validate(&contract_addr)?;
+    let start = start_after.as_deref().map(Bound::exclusive);
+
+    let royalty_fees = TOKEN_ROYALTIES
+        .prefix(&nft_contract)
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let (token_id, royalty) = item?;
+            Ok(TokenRoyaltyResponse {
+                token_id,
+                royalty_fee: royalty.royalty_fee,
+                creator: royalty.creator.to_string(),
+                splits: royalty_splits(&royalty)
+            })
+        })
+        .collect::<StdResult<Vec<TokenRoyaltyResponse>>>()?;
+    Ok(TokenRoyaltyListResponse { royalty_fees })
+}
+
+pub fn query_royalty_admin(
+    deps: Deps,
+    address: String
+) -> StdResult<RoyaltyAdminResponse> {
+    let address_raw = deps.api.addr_validate(&address)?;
+    Ok(RoyaltyAdminResponse {
+        address,
+        enable: ROYALTY_ADMINS.may_load(deps.storage, &address_raw)?.unwrap_or(false)
+    })
+}
+
+fn royalty_splits(royalty: &Royalty) -> Vec<RoyaltySplit> {
+    royalty.splits.iter().map(|x| RoyaltySplit {
+        recipient: x.recipient.to_string(),
+        share: x.share
+    }).collect()
+}
@@ -0,0 +1,177 @@
+use cosmwasm_std::{Addr, Decimal, Deps, DepsMut, Env, MessageInfo, Response, StdResult, Storage};
+use marketplace::auction::{Royalty, RoyaltySplit};
+use marketplace::royalty::{query_nft_controller, validate_royalty_splits, ROYALTY_RATE_COOLDOWN};
+
+use crate::error::ContractError;
+use crate::state::{CONFIG, ROYALTY_ADMINS, COLLECTION_ROYALTIES, TOKEN_ROYALTIES, COLLECTION_RATE_CHANGED_AT, TOKEN_RATE_CHANGED_AT};
+
+#[allow(clippy::too_many_arguments)]
+pub fn set_royalty_fee(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    contract_addr: String,
+    token_id: Option<String>,
+    creator: String,
+    royalty_fee: Decimal,
+    splits: Option<Vec<RoyaltySplit>>
+) -> Result<Response, ContractError> {
+    let nft_contract_addr = deps.api.addr_validate(&contract_addr)?;
+    let creator_addr = deps.api.addr_validate(&creator)?;
+    let is_royalty_admin = only_royalty_setter(deps.as_ref(), &info, &nft_contract_addr)?;
+    let config = CONFIG.load(deps.storage)?;
+    if royalty_fee > config.max_royalty_fee {
+        return Err(ContractError::InvalidRoyalty("royalty fee is more than max royalty fee".to_string()));
+    }
+    let current = match &token_id {
+        Some(token_id) => TOKEN_ROYALTIES.may_load(deps.storage, (&nft_contract_addr, token_id))?,
+        None => COLLECTION_ROYALTIES.may_load(deps.storage, &nft_contract_addr)?
+    };
+    let is_rate_changed = current.map(|x| x.royalty_fee != royalty_fee).unwrap_or(true);
+    if is_rate_changed {
+        check_rate_cooldown(deps.as_ref(), &env, is_royalty_admin, &nft_contract_addr, &token_id)?;
+        save_rate_changed_at(deps.storage, &env, &nft_contract_addr, &token_id)?;
+    }
+    let royalty = Royalty {
+        royalty_fee,
+        creator: creator_addr,
+        splits: validate_royalty_splits(deps.api, royalty_fee, splits).map_err(ContractError::InvalidRoyalty)?
+    };
+    match &token_id {
+        Some(token_id) => TOKEN_ROYALTIES.save(deps.storage, (&nft_contract_addr, token_id), &royalty)?,
+        None => COLLECTION_ROYALTIES.save(deps.storage, &nft_contract_addr, &royalty)?
+    };
+    Ok(Response::new()
+        .add_attribute("action", "set_royalty_fee")
+        .add_attribute("nft_contract", contract_addr)
+        .add_attribute("token_id", token_id.unwrap_or_default())
+        .add_attribute("creator", creator)
+        .add_attribute("royalty_fee", royalty_fee.to_string()))
+}
+
+pub fn remove_royalty_fee(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    contract_addr: String,
+    token_id: Option<String>
+) -> Result<Response, ContractError> {
+    let nft_contract_addr = deps.api.addr_validate(&contract_addr)?;
+    only_royalty_setter(deps.as_ref(), &info, &nft_contract_addr)?;
+    // removing lowers the rate so it is not limited, but a new rate right after it is
+    save_rate_changed_at(deps.storage, &env, &nft_contract_addr, &token_id)?;
+    match &token_id {
+        Some(token_id) => TOKEN_ROYALTIES.remove(deps.storage, (&nft_contract_addr, token_id)),
+        None => COLLECTION_ROYALTIES.remove(deps.storage, &nft_contract_addr)
+    };
+    Ok(Response::new()
+        .add_attribute("action", "remove_royalty_fee")
+        .add_attribute("nft_contract", contract_addr)
+        .add_attribute("token_id", token_id.unwrap_or_default()))
+}
+
+// rate changes by the collection itself, royalty admins are not limited
+fn check_rate_cooldown(
+    deps: Deps,
+    env: &Env,
+    is_royalty_admin: bool,
+    nft_contract: &Addr,
+    token_id: &Option<String>
+) -> Result<(), ContractError> {
+    if is_royalty_admin {
+        return Ok(());
+    }
+    let changed_at = match token_id {
+        Some(token_id) => TOKEN_RATE_CHANGED_AT.may_load(deps.storage, (nft_contract, token_id))?,
+        None => COLLECTION_RATE_CHANGED_AT.may_load(deps.storage, nft_contract)?
+    };
+    if let Some(changed_at) = changed_at {
+        if env.block.time.seconds() < changed_at + ROYALTY_RATE_COOLDOWN {
+            return Err(ContractError::InvalidRoyalty("royalty fee was changed recently".to_string()));
+        }
+    }
+    Ok(())
+}
+
+fn save_rate_changed_at(
+    storage: &mut dyn Storage,
+    env: &Env,
+    nft_contract: &Addr,
+    token_id: &Option<String>
+) -> StdResult<()> {
+    let block_time = env.block.time.seconds();
+    match token_id {
+        Some(token_id) => TOKEN_RATE_CHANGED_AT.save(storage, (nft_contract, token_id), &block_time),
+        None => COLLECTION_RATE_CHANGED_AT.save(storage, nft_contract, &block_time)
+    }
+}
+
+pub fn set_royalty_admin(
+    deps: DepsMut,
+    info: MessageInfo,
+    address: String,
+    enable: bool
+) -> Result<Response, ContractError> {
+    only_owner(deps.as_ref(), &info)?;
+    let address_raw = deps.api.addr_validate(&address)?;
+    if enable {
+        ROYALTY_ADMINS.save(deps.storage, &address_raw, &true)?;
+    } else {
+        ROYALTY_ADMINS.remove(deps.storage, &address_raw);
+    }
+    Ok(Response::new()
+        .add_attribute("action", "set_royalty_admin")
+        .add_attribute("address", address)
+        .add_attribute("enable", enable.to_string()))
+}
+
+pub fn update_config(
+    deps: DepsMut,
+    info: MessageInfo,
+    owner: Option<String>,
+    max_royalty_fee: Option<Decimal>
+) -> Result<Response, ContractError> {
+    only_owner(deps.as_ref(), &info)?;
+    let mut config = CONFIG.load(deps.storage)?;
+    if let Some(owner) = owner {
+        config.owner = deps.api.addr_validate(&owner)?;
+    }
+    if let Some(max_royalty_fee) = max_royalty_fee {
+        if max_royalty_fee > Decimal::one() {
+            return Err(ContractError::InvalidRoyalty("max royalty fee is more than sale price".to_string()));
+        }
+        config.max_royalty_fee = max_royalty_fee;
+    }
+    CONFIG.save(deps.storage, &config)?;
+    Ok(Response::new()
+        .add_attribute("action", "update_config")
+        .add_attribute("owner", config.owner)
+        .add_attribute("max_royalty_fee", config.max_royalty_fee.to_string()))
+}
+
+pub fn only_owner(
+    deps: Deps,
+    info: &MessageInfo
+) -> Result<(), ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    Ok(())
+}
+
+// royalty admins set any royalty, the minter or contract admin of the nft contract only its own
+// true for royalty admins
+fn only_royalty_setter(
+    deps: Deps,
+    info: &MessageInfo,
+    nft_contract: &Addr
+) -> Result<bool, ContractError> {
+    if ROYALTY_ADMINS.has(deps.storage, &info.sender) {
+        return Ok(true);
+    }
+    if !query_nft_controller(&deps.querier, nft_contract, &info.sender)? {
+        return Err(ContractError::Unauthorized {});
+    }
+    Ok(false)
+}
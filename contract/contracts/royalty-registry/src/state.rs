@@ -0,0 +1,20 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use cosmwasm_std::{Addr, Decimal};
+use cw_storage_plus::{Item, Map};
+use marketplace::auction::Royalty;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Config {
+    pub owner: Addr,
+    pub max_royalty_fee: Decimal
+}
+
+pub const CONFIG: Item<Config> = Item::new("config");
+pub const ROYALTY_ADMINS: Map<&Addr, bool> = Map::new("royalty_admins");
+pub const COLLECTION_ROYALTIES: Map<&Addr, Royalty> = Map::new("collection_royalties");
+// nft contract, token id
+pub const TOKEN_ROYALTIES: Map<(&Addr, &str), Royalty> = Map::new("token_royalties");
+// last rate change by the collection itself, same keys as the royalties
+pub const COLLECTION_RATE_CHANGED_AT: Map<&Addr, u64> = Map::new("collection_rate_changed_at");
+pub const TOKEN_RATE_CHANGED_AT: Map<(&Addr, &str), u64> = Map::new("token_rate_changed_at");
@@ -0,0 +1,185 @@
+use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage};
+use cosmwasm_std::{
+    from_binary, to_binary, ContractInfoResponse, ContractResult, Decimal, DepsMut, OwnedDeps, SystemResult, WasmQuery
+};
+use marketplace::auction::{RoyaltyFeeResponse, RoyaltyResponse, RoyaltySplit, AllRoyaltyListResponse};
+use marketplace::royalty_registry::{InstantiateMsg, ExecuteMsg, QueryMsg, TokenRoyaltyListResponse};
+
+use crate::contract::{instantiate, execute, query};
+use crate::error::ContractError;
+use marketplace::royalty::{MinterQueryMsg, MinterResponse, ROYALTY_RATE_COOLDOWN};
+use crate::querier::{Cw2981QueryMsg, Cw2981ExtensionMsg, RoyaltiesInfoResponse};
+
+fn setup_contract(deps: DepsMut) {
+    let msg = InstantiateMsg {
+        max_royalty_fee: Decimal::percent(20)
+    };
+    instantiate(deps, mock_env(), mock_info("owner", &[]), msg).unwrap();
+}
+
+// nft contract "nft" with minter "minter" and cw2981 royalty of 5% to "artist", contract "plain" has neither
+fn mock_nft_querier(querier: &mut MockQuerier) {
+    querier.update_wasm(|query| match query {
+        WasmQuery::Smart { contract_addr, msg } if contract_addr == "nft" => {
+            if let Ok(MinterQueryMsg::Minter {}) = from_binary(msg) {
+                return SystemResult::Ok(ContractResult::Ok(to_binary(&MinterResponse {
+                    minter: "minter".to_string()
+                }).unwrap()));
+            }
+            match from_binary(msg).unwrap() {
+                Cw2981QueryMsg::Extension { msg: Cw2981ExtensionMsg::RoyaltyInfo { sale_price, .. } } => {
+                    SystemResult::Ok(ContractResult::Ok(to_binary(&RoyaltiesInfoResponse {
+                        address: "artist".to_string(),
+                        royalty_amount: sale_price * Decimal::percent(5)
+                    }).unwrap()))
+                }
+            }
+        },
+        WasmQuery::Smart { .. } => SystemResult::Ok(ContractResult::Err("unknown query".to_string())),
+        WasmQuery::ContractInfo { .. } => {
+            SystemResult::Ok(ContractResult::Ok(to_binary(&ContractInfoResponse::new(1, "deployer")).unwrap()))
+        },
+        _ => panic!("DO NOT ENTER HERE")
+    });
+}
+
+fn query_royalty(deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>, contract_addr: &str, token_id: Option<&str>) -> Option<RoyaltyResponse> {
+    let res: RoyaltyFeeResponse = from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::RoyaltyFee {
+        contract_addr: contract_addr.to_string(),
+        token_id: token_id.map(|x| x.to_string())
+    }).unwrap()).unwrap();
+    res.royalty_fee
+}
+
+#[test]
+fn resolve_royalty() {
+    let mut deps = mock_dependencies();
+    mock_nft_querier(&mut deps.querier);
+    setup_contract(deps.as_mut());
+
+    // cw2981 royalty info is used when nothing is registered
+    assert_eq!(query_royalty(&deps, "nft", Some("1")), Some(RoyaltyResponse {
+        royalty_fee: Decimal::percent(5),
+        creator: "artist".to_string(),
+        splits: vec![]
+    }));
+    assert_eq!(query_royalty(&deps, "nft", None), None);
+    assert_eq!(query_royalty(&deps, "plain", Some("1")), None);
+
+    // only royalty admins or the nft contract controller set royalties
+    let msg = ExecuteMsg::SetRoyaltyFee {
+        contract_addr: "nft".to_string(),
+        token_id: None,
+        creator: "creator".to_string(),
+        royalty_fee: Decimal::percent(10),
+        splits: None
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info("anyone", &[]), msg.clone()).unwrap_err();
+    match err {
+        ContractError::Unauthorized { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    execute(deps.as_mut(), mock_env(), mock_info("minter", &[]), msg).unwrap();
+
+    let too_high = ExecuteMsg::SetRoyaltyFee {
+        contract_addr: "nft".to_string(),
+        token_id: Some("1".to_string()),
+        creator: "creator".to_string(),
+        royalty_fee: Decimal::percent(21),
+        splits: None
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info("minter", &[]), too_high).unwrap_err();
+    match err {
+        ContractError::InvalidRoyalty { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+
+    // collection royalty overrides cw2981, a token royalty overrides the collection
+    assert_eq!(query_royalty(&deps, "nft", Some("1")).unwrap().royalty_fee, Decimal::percent(10));
+    execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), ExecuteMsg::SetRoyaltyAdmin {
+        address: "admin".to_string(),
+        enable: true
+    }).unwrap();
+    execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), ExecuteMsg::SetRoyaltyFee {
+        contract_addr: "nft".to_string(),
+        token_id: Some("2".to_string()),
+        creator: "creator".to_string(),
+        royalty_fee: Decimal::percent(8),
+        splits: Some(vec![
+            RoyaltySplit { recipient: "creator".to_string(), share: Decimal::percent(6) },
+            RoyaltySplit { recipient: "partner".to_string(), share: Decimal::percent(2) }
+        ])
+    }).unwrap();
+    let royalty = query_royalty(&deps, "nft", Some("2")).unwrap();
+    assert_eq!(royalty.royalty_fee, Decimal::percent(8));
+    assert_eq!(royalty.splits.len(), 2);
+    assert_eq!(query_royalty(&deps, "nft", Some("1")).unwrap().royalty_fee, Decimal::percent(10));
+
+    let res: AllRoyaltyListResponse = from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::AllRoyaltyFee {
+        start_after: None,
+        limit: None
+    }).unwrap()).unwrap();
+    assert_eq!(res.royalty_fees.len(), 1);
+    assert_eq!(res.royalty_fees[0].contract_addr, "nft".to_string());
+    let res: TokenRoyaltyListResponse = from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::TokenRoyaltyFees {
+        contract_addr: "nft".to_string(),
+        start_after: None,
+        limit: None
+    }).unwrap()).unwrap();
+    assert_eq!(res.royalty_fees.len(), 1);
+    assert_eq!(res.royalty_fees[0].token_id, "2".to_string());
+
+    // removing the collection royalty falls back to cw2981 again
+    execute(deps.as_mut(), mock_env(), mock_info("minter", &[]), ExecuteMsg::RemoveRoyaltyFee {
+        contract_addr: "nft".to_string(),
+        token_id: None
+    }).unwrap();
+    assert_eq!(query_royalty(&deps, "nft", Some("1")).unwrap().royalty_fee, Decimal::percent(5));
+    assert_eq!(query_royalty(&deps, "nft", Some("2")).unwrap().royalty_fee, Decimal::percent(8));
+}
+
+#[test]
+fn rate_cooldown() {
+    let mut deps = mock_dependencies();
+    mock_nft_querier(&mut deps.querier);
+    setup_contract(deps.as_mut());
+    let set_royalty = |royalty_fee: u64, creator: &str| ExecuteMsg::SetRoyaltyFee {
+        contract_addr: "nft".to_string(),
+        token_id: None,
+        creator: creator.to_string(),
+        royalty_fee: Decimal::percent(royalty_fee),
+        splits: None
+    };
+    let mut env = mock_env();
+    execute(deps.as_mut(), env.clone(), mock_info("minter", &[]), set_royalty(10, "creator")).unwrap();
+
+    // the rate of a collection can't change again within the cooldown, other terms can
+    let err = execute(deps.as_mut(), env.clone(), mock_info("minter", &[]), set_royalty(12, "creator")).unwrap_err();
+    match err {
+        ContractError::InvalidRoyalty { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+    execute(deps.as_mut(), env.clone(), mock_info("minter", &[]), set_royalty(10, "studio")).unwrap();
+
+    // removing and setting again is limited too
+    execute(deps.as_mut(), env.clone(), mock_info("minter", &[]), ExecuteMsg::RemoveRoyaltyFee {
+        contract_addr: "nft".to_string(),
+        token_id: None
+    }).unwrap();
+    let err = execute(deps.as_mut(), env.clone(), mock_info("minter", &[]), set_royalty(12, "creator")).unwrap_err();
+    match err {
+        ContractError::InvalidRoyalty { .. } => {}
+        e => panic!("unexpected error: {}", e)
+    }
+
+    // royalty admins are not limited
+    execute(deps.as_mut(), env.clone(), mock_info("owner", &[]), ExecuteMsg::SetRoyaltyAdmin {
+        address: "admin".to_string(),
+        enable: true
+    }).unwrap();
+    execute(deps.as_mut(), env.clone(), mock_info("admin", &[]), set_royalty(12, "creator")).unwrap();
+
+    env.block.time = env.block.time.plus_seconds(ROYALTY_RATE_COOLDOWN);
+    execute(deps.as_mut(), env, mock_info("minter", &[]), set_royalty(8, "creator")).unwrap();
+    assert_eq!(query_royalty(&deps, "nft", None).unwrap().royalty_fee, Decimal::percent(8));
+}
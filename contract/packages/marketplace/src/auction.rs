@@ -1,4 +1,4 @@
-use cosmwasm_std::{Uint128, Addr, Decimal};
+use cosmwasm_std::{Uint128, Addr, Decimal, Binary, Coin};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use cw721::{Cw721ReceiveMsg};
@@ -13,7 +13,8 @@ pub struct InstantiateMsg {
     pub duration: u64,
     pub extension_duration: u64,
     pub accepted_denom: Vec<String>,
-    pub collector_address: String
+    pub collector_address: String,
+    pub config_timelock: u64
 }
 
 /// This is like Cw721HandleMsg but we add a Mint command for an owner
@@ -23,38 +24,146 @@ pub struct InstantiateMsg {
 #[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
     ReceiveNft(Cw721ReceiveMsg),
+    // the nft stays with the seller, this contract must be approved to transfer it
+    CreateApprovalListing {
+        nft_contract: String,
+        token_id: String,
+        denom: String,
+        reserve_price: Uint128,
+        is_instant_sale: bool,
+        referrer: Option<String>
+    },
+    // close an expired listing, or an approval listing whose seller no longer owns or approved the nft, the bidder is refunded
+    InvalidateListing {
+        auction_id: Uint128
+    },
+    // close an expired listing, an escrowed nft goes back to the seller
+    ReclaimExpired {
+        auction_id: Uint128
+    },
     CancelAuction {
         auction_id: Uint128
     },
+    // nft goes to recipient and refunds go to refund_to, both default to the sender
+    // with send_msg the nft is sent to the recipient contract with SendNft, a rejected hook falls back to TransferNft
+    // referrer is the frontend the bid came from, it shares the protocol fee once approved
     PlaceBid {
-        auction_id: Uint128
+        auction_id: Uint128,
+        recipient: Option<String>,
+        refund_to: Option<String>,
+        send_msg: Option<Binary>,
+        referrer: Option<String>
     },
     Settle {
         auction_id: Uint128
     },
-    // admin
-    AdminChangeConfig {
-        protocol_fee: Decimal,
-        min_increment: Decimal,
-        min_reserve_price: Uint128,
-        max_royalty_fee: Decimal,
-        duration: u64,
-        extension_duration: u64,
-        accepted_denom: Vec<String>,
-        collector_address: String
+    // auctions that can't be settled are skipped, the rest are settled in their own sub messages
+    // and a failed settlement is recorded instead of reverting the batch
+    BatchSettle {
+        auction_ids: Vec<Uint128>
+    },
+    // approval listings, invalid items are skipped with a list_skipped event
+    BatchList {
+        items: Vec<BatchListItem>
+    },
+    // invalid ids are skipped with a cancel_skipped event
+    BatchCancel {
+        auction_ids: Vec<Uint128>
+    },
+    // only listings without a bid, buy_now_price reprices buy now listings and reserve_price auctions
+    // expires_at 0 clears the expiry
+    UpdateListing {
+        auction_id: Uint128,
+        reserve_price: Option<Uint128>,
+        denom: Option<String>,
+        buy_now_price: Option<Uint128>,
+        expires_at: Option<u64>
+    },
+    // only listings without a bid, other items are skipped
+    BatchUpdatePrice {
+        items: Vec<PriceUpdate>
+    },
+    // buy now listings paid with the attached coin, unspent funds are refunded
+    // with allow_partial, listings that can't be bought are skipped instead of failing the sweep
+    Sweep {
+        target: SweepTarget,
+        max_total_price: Uint128,
+        allow_partial: bool,
+        referrer: Option<String>
+    },
+    // signed orders, signatures are checked against the pubkey registered by the signer
+    RegisterPubkey {
+        pubkey: Binary
+    },
+    FulfillOrder {
+        order: SignedOrder,
+        signature: Binary
+    },
+    CancelOrder {
+        order: SignedOrder
+    },
+    // cancels every order signed with the current nonce
+    IncrementNonce {},
+    // bid orders are paid from deposited funds
+    DepositOrderFunds {},
+    WithdrawOrderFunds {
+        denom: String,
+        amount: Uint128
     },
+    // referrers are paid only after the admin approves them into a tier
+    RegisterReferrer {},
+    // splits accrued protocol fees by the fee distribution
+    DistributeFees {},
+    // admin
+    // config changes are timelocked, they can only be executed after config_timelock has passed
+    ProposeConfigChange(ConfigChange),
+    ExecuteConfigChange {},
+    CancelConfigChange {},
     AdminCancelAuction {
         auction_id: Uint128
     },
+    // royalty admins, or the minter or contract admin of the nft contract with a rate change cooldown, both within max royalty fee
+    // shares of the splits must sum to royalty fee, none pays all of it to the creator
     SetRoyaltyFee{
         contract_addr: String,
         creator: String,
         royalty_fee: Decimal,
+        splits: Option<Vec<RoyaltySplit>>
     },
     SetRoyaltyAdmin {
         address: String,
         enable: bool
     },
+    SetOracleConfig {
+        max_observations: u64,
+        outlier_threshold: Option<Decimal>
+    },
+    // none disables the reward, it is paid for settlement only
+    SetKeeperReward {
+        reward: Option<KeeperReward>,
+        min_delay: u64
+    },
+    // limits every batch message
+    SetMaxBatchSize {
+        max_batch_size: u64
+    },
+    // royalties of new listings are resolved by the registry, none uses the royalties of this contract
+    SetRoyaltyRegistry {
+        address: Option<String>
+    },
+    // fee share is the part of the protocol fee paid to referrers of the tier, none removes the tier
+    SetReferralTier {
+        tier: u8,
+        fee_share: Option<Decimal>
+    },
+    ApproveReferrer {
+        referrer: String,
+        tier: u8
+    },
+    // earnings and volume of the referrer are kept
+    RevokeReferrer {
+        referrer: String
+    },
     // stop create new auction
     AdminPause {},
     AdminResume {},
@@ -62,7 +171,14 @@ pub enum ExecuteMsg {
         nft_contract: String,
         token_id: String,
         owner: String
-    }
+    },
+    // sent by this contract to itself for each item of a batch settlement
+    SettleBatchItem {
+        auction_id: Uint128,
+        keeper: Option<String>
+    },
+    // anyone, rebuilds the next batch of state left by migrate, other messages are rejected until it is done
+    ContinueMigration {}
 }
 
 /// This is like Cw721HandleMsg but we add a Mint command for an owner
@@ -95,8 +211,22 @@ pub enum QueryMsg {
         nft_contract: String,
         token_id: String
     },
+    // start_after is the bid number of the last bid of the previous page
     BidHistoryByAuctionId {
         auction_id: Uint128,
+        start_after: Option<Uint128>,
+        limit: Option<u32>,
+        is_desc: Option<bool>
+    },
+    // ended but not settled auctions of every collection, oldest end time first
+    SettleableAuctions {
+        start_after: Option<Uint128>,
+        limit: Option<u32>
+    },
+    // start_after is (auction id, bid number) of the last bid of the previous page
+    BidsByBidder {
+        bidder: String,
+        start_after: Option<(Uint128, Uint128)>,
         limit: Option<u32>
     },
     BidsCount {
@@ -131,20 +261,332 @@ pub enum QueryMsg {
         bidder: String,
         start_after: Option<u128>,
         limit: Option<u32>,
+    },
+    PendingConfigChange {},
+    // only one of nft_contract, seller and buyer can be set
+    SalesHistory {
+        nft_contract: Option<String>,
+        seller: Option<String>,
+        buyer: Option<String>,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+        is_desc: Option<bool>
+    },
+    TokenProvenance {
+        nft_contract: String,
+        token_id: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+        is_desc: Option<bool>
+    },
+    CollectionStats {
+        nft_contract: String,
+        denom: String
+    },
+    // ordered by all time volume, highest first
+    TopCollectionsByVolume {
+        denom: String,
+        start_after: Option<String>,
+        limit: Option<u32>
+    },
+    OracleConfig {},
+    KeeperReward {},
+    MaxBatchSize {},
+    RoyaltyRegistry {},
+    // sale prices of the last window seconds
+    PriceObservation {
+        nft_contract: String,
+        denom: String,
+        window: u64
+    },
+    // cursor is returned by the previous page
+    // the index covers the collection, status and the sorted field, the other filters are checked per entry and
+    // a page stops after a fixed number of entries, so it can be short or empty while next_cursor is still set
+    SearchAuctions {
+        filter: AuctionFilter,
+        sort_by: Option<AuctionSortBy>,
+        is_desc: Option<bool>,
+        cursor: Option<Binary>,
+        limit: Option<u32>
+    },
+    // newest first, start_after is next_start_after of the previous page
+    Activity {
+        address: String,
+        kinds: Option<Vec<ActivityKind>>,
+        start_after: Option<u64>,
+        limit: Option<u32>
+    },
+    OrderAccount {
+        address: String
+    },
+    // digest to sign for an order
+    OrderDigest {
+        order: SignedOrder
+    },
+    FeeDistribution {},
+    // protocol fees waiting for DistributeFees
+    AccruedFees {},
+    ReferralTiers {},
+    // volume and earnings per denom
+    Referrer {
+        address: String
+    },
+    Referrers {
+        start_after: Option<String>,
+        limit: Option<u32>,
+        is_approved: Option<bool>
+    },
+    // auctions whose last settlement or close by a batch or tick failed
+    FailedSettlements {
+        start_after: Option<Uint128>,
+        limit: Option<u32>
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SweepTarget {
+    // bought in the given order
+    AuctionIds {
+        auction_ids: Vec<Uint128>
+    },
+    // cheapest buyable listings of the collection in the denom of the attached coin, among a limited number of the cheapest
+    Cheapest {
+        nft_contract: String,
+        max_count: u32
     }
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BatchListItem {
+    pub nft_contract: String,
+    pub token_id: String,
+    pub denom: String,
+    pub reserve_price: Uint128,
+    pub is_instant_sale: bool,
+    pub referrer: Option<String>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PriceUpdate {
+    pub auction_id: Uint128,
+    pub reserve_price: Uint128
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MaxBatchSizeResponse {
+    pub max_batch_size: u64
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RoyaltyRegistryResponse {
+    pub address: Option<String>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ReferralTier {
+    pub tier: u8,
+    pub fee_share: Decimal
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ReferralTiersResponse {
+    pub tiers: Vec<ReferralTier>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ReferralStatsResponse {
+    pub denom: String,
+    // price of the sales the referrer was paid for
+    pub volume: Uint128,
+    pub earnings: Uint128,
+    pub num_sales: u64
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ReferrerResponse {
+    pub address: String,
+    pub tier: u8,
+    pub is_approved: bool,
+    // zero while not approved or the tier is removed
+    pub fee_share: Decimal,
+    pub stats: Vec<ReferralStatsResponse>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ReferrersResponse {
+    pub referrers: Vec<ReferrerResponse>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderSide {
+    // signed by the seller, filled by a buyer paying the price
+    Ask,
+    // signed by a bidder with deposited funds, filled by the owner of the nft
+    Bid
+}
+
+// signature covers the sha256 digest of the order serialized as json
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SignedOrder {
+    pub side: OrderSide,
+    pub signer: String,
+    pub nft_contract: String,
+    pub token_id: String,
+    pub denom: String,
+    pub price: Uint128,
+    // seconds
+    pub expiry: u64,
+    pub nonce: u64,
+    pub chain_id: String,
+    pub contract_address: String
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OrderAccountResponse {
+    pub pubkey: Option<Binary>,
+    pub nonce: u64,
+    pub deposits: Vec<Coin>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OrderDigestResponse {
+    pub digest: Binary,
+    // filled or cancelled
+    pub is_closed: bool
+}
+
+// without status, only auctions that are not settled are returned
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct AuctionFilter {
+    pub nft_contract: Option<String>,
+    pub auction_type: Option<AuctionType>,
+    pub denom: Option<String>,
+    pub status: Option<AuctionStatus>,
+    pub min_price: Option<Uint128>,
+    pub max_price: Option<Uint128>,
+    pub ending_before: Option<u64>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuctionStatus {
+    NotStarted,
+    Live,
+    Ended,
+    Settled
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuctionSortBy {
+    Id,
+    Price,
+    EndTime
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SearchAuctionsResponse {
+    pub auctions: Vec<AuctionResponse>,
+    pub next_cursor: Option<Binary>
+}
+
+// only fields that are set will be changed
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct ConfigChange {
+    pub protocol_fee: Option<Decimal>,
+    pub min_increment: Option<Decimal>,
+    pub min_reserve_price: Option<Uint128>,
+    pub max_royalty_fee: Option<Decimal>,
+    pub duration: Option<u64>,
+    pub extension_duration: Option<u64>,
+    pub accepted_denom: Option<Vec<String>>,
+    pub collector_address: Option<String>,
+    pub config_timelock: Option<u64>,
+    // protocol fees go to the collector address until a distribution is set
+    pub fee_distribution: Option<FeeDistribution>,
+    // protocol fees go back to the collector address, accrued fees are distributed first
+    #[serde(default)]
+    pub remove_fee_distribution: bool,
+    // primary sales pay this protocol fee and no royalty, every sale is secondary until it is set
+    pub primary_protocol_fee: Option<Decimal>,
+    // every sale is secondary again
+    #[serde(default)]
+    pub remove_primary_protocol_fee: bool
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FeeRecipient {
+    pub address: String,
+    pub weight: u64
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FeeDistribution {
+    pub recipients: Vec<FeeRecipient>,
+    // weight of the fee burned with bank burn
+    pub burn_weight: u64,
+    // fees are split at settlement, otherwise they accrue until DistributeFees
+    pub distribute_on_settle: bool
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FeeDistributionResponse {
+    pub distribution: Option<FeeDistribution>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AccruedFeesResponse {
+    pub fees: Vec<Coin>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingConfigChange {
+    pub change: ConfigChange,
+    pub eta: u64
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingConfigChangeResponse {
+    pub pending: Option<PendingConfigChange>
+}
+
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Royalty {
     pub royalty_fee: Decimal,
-    pub creator: Addr
+    // receives the rounding dust of the splits
+    pub creator: Addr,
+    // empty for royalties set before splits, the creator gets the whole royalty then
+    #[serde(default)]
+    pub splits: Vec<RoyaltyShare>
+}
+
+// share is a part of the sale price
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RoyaltyShare {
+    pub recipient: Addr,
+    pub share: Decimal
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RoyaltySplit {
+    pub recipient: String,
+    pub share: Decimal
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RoyaltyPayout {
+    pub recipient: String,
+    pub amount: Uint128
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct RoyaltyResponse {
     pub royalty_fee: Decimal,
-    pub creator: String
+    pub creator: String,
+    pub splits: Vec<RoyaltySplit>
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -162,7 +604,8 @@ pub struct RoyaltyFeeResponse {
 pub struct AllRoyaltyResponse {
     pub contract_addr: String,
     pub royalty_fee: Decimal,
-    pub creator: String
+    pub creator: String,
+    pub splits: Vec<RoyaltySplit>
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -185,7 +628,9 @@ pub struct ConfigResponse {
     pub extension_duration: u64,
     pub accepted_denom: Vec<String>,
     pub collector_address: String,
-    pub max_royalty_fee: Decimal
+    pub max_royalty_fee: Decimal,
+    pub config_timelock: u64,
+    pub primary_protocol_fee: Option<Decimal>
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -221,7 +666,14 @@ pub struct AuctionResponse {
     pub amount: Uint128,
     pub creator_address: Option<String>,
     pub royalty_fee: Decimal,
-    pub is_settled: bool
+    pub is_settled: bool,
+    pub is_approval_listing: bool,
+    pub expires_at: Option<u64>,
+    pub recipient: Option<String>,
+    pub refund_to: Option<String>,
+    pub send_msg: Option<Binary>,
+    pub listing_referrer: Option<String>,
+    pub bid_referrer: Option<String>
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -231,7 +683,63 @@ pub struct CalculatePriceResponse {
     pub amount: Uint128,
     pub protocol_fee: Uint128,
     pub royalty_fee: Uint128,
-    pub seller_amount: Uint128
+    pub royalty_payouts: Vec<RoyaltyPayout>,
+    pub seller_amount: Uint128,
+    // paid out of protocol fee to whoever settles now, zero until the keeper delay after the end time has passed
+    pub keeper_reward: Uint128,
+    // the open listing of the token is a primary sale
+    pub is_primary_sale: bool
+}
+
+// reward paid to the caller of settle, never more than the protocol fee
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum KeeperReward {
+    ProtocolFeeShare { share: Decimal },
+    Flat { amounts: Vec<Coin> }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FailedSettlementResponse {
+    pub auction_id: Uint128,
+    pub reason: String,
+    pub time: u64,
+    pub attempts: u32,
+    // tick tries again from this time on
+    pub retry_time: u64
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FailedSettlementsResponse {
+    pub settlements: Vec<FailedSettlementResponse>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct KeeperRewardResponse {
+    pub reward: Option<KeeperReward>,
+    // seconds after end time before the reward is paid
+    pub min_delay: u64
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BidStatus {
+    Leading,
+    Outbid,
+    Won,
+    Refunded
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BidStatusResponse {
+    pub bid_number: Uint128,
+    pub bid: Bid,
+    pub status: BidStatus
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BidsByBidderResponse {
+    pub bids: Vec<BidStatusResponse>
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -240,7 +748,115 @@ pub struct Bid {
     pub bidder: Addr,
     pub time: u64,
     pub denom: String,
-    pub amount: Uint128
+    pub amount: Uint128,
+    #[serde(default)]
+    pub recipient: Option<Addr>,
+    #[serde(default)]
+    pub refund_to: Option<Addr>,
+    #[serde(default)]
+    pub send_msg: Option<Binary>,
+    #[serde(default)]
+    pub referrer: Option<Addr>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Sale {
+    pub sale_id: u64,
+    pub auction_id: Uint128,
+    pub nft_contract: Addr,
+    pub token_id: String,
+    pub seller: Addr,
+    pub buyer: Addr,
+    pub denom: String,
+    pub price: Uint128,
+    pub protocol_fee: Uint128,
+    pub royalty_fee: Uint128,
+    pub time: u64,
+    // sold by its creator or minter, or sold here for the first time
+    #[serde(default)]
+    pub is_primary_sale: bool
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ActivityKind {
+    ListingCreated,
+    BidPlaced,
+    Outbid,
+    Won,
+    Sold,
+    Cancelled,
+    RoyaltyReceived,
+    PriceChanged,
+    // a signed bid order of the address was filled by the nft owner
+    OfferAccepted
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Activity {
+    pub seq: u64,
+    pub kind: ActivityKind,
+    pub auction_id: Uint128,
+    pub nft_contract: Addr,
+    pub token_id: String,
+    pub denom: String,
+    pub amount: Uint128,
+    pub counterparty: Option<Addr>,
+    pub time: u64
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ActivityResponse {
+    pub activities: Vec<Activity>,
+    // set when the page stopped before the oldest activity, it can be short or empty when kinds are filtered
+    pub next_start_after: Option<u64>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SalesResponse {
+    pub sales: Vec<Sale>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CollectionStatsResponse {
+    pub nft_contract: String,
+    pub denom: String,
+    pub floor_price: Option<Uint128>,
+    pub volume_24h: Uint128,
+    pub volume_7d: Uint128,
+    pub total_volume: Uint128,
+    pub num_sales: u64,
+    pub last_sale: Option<Sale>,
+    pub active_listings: u64
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CollectionVolumeResponse {
+    pub nft_contract: String,
+    pub denom: String,
+    pub total_volume: Uint128,
+    pub num_sales: u64
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TopCollectionsResponse {
+    pub collections: Vec<CollectionVolumeResponse>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OracleConfigResponse {
+    pub max_observations: u64,
+    pub outlier_threshold: Option<Decimal>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PriceObservationResponse {
+    pub nft_contract: String,
+    pub denom: String,
+    pub window: u64,
+    pub twap: Uint128,
+    pub median: Uint128,
+    pub sample_count: u64
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -265,9 +881,21 @@ pub enum Cw721HookMsg {
     CreateAuction {
         denom: String,
         reserve_price: Uint128,
-        is_instant_sale: bool // default is false
+        is_instant_sale: bool, // default is false
+        referrer: Option<String>
     }
 }
 
+// called by the chain (clock or cron module), not by accounts
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SudoMsg {
+    // settle ended auctions and close expired or invalid listings in end time order, continuing from the last tick
+    Tick {}
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
-pub struct MigrateMsg {}
\ No newline at end of file
+pub struct MigrateMsg {
+    // used to backfill config_timelock when migrating from v2.0.0
+    pub config_timelock: Option<u64>
+}
\ No newline at end of file
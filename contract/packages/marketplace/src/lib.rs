@@ -1 +1,3 @@
-pub mod auction;
\ No newline at end of file
+pub mod auction;
+pub mod royalty;
+pub mod royalty_registry;
@@ -0,0 +1,82 @@
+use cosmwasm_std::{to_binary, Addr, Api, ContractInfoResponse, Decimal, QuerierWrapper, QueryRequest, StdResult, WasmQuery};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::auction::{RoyaltyShare, RoyaltySplit};
+
+pub const MAX_ROYALTY_SPLITS: usize = 10;
+// rate changes by the collection itself, royalty admins are not limited
+pub const ROYALTY_RATE_COOLDOWN: u64 = 7 * 86400;
+
+// minter query of cw721-base, it is not part of the cw721 spec
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MinterQueryMsg {
+    Minter {}
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MinterResponse {
+    pub minter: String
+}
+
+// none for nft contracts without minter query
+pub fn query_nft_minter(
+    querier: &QuerierWrapper,
+    nft_contract: &Addr
+) -> StdResult<Option<String>> {
+    let minter: StdResult<MinterResponse> = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: nft_contract.to_string(),
+        msg: to_binary(&MinterQueryMsg::Minter {})?
+    }));
+    Ok(minter.ok().map(|x| x.minter))
+}
+
+// sender is the minter or the contract admin of the nft contract
+pub fn query_nft_controller(
+    querier: &QuerierWrapper,
+    nft_contract: &Addr,
+    sender: &Addr
+) -> StdResult<bool> {
+    // nft contracts without minter query are checked by admin only
+    if query_nft_minter(querier, nft_contract)?.as_deref() == Some(sender.as_str()) {
+        return Ok(true);
+    }
+    let contract_info: ContractInfoResponse = querier.query(&QueryRequest::Wasm(WasmQuery::ContractInfo {
+        contract_addr: nft_contract.to_string()
+    }))?;
+    Ok(contract_info.admin.as_deref() == Some(sender.as_str()))
+}
+
+// none splits pay the whole royalty to the creator, the error is the reason the splits are invalid
+pub fn validate_royalty_splits(
+    api: &dyn Api,
+    royalty_fee: Decimal,
+    splits: Option<Vec<RoyaltySplit>>
+) -> Result<Vec<RoyaltyShare>, String> {
+    let splits = match splits {
+        Some(v) => v,
+        None => return Ok(vec![])
+    };
+    if splits.is_empty() || splits.len() > MAX_ROYALTY_SPLITS {
+        return Err("invalid number of royalty splits".to_string());
+    }
+    let mut shares: Vec<RoyaltyShare> = vec![];
+    for split in splits {
+        let recipient = api.addr_validate(&split.recipient).map_err(|e| e.to_string())?;
+        if split.share.is_zero() {
+            return Err("royalty share is zero".to_string());
+        }
+        if shares.iter().any(|x| x.recipient == recipient) {
+            return Err("duplicated royalty recipient".to_string());
+        }
+        shares.push(RoyaltyShare {
+            recipient,
+            share: split.share
+        });
+    }
+    let total = shares.iter().fold(Decimal::zero(), |acc, x| acc + x.share);
+    if total != royalty_fee {
+        return Err("royalty shares don't sum to royalty fee".to_string());
+    }
+    Ok(shares)
+}
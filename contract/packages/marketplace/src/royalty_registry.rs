@@ -0,0 +1,81 @@
+use cosmwasm_std::Decimal;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::auction::RoyaltySplit;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InstantiateMsg {
+    pub max_royalty_fee: Decimal
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecuteMsg {
+    // royalty admins, or the minter or contract admin of the nft contract within max royalty fee
+    // none token id sets the royalty of the whole collection, a token royalty overrides it
+    SetRoyaltyFee {
+        contract_addr: String,
+        token_id: Option<String>,
+        creator: String,
+        royalty_fee: Decimal,
+        splits: Option<Vec<RoyaltySplit>>
+    },
+    RemoveRoyaltyFee {
+        contract_addr: String,
+        token_id: Option<String>
+    },
+    SetRoyaltyAdmin {
+        address: String,
+        enable: bool
+    },
+    UpdateConfig {
+        owner: Option<String>,
+        max_royalty_fee: Option<Decimal>
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryMsg {
+    Config {},
+    // resolved royalty, token royalty first then collection royalty then cw2981 royalty info of the token
+    RoyaltyFee {
+        contract_addr: String,
+        token_id: Option<String>
+    },
+    // collection royalties
+    AllRoyaltyFee {
+        start_after: Option<String>,
+        limit: Option<u32>
+    },
+    TokenRoyaltyFees {
+        contract_addr: String,
+        start_after: Option<String>,
+        limit: Option<u32>
+    },
+    RoyaltyAdmin {
+        address: String
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrateMsg {}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ConfigResponse {
+    pub owner: String,
+    pub max_royalty_fee: Decimal
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TokenRoyaltyResponse {
+    pub token_id: String,
+    pub royalty_fee: Decimal,
+    pub creator: String,
+    pub splits: Vec<RoyaltySplit>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TokenRoyaltyListResponse {
+    pub royalty_fees: Vec<TokenRoyaltyResponse>
+}
@@ -17,7 +17,8 @@ const wallet = create_wallet(MNEMONIC);
           accepted_denom: ['uluna','ibc/B3504E092456BA618CC28AC671A71FB08C6CA0FD0BE7C8A5B5A3E2DD933CC9E4','ibc/CBF67A2BCF6CAE343FDF251E510C8E18C361FC02B23430C121116E0811835DEF'],
           min_reserve_price: '1000', // Minimum Price is 1 Luna
           max_royalty_fee: '0.2', // Maximum Royalty Fee is 20%
-          collector_address: 'terra1endu7640tu3jf72qxsyd82fxapsyulv8zxqluk' // Royalty Receiving Fee 
+          collector_address: 'terra1endu7640tu3jf72qxsyd82fxapsyulv8zxqluk', // Royalty Receiving Fee 
+          config_timelock: 172800 // Config changes can be executed 2 days after being proposed (denominated in seconds)
         },'kw-marketplace')
       const auction_addr = result.contract_addr
       console.log('auction address is ',auction_addr)
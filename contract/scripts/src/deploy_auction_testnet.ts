@@ -25,6 +25,7 @@ const wallet = create_wallet(MNEMONIC);
       min_reserve_price: "1000",
       max_royalty_fee: "0.2",
     collector_address: wallet.key.accAddress,
+      config_timelock: 300,
     }, 'kw-os');
     const auction_addr = response.contract_addr;
     console.log(auction_addr);